@@ -0,0 +1,750 @@
+//! Compact binary encoding of a DynamicValue, read back with the schema it conforms to. Smaller
+//! than bincode's layout, and independent of any other crate's format.
+//!
+//! Layout:
+//! * `u8` and `i8` are a single byte
+//! * Wider integers are LEB128 varints, with signed integers zigzag encoded first, so that small
+//!   negative numbers stay small
+//! * Floats are little-endian
+//! * Bools are packed eight to a byte, starting at the least significant bit. A new byte is
+//!   started where the next bool is written once the previous one is full, so the bits of a
+//!   byte may be interleaved with other data
+//! * A char is a varint of its code point
+//! * Strings and bytes are a varint length followed by their contents
+//! * An Option is a bool, followed by the value when it is present
+//! * Sequences and maps are a varint length followed by their elements, or keys and values
+//! * An enum is a varint variant index followed by the data of the variant
+//! * Structs and tuples are their fields in order, and unit types take no space at all
+//!
+//! A value may hold at most `MAX_EMPTY_ELEMENTS` elements of sequences and maps which take no
+//! space, e.g. those of a `Vec<()>`, counted across all of its sequences and maps. The length of
+//! such a sequence is all there is to it, so without a limit corrupt lengths could make decoding
+//! run out of memory.
+
+use crate::deserialize::RECURSION_LIMIT;
+use crate::error::KobbleError;
+use crate::path::{PathSegment, ValuePath};
+use crate::scope::Scopes;
+use crate::{
+    DynamicValue, EnumSchema, Schema, StructSchema, TupleSchema, VariantSchema, VariantValue,
+};
+
+/// Maximum number of elements which take no space in a value, across all of its sequences and
+/// maps
+pub const MAX_EMPTY_ELEMENTS: usize = 1 << 16;
+
+/// Encode `value`, which must conform to `schema`, with references to recursive types nested at
+/// most `RECURSION_LIMIT` deep so that `decode` can read it
+pub fn encode(value: &DynamicValue, schema: &Schema) -> Result<Vec<u8>, KobbleError> {
+    encode_with_recursion_limit(value, schema, Some(RECURSION_LIMIT))
+}
+
+/// As `encode`, allowing references to nest `limit` deep, or however deep they go with `None`
+pub fn encode_with_recursion_limit(
+    value: &DynamicValue,
+    schema: &Schema,
+    limit: Option<usize>,
+) -> Result<Vec<u8>, KobbleError> {
+    schema
+        .validate_with_recursion_limit(value, limit)
+        .map_err(|errors| errors.into_iter().next().unwrap())?;
+
+    let mut writer = Writer::default();
+    writer.value(value)?;
+    Ok(writer.bytes)
+}
+
+/// Decode a value encoded with `encode`, using the same schema
+pub fn decode(bytes: &[u8], schema: &Schema) -> Result<DynamicValue, KobbleError> {
+    decode_with_recursion_limit(bytes, schema, Some(RECURSION_LIMIT))
+}
+
+/// As `decode`, allowing references to nest `limit` deep, or however deep they go with `None`.
+/// Each reference takes more stack, so the thread decoding must have enough for the data it reads.
+pub fn decode_with_recursion_limit(
+    bytes: &[u8],
+    schema: &Schema,
+    limit: Option<usize>,
+) -> Result<DynamicValue, KobbleError> {
+    let mut reader = Reader {
+        bytes,
+        pos: 0,
+        bits: None,
+        scopes: Scopes::default(),
+        depth: 0,
+        recursion_limit: limit,
+        empty_elements: EmptyElements::default(),
+        path: ValuePath::root(),
+    };
+
+    let value = reader.value(schema)?;
+    reader.finish()?;
+    Ok(value)
+}
+
+/// Counts the elements which take no space, so that encoding and decoding share a budget of them
+#[derive(Default)]
+struct EmptyElements(usize);
+
+impl EmptyElements {
+    /// Count an element of the sequence or map at `path`
+    fn add(&mut self, path: &ValuePath) -> Result<(), KobbleError> {
+        self.0 += 1;
+        if self.0 > MAX_EMPTY_ELEMENTS {
+            return Err(KobbleError::InvalidFormat {
+                path: path.clone(),
+                message: format!(
+                    "more than {} elements which take no space",
+                    MAX_EMPTY_ELEMENTS
+                ),
+            });
+        }
+        Ok(())
+    }
+}
+
+fn zigzag(n: i128) -> u128 {
+    ((n << 1) ^ (n >> 127)) as u128
+}
+
+fn unzigzag(n: u128) -> i128 {
+    (n >> 1) as i128 ^ -((n & 1) as i128)
+}
+
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+    /// The byte holding the most recent bools, and how many of its bits are used
+    bits: Option<(usize, u32)>,
+    empty_elements: EmptyElements,
+    path: ValuePath,
+}
+
+impl Writer {
+    fn varint(&mut self, mut n: u128) {
+        while n >= 0x80 {
+            self.bytes.push(n as u8 | 0x80);
+            n >>= 7;
+        }
+        self.bytes.push(n as u8);
+    }
+
+    fn len(&mut self, len: usize) {
+        self.varint(len as u128);
+    }
+
+    fn bool(&mut self, v: bool) {
+        let (pos, used) = match self.bits {
+            Some((pos, used)) if used < 8 => (pos, used),
+            _ => {
+                self.bytes.push(0);
+                (self.bytes.len() - 1, 0)
+            }
+        };
+
+        self.bytes[pos] |= (v as u8) << used;
+        self.bits = Some((pos, used + 1));
+    }
+
+    /// Write a value nested one step further in
+    fn nested(&mut self, segment: PathSegment, value: &DynamicValue) -> Result<(), KobbleError> {
+        self.path.push(segment);
+        self.value(value)?;
+        self.path.0.pop();
+        Ok(())
+    }
+
+    fn values(&mut self, values: &[DynamicValue]) -> Result<(), KobbleError> {
+        for (i, value) in values.iter().enumerate() {
+            self.nested(PathSegment::Index(i), value)?;
+        }
+        Ok(())
+    }
+
+    fn fields(&mut self, fields: &[(String, DynamicValue)]) -> Result<(), KobbleError> {
+        for (name, value) in fields {
+            self.nested(PathSegment::Field(name.clone()), value)?;
+        }
+        Ok(())
+    }
+
+    /// Write the elements of a sequence or map, checking that few enough of them take no space
+    /// for the value to be read back
+    fn repeated<T>(
+        &mut self,
+        elements: &[T],
+        mut element: impl FnMut(&mut Self, usize, &T) -> Result<(), KobbleError>,
+    ) -> Result<(), KobbleError> {
+        self.len(elements.len());
+        for (i, value) in elements.iter().enumerate() {
+            let before = (self.bytes.len(), self.bits);
+            element(self, i, value)?;
+            if (self.bytes.len(), self.bits) == before {
+                self.empty_elements.add(&self.path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Every choice made while encoding is recorded in the value itself; the schema is only
+    /// needed to check it beforehand
+    fn value(&mut self, value: &DynamicValue) -> Result<(), KobbleError> {
+        match value {
+            DynamicValue::I8(v) => self.bytes.push(*v as u8),
+            DynamicValue::U8(v) => self.bytes.push(*v),
+            DynamicValue::I16(v) => self.varint(zigzag(*v as i128)),
+            DynamicValue::U16(v) => self.varint(*v as u128),
+            DynamicValue::I32(v) => self.varint(zigzag(*v as i128)),
+            DynamicValue::U32(v) => self.varint(*v as u128),
+            DynamicValue::I64(v) => self.varint(zigzag(*v as i128)),
+            DynamicValue::U64(v) => self.varint(*v as u128),
+            DynamicValue::I128(v) => self.varint(zigzag(*v)),
+            DynamicValue::U128(v) => self.varint(*v),
+            DynamicValue::F32(v) => self.bytes.extend_from_slice(&v.to_le_bytes()),
+            DynamicValue::F64(v) => self.bytes.extend_from_slice(&v.to_le_bytes()),
+            DynamicValue::Bool(v) => self.bool(*v),
+            DynamicValue::Char(v) => self.varint(*v as u128),
+            DynamicValue::Unit | DynamicValue::UnitStruct(_) => (),
+            DynamicValue::Bytes(v) => {
+                self.len(v.len());
+                self.bytes.extend_from_slice(v);
+            }
+            DynamicValue::String(v) => {
+                self.len(v.len());
+                self.bytes.extend_from_slice(v.as_bytes());
+            }
+            DynamicValue::Option(v) => {
+                self.bool(v.is_some());
+                if let Some(v) = v {
+                    self.value(v)?;
+                }
+            }
+            DynamicValue::Struct { fields, .. } => self.fields(fields)?,
+            DynamicValue::Tuple(values) | DynamicValue::TupleStruct(_, values) => {
+                self.values(values)?
+            }
+            DynamicValue::NewtypeStruct(_, v) => self.nested(PathSegment::Index(0), v)?,
+            DynamicValue::UniformSequence(values) => {
+                self.repeated(values, |w, i, v| w.nested(PathSegment::Index(i), v))?
+            }
+            DynamicValue::Map(entries) => self.repeated(entries, |w, i, (k, v)| {
+                w.nested(PathSegment::Key(i), k)?;
+                w.nested(PathSegment::Index(i), v)
+            })?,
+            DynamicValue::Enum { index, value, .. } => {
+                self.varint(*index as u128);
+                match value {
+                    VariantValue::Unit => (),
+                    VariantValue::Newtype(v) => self.nested(PathSegment::Index(0), v)?,
+                    VariantValue::Tuple(values) => self.values(values)?,
+                    VariantValue::Struct(fields) => self.fields(fields)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    /// The byte holding the most recent bools, and how many of its bits are used
+    bits: Option<(u8, u32)>,
+    scopes: Scopes<'a>,
+    /// Number of references followed to reach the current value
+    depth: usize,
+    recursion_limit: Option<usize>,
+    empty_elements: EmptyElements,
+    path: ValuePath,
+}
+
+impl<'a> Reader<'a> {
+    fn error(&self, message: impl Into<String>) -> KobbleError {
+        KobbleError::InvalidFormat {
+            path: self.path.clone(),
+            message: message.into(),
+        }
+    }
+
+    /// Check that nothing is left over once the value has been read
+    fn finish(&self) -> Result<(), KobbleError> {
+        if let Some((byte, used)) = self.bits {
+            if used < 8 && byte >> used != 0 {
+                return Err(self.error("unused bits set after the last bool"));
+            }
+        }
+
+        if self.pos != self.bytes.len() {
+            return Err(self.error(format!(
+                "{} bytes left over after the value",
+                self.bytes.len() - self.pos
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], KobbleError> {
+        if self.bytes.len() - self.pos < n {
+            return Err(self.error("unexpected end of input"));
+        }
+
+        let bytes = &self.bytes[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, KobbleError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], KobbleError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn varint(&mut self) -> Result<u128, KobbleError> {
+        let mut n = 0u128;
+        for shift in (0..128).step_by(7) {
+            let byte = self.byte()?;
+            let bits = (byte & 0x7f) as u128;
+            if bits << shift >> shift != bits {
+                return Err(self.error("varint overflows 128 bits"));
+            }
+
+            n |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+
+        Err(self.error("varint overflows 128 bits"))
+    }
+
+    fn unsigned<T: TryFrom<u128>>(&mut self, kind: &str) -> Result<T, KobbleError> {
+        let n = self.varint()?;
+        T::try_from(n).map_err(|_| self.error(format!("{} is out of range for {}", n, kind)))
+    }
+
+    fn signed<T: TryFrom<i128>>(&mut self, kind: &str) -> Result<T, KobbleError> {
+        let n = unzigzag(self.varint()?);
+        T::try_from(n).map_err(|_| self.error(format!("{} is out of range for {}", n, kind)))
+    }
+
+    fn len(&mut self) -> Result<usize, KobbleError> {
+        self.unsigned("a length")
+    }
+
+    fn bool(&mut self) -> Result<bool, KobbleError> {
+        let (byte, used) = match self.bits {
+            Some((byte, used)) if used < 8 => (byte, used),
+            _ => (self.byte()?, 0),
+        };
+
+        self.bits = Some((byte, used + 1));
+        Ok(byte >> used & 1 == 1)
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, KobbleError> {
+        let len = self.len()?;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    /// Read the length and elements of a sequence or map. Elements which take no space can't be
+    /// checked against the length of the input, so there may only be so many of them in all.
+    fn repeated<T>(
+        &mut self,
+        mut element: impl FnMut(&mut Self, usize) -> Result<T, KobbleError>,
+    ) -> Result<Vec<T>, KobbleError> {
+        let len = self.len()?;
+        let mut elements = Vec::with_capacity(len.min(self.bytes.len() - self.pos));
+        for i in 0..len {
+            let before = (self.pos, self.bits);
+            elements.push(element(self, i)?);
+            if (self.pos, self.bits) == before {
+                self.empty_elements.add(&self.path)?;
+            }
+        }
+        Ok(elements)
+    }
+
+    /// Read a value nested one step further in
+    fn nested(
+        &mut self,
+        segment: PathSegment,
+        schema: &'a Schema,
+    ) -> Result<DynamicValue, KobbleError> {
+        self.path.push(segment);
+        let value = self.value(schema)?;
+        self.path.0.pop();
+        Ok(value)
+    }
+
+    fn elements(&mut self, schemas: &'a TupleSchema) -> Result<Vec<DynamicValue>, KobbleError> {
+        schemas
+            .iter()
+            .enumerate()
+            .map(|(i, schema)| self.nested(PathSegment::Index(i), schema))
+            .collect()
+    }
+
+    fn fields(
+        &mut self,
+        schema: &'a StructSchema,
+    ) -> Result<Vec<(String, DynamicValue)>, KobbleError> {
+        schema
+            .fields
+            .iter()
+            .map(|(name, schema)| {
+                Ok((
+                    name.clone(),
+                    self.nested(PathSegment::Field(name.clone()), schema)?,
+                ))
+            })
+            .collect()
+    }
+
+    /// Read a value of the given schema. Values which contain others are read by functions of
+    /// their own, keeping this one small, as it is on the stack once for every level of nesting.
+    fn value(&mut self, schema: &'a Schema) -> Result<DynamicValue, KobbleError> {
+        match schema {
+            Schema::Recursive { definitions, root } => {
+                self.scopes.push(definitions);
+                let value = self.value(root)?;
+                self.scopes.pop();
+                Ok(value)
+            }
+            Schema::Ref(name) => self.reference(name),
+            Schema::Option(inner) => {
+                if self.bool()? {
+                    Ok(DynamicValue::Option(Some(Box::new(self.value(inner)?))))
+                } else {
+                    Ok(DynamicValue::Option(None))
+                }
+            }
+            Schema::Struct(schema) => Ok(DynamicValue::Struct {
+                name: schema.name.clone(),
+                fields: self.fields(schema)?,
+            }),
+            Schema::Tuple(schemas) => Ok(DynamicValue::Tuple(self.elements(schemas)?)),
+            Schema::TupleStruct(name, schemas) => Ok(DynamicValue::TupleStruct(
+                name.clone(),
+                self.elements(schemas)?,
+            )),
+            Schema::NewtypeStruct(name, inner) => Ok(DynamicValue::NewtypeStruct(
+                name.clone(),
+                Box::new(self.nested(PathSegment::Index(0), inner)?),
+            )),
+            Schema::UniformSequence(inner) => self.sequence(inner),
+            Schema::Map { key, value } => self.map(key, value),
+            Schema::Enum(schema) => self.variant(schema),
+            schema => self.primitive(schema),
+        }
+    }
+
+    fn reference(&mut self, name: &str) -> Result<DynamicValue, KobbleError> {
+        if self
+            .recursion_limit
+            .is_some_and(|limit| self.depth >= limit)
+        {
+            return Err(KobbleError::RecursionLimit {
+                path: self.path.clone(),
+                name: name.to_string(),
+            });
+        }
+
+        let schema = self
+            .scopes
+            .lookup(name)
+            .map_err(|e| e.at(self.path.clone()))?;
+
+        self.depth += 1;
+        let value = self.value(schema)?;
+        self.depth -= 1;
+        Ok(value)
+    }
+
+    fn sequence(&mut self, inner: &'a Schema) -> Result<DynamicValue, KobbleError> {
+        let values = self.repeated(|r, i| r.nested(PathSegment::Index(i), inner))?;
+        Ok(DynamicValue::UniformSequence(values))
+    }
+
+    fn map(&mut self, key: &'a Schema, value: &'a Schema) -> Result<DynamicValue, KobbleError> {
+        let entries = self.repeated(|r, i| {
+            let k = r.nested(PathSegment::Key(i), key)?;
+            let v = r.nested(PathSegment::Index(i), value)?;
+            Ok((k, v))
+        })?;
+        Ok(DynamicValue::Map(entries))
+    }
+
+    fn variant(&mut self, schema: &'a EnumSchema) -> Result<DynamicValue, KobbleError> {
+        let index = self.unsigned::<u32>("a variant index")?;
+        let Some((variant, variant_schema)) = schema.variants.get(index as usize) else {
+            return Err(KobbleError::InvalidEnumIndex {
+                path: self.path.clone(),
+                name: schema.name.clone(),
+                index,
+            });
+        };
+
+        let value = match variant_schema {
+            VariantSchema::Unit => VariantValue::Unit,
+            VariantSchema::Newtype(inner) => {
+                VariantValue::Newtype(Box::new(self.nested(PathSegment::Index(0), inner)?))
+            }
+            VariantSchema::Tuple(schemas) => VariantValue::Tuple(self.elements(schemas)?),
+            VariantSchema::Struct(schema) => VariantValue::Struct(self.fields(schema)?),
+        };
+
+        Ok(DynamicValue::Enum {
+            name: schema.name.clone(),
+            variant: variant.clone(),
+            index,
+            value,
+        })
+    }
+
+    /// Read a value which contains no others
+    fn primitive(&mut self, schema: &'a Schema) -> Result<DynamicValue, KobbleError> {
+        Ok(match schema {
+            Schema::I8 => DynamicValue::I8(self.byte()? as i8),
+            Schema::U8 => DynamicValue::U8(self.byte()?),
+            Schema::I16 => DynamicValue::I16(self.signed("i16")?),
+            Schema::U16 => DynamicValue::U16(self.unsigned("u16")?),
+            Schema::I32 => DynamicValue::I32(self.signed("i32")?),
+            Schema::U32 => DynamicValue::U32(self.unsigned("u32")?),
+            Schema::I64 => DynamicValue::I64(self.signed("i64")?),
+            Schema::U64 => DynamicValue::U64(self.unsigned("u64")?),
+            Schema::I128 => DynamicValue::I128(self.signed("i128")?),
+            Schema::U128 => DynamicValue::U128(self.varint()?),
+            Schema::F32 => DynamicValue::F32(f32::from_le_bytes(self.array()?)),
+            Schema::F64 => DynamicValue::F64(f64::from_le_bytes(self.array()?)),
+            Schema::Bool => DynamicValue::Bool(self.bool()?),
+            Schema::Char => {
+                let n = self.unsigned::<u32>("char")?;
+                DynamicValue::Char(
+                    char::from_u32(n)
+                        .ok_or_else(|| self.error(format!("{:#x} is not a char", n)))?,
+                )
+            }
+            Schema::Unit => DynamicValue::Unit,
+            Schema::Bytes => DynamicValue::Bytes(self.bytes()?),
+            Schema::String => DynamicValue::String(
+                String::from_utf8(self.bytes()?).map_err(|_| self.error("invalid UTF-8"))?,
+            ),
+            Schema::UnitStruct(name) => DynamicValue::UnitStruct(name.clone()),
+            schema => unreachable!("{:?} is not a primitive", schema),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        decode, decode_with_recursion_limit, encode, encode_with_recursion_limit,
+        MAX_EMPTY_ELEMENTS,
+    };
+    use crate::{from_dynamic, to_dynamic, DynamicValue, KobbleError, Schema, ValuePath};
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Shape {
+        Circle { radius: f32, filled: bool },
+        Polygon(Vec<(i16, i16)>),
+        Empty,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Drawing {
+        id: u64,
+        offset: i64,
+        visible: bool,
+        locked: bool,
+        name: String,
+        initial: char,
+        shapes: Vec<Shape>,
+        layers: BTreeMap<u32, Option<i128>>,
+        #[serde(with = "serde_bytes")]
+        thumbnail: Vec<u8>,
+        scale: f64,
+        children: Vec<Drawing>,
+    }
+
+    fn drawing() -> Drawing {
+        Drawing {
+            id: 7,
+            offset: -3,
+            visible: true,
+            locked: false,
+            name: "Spoon".into(),
+            initial: 'ß',
+            shapes: vec![
+                Shape::Circle {
+                    radius: 1.5,
+                    filled: true,
+                },
+                Shape::Polygon(vec![(0, 0), (-200, 300), (i16::MIN, i16::MAX)]),
+                Shape::Empty,
+            ],
+            layers: [(1, Some(i128::MIN)), (300, None), (u32::MAX, Some(-1))]
+                .into_iter()
+                .collect(),
+            thumbnail: vec![1, 2, 3],
+            scale: 0.25,
+            children: vec![Drawing {
+                id: u64::MAX,
+                offset: i64::MIN,
+                visible: false,
+                locked: true,
+                name: String::new(),
+                initial: 'a',
+                shapes: vec![],
+                layers: BTreeMap::new(),
+                thumbnail: vec![],
+                scale: -1.0,
+                children: vec![],
+            }],
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let schema = Schema::infer::<Drawing>();
+        let value = to_dynamic(&drawing()).unwrap();
+
+        let bytes = encode(&value, &schema).unwrap();
+        let decoded = decode(&bytes, &schema).unwrap();
+        assert_eq!(decoded, value);
+        assert_eq!(from_dynamic::<Drawing>(&decoded).unwrap(), drawing());
+
+        // Smaller than bincode's fixed-width layout
+        assert!(bytes.len() < bincode::serialize(&drawing()).unwrap().len() / 2);
+    }
+
+    #[test]
+    fn test_layout() {
+        let schema = Schema::infer::<(u32, i32, bool, u8, bool, Option<()>, Vec<bool>)>();
+        let value =
+            to_dynamic(&(300u32, -2i32, true, 9u8, true, Some(()), vec![false; 6])).unwrap();
+
+        // The first eight bools share a byte, despite the u8 in between
+        assert_eq!(
+            encode(&value, &schema).unwrap(),
+            vec![0xac, 0x02, 0x03, 0b0000_0111, 9, 0x06, 0]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let schema = Schema::infer::<Drawing>();
+        let bytes = encode(&to_dynamic(&drawing()).unwrap(), &schema).unwrap();
+
+        let err = decode(&bytes[..bytes.len() - 1], &schema).unwrap_err();
+        assert_eq!(err.path().to_string(), "children[0].children");
+
+        let mut extra = bytes.clone();
+        extra.push(0);
+        assert!(matches!(
+            decode(&extra, &schema),
+            Err(KobbleError::InvalidFormat { .. })
+        ));
+
+        // The variant index of the first shape, after the id, offset, bools, name, initial and the
+        // number of shapes
+        let mut variant = bytes.clone();
+        variant[12] = 5;
+        assert_eq!(
+            decode(&variant, &schema).unwrap_err(),
+            KobbleError::InvalidEnumIndex {
+                path: ValuePath::root().field("shapes").index(0),
+                name: "Shape".into(),
+                index: 5,
+            }
+        );
+
+        // Values which don't conform to the schema aren't encoded
+        assert!(matches!(
+            encode(&DynamicValue::U8(1), &schema),
+            Err(KobbleError::SchemaMismatch { .. })
+        ));
+
+        // Deeply nested values stop at the recursion limit rather than overflowing the stack
+        #[derive(Serialize, Deserialize)]
+        struct Nested(Option<Box<Nested>>);
+
+        let mut nested = Nested(None);
+        for _ in 0..crate::RECURSION_LIMIT + 1 {
+            nested = Nested(Some(Box::new(nested)));
+        }
+        let schema = Schema::infer::<Nested>();
+        let nested = to_dynamic(&nested).unwrap();
+        assert!(matches!(
+            encode(&nested, &schema),
+            Err(KobbleError::RecursionLimit { .. })
+        ));
+
+        // Unless the limit is lifted for both
+        let bytes = encode_with_recursion_limit(&nested, &schema, None).unwrap();
+        assert!(matches!(
+            decode(&bytes, &schema),
+            Err(KobbleError::RecursionLimit { .. })
+        ));
+        assert_eq!(
+            decode_with_recursion_limit(&bytes, &schema, None).unwrap(),
+            nested
+        );
+    }
+
+    #[test]
+    fn test_empty_elements() {
+        // Elements which take no space are limited in number
+        let schema = Schema::infer::<Vec<()>>();
+        let units = to_dynamic(&vec![(); MAX_EMPTY_ELEMENTS]).unwrap();
+        let bytes = encode(&units, &schema).unwrap();
+        assert_eq!(decode(&bytes, &schema).unwrap(), units);
+
+        let units = to_dynamic(&vec![(); MAX_EMPTY_ELEMENTS + 1]).unwrap();
+        assert!(matches!(
+            encode(&units, &schema),
+            Err(KobbleError::InvalidFormat { .. })
+        ));
+
+        // The limit is on all of them together, not each sequence
+        let nested = Schema::infer::<Vec<Vec<()>>>();
+        let halves = to_dynamic(&vec![vec![(); MAX_EMPTY_ELEMENTS / 2]; 2]).unwrap();
+        let bytes = encode(&halves, &nested).unwrap();
+        assert_eq!(decode(&bytes, &nested).unwrap(), halves);
+
+        let halves = to_dynamic(&vec![vec![(); MAX_EMPTY_ELEMENTS / 2 + 1]; 2]).unwrap();
+        assert!(matches!(
+            encode(&halves, &nested),
+            Err(KobbleError::InvalidFormat { .. })
+        ));
+
+        // Two sequences of 32769 units
+        let halves = [2, 0x81, 0x80, 0x02, 0x81, 0x80, 0x02];
+        assert!(matches!(
+            decode(&halves, &nested),
+            Err(KobbleError::InvalidFormat { .. })
+        ));
+
+        let huge = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f];
+        assert!(matches!(
+            decode(&huge, &schema),
+            Err(KobbleError::InvalidFormat { .. })
+        ));
+        let map = Schema::infer::<BTreeMap<(), ()>>();
+        assert!(matches!(
+            decode(&huge, &map),
+            Err(KobbleError::InvalidFormat { .. })
+        ));
+
+        // Bools take a bit each, so they run out along with the input
+        let options = Schema::infer::<Vec<Option<()>>>();
+        assert!(matches!(
+            decode(&huge, &options),
+            Err(KobbleError::InvalidFormat { .. })
+        ));
+    }
+}
@@ -0,0 +1,8 @@
+//! Source code for other languages and crates, declaring types which read and write the bincode
+//! layout described by a schema.
+
+mod rust;
+mod typescript;
+
+pub use self::rust::rust;
+pub use self::typescript::typescript;
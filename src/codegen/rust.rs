@@ -0,0 +1,531 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use crate::idl::{declared_name, Declarations, DeclaredType};
+use crate::{Schema, VariantSchema};
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+    "unsafe", "use", "where", "while", "abstract", "become", "box", "do", "final", "gen", "macro",
+    "override", "priv", "try", "typeof", "unsized", "virtual", "yield",
+];
+
+/// Rust source declaring the types used by the schema, such that they are encoded by bincode
+/// (with fixed-width integers) exactly as the schema describes. Each type derives `Serialize` and
+/// `Deserialize`, and uses the name given in the schema, or one made from it if another type
+/// has that name.
+///
+/// Bytes become `Vec<u8>`, which bincode encodes the same way. Maps become `BTreeMap`s, or a
+/// `Vec` of pairs if the key contains floats and so can't be ordered. A schema which isn't a
+/// declared type is given a type alias, usually `Root`.
+pub fn rust(schema: &Schema) -> String {
+    let declarations = Declarations::of(schema);
+    let generator = Generator::new(&declarations);
+
+    let mut out = String::from("use serde::{Deserialize, Serialize};\n");
+    if generator.uses_maps {
+        out.push_str("use std::collections::BTreeMap;\n");
+    }
+
+    if let Some(root) = &declarations.root {
+        writeln!(
+            out,
+            "\npub type {} = {};",
+            root,
+            generator.ty(&declarations.schema, false)
+        )
+        .unwrap();
+    }
+
+    for declared in &declarations.types {
+        out.push('\n');
+        generator.declaration(declared, &mut out);
+    }
+
+    out
+}
+
+/// An identifier for the name, along with the name itself if serde needs to be told it
+fn ident(name: &str) -> (String, Option<&str>) {
+    let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && name != "_";
+
+    if !valid {
+        let mut ident: String = name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        if !ident.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            ident.insert(0, '_');
+        }
+        (ident, Some(name))
+    } else if matches!(name, "self" | "Self" | "super" | "crate") {
+        // Can't be raw identifiers
+        (format!("{}_", name), Some(name))
+    } else if KEYWORDS.contains(&name) {
+        (format!("r#{}", name), None)
+    } else {
+        (name.to_string(), None)
+    }
+}
+
+/// An identifier for the name, as `ident` gives, which isn't taken yet
+fn unique_ident<'n>(name: &'n str, taken: &mut HashSet<String>) -> (String, Option<&'n str>) {
+    let (ident, rename) = ident(name);
+    let mut unique = ident.clone();
+    let mut n = 0;
+    while !taken.insert(unique.clone()) {
+        n += 1;
+        unique = format!("{}_{}", ident, n);
+    }
+
+    if unique == ident {
+        (unique, rename)
+    } else {
+        (unique, Some(name))
+    }
+}
+
+/// The schemas directly within a schema
+fn children(schema: &Schema) -> Vec<&Schema> {
+    match schema {
+        Schema::Option(inner)
+        | Schema::UniformSequence(inner)
+        | Schema::NewtypeStruct(_, inner) => {
+            vec![inner]
+        }
+        Schema::Tuple(schemas) | Schema::TupleStruct(_, schemas) => schemas.iter().collect(),
+        Schema::Map { key, value } => vec![key, value],
+        Schema::Struct(s) => s.fields.iter().map(|(_, s)| s).collect(),
+        Schema::Enum(e) => e
+            .variants
+            .iter()
+            .flat_map(|(_, variant)| match variant {
+                VariantSchema::Struct(s) => s.fields.iter().map(|(_, s)| s).collect(),
+                VariantSchema::Tuple(schemas) => schemas.iter().collect(),
+                VariantSchema::Newtype(inner) => vec![&**inner],
+                VariantSchema::Unit => vec![],
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+struct Generator<'a> {
+    /// Declared types, by name
+    types: HashMap<&'a str, &'a DeclaredType>,
+    /// The identifier of each declared type, by name
+    idents: HashMap<&'a str, String>,
+    uses_maps: bool,
+}
+
+impl<'a> Generator<'a> {
+    fn new(declarations: &'a Declarations) -> Self {
+        let mut taken: HashSet<String> = declarations.root.iter().cloned().collect();
+        let idents = declarations
+            .types
+            .iter()
+            .map(|t| (t.name.as_str(), unique_ident(&t.name, &mut taken).0))
+            .collect();
+
+        let mut generator = Self {
+            types: declarations.by_name(),
+            idents,
+            uses_maps: false,
+        };
+        let uses_maps = declarations.types.iter().any(|t| {
+            generator.any(
+                &t.schema,
+                &mut HashSet::new(),
+                &|s| matches!(s, Schema::Map { key, .. } if generator.is_orderable(key)),
+            )
+        });
+        generator.uses_maps = uses_maps;
+        generator
+    }
+
+    /// Whether the schema, or any schema within it, satisfies the predicate
+    fn any(
+        &self,
+        schema: &'a Schema,
+        seen: &mut HashSet<&'a str>,
+        predicate: &impl Fn(&'a Schema) -> bool,
+    ) -> bool {
+        if predicate(schema) {
+            return true;
+        }
+
+        match schema {
+            Schema::Ref(name) => match self.types.get(name.as_str()) {
+                Some(t) if seen.insert(name) => self.any(&t.schema, seen, predicate),
+                _ => false,
+            },
+            _ => children(schema)
+                .into_iter()
+                .any(|s| self.any(s, seen, predicate)),
+        }
+    }
+
+    /// Whether values can derive `Ord` and `Hash`, which floats can't
+    fn is_orderable(&self, schema: &'a Schema) -> bool {
+        !self.any(schema, &mut HashSet::new(), &|s| {
+            matches!(s, Schema::F32 | Schema::F64)
+        })
+    }
+
+    /// The identifier of a declared type
+    fn type_ident(&self, name: &str) -> String {
+        match self.idents.get(name) {
+            Some(ident) => ident.clone(),
+            None => ident(name).0,
+        }
+    }
+
+    /// The type as it is used. A recursive type is boxed unless `indirect`, i.e. already behind
+    /// a pointer.
+    fn ty(&self, schema: &'a Schema, indirect: bool) -> String {
+        match schema {
+            Schema::I8 => "i8".into(),
+            Schema::U8 => "u8".into(),
+            Schema::I16 => "i16".into(),
+            Schema::U16 => "u16".into(),
+            Schema::I32 => "i32".into(),
+            Schema::U32 => "u32".into(),
+            Schema::I64 => "i64".into(),
+            Schema::U64 => "u64".into(),
+            Schema::I128 => "i128".into(),
+            Schema::U128 => "u128".into(),
+            Schema::F32 => "f32".into(),
+            Schema::F64 => "f64".into(),
+            Schema::Bool => "bool".into(),
+            Schema::Char => "char".into(),
+            Schema::Unit => "()".into(),
+            Schema::Bytes => "Vec<u8>".into(),
+            Schema::String => "String".into(),
+            Schema::Option(inner) => format!("Option<{}>", self.ty(inner, indirect)),
+            Schema::UniformSequence(inner) => format!("Vec<{}>", self.ty(inner, true)),
+            Schema::Map { key, value } => {
+                let (k, v) = (self.ty(key, true), self.ty(value, true));
+                if self.is_orderable(key) {
+                    format!("BTreeMap<{}, {}>", k, v)
+                } else {
+                    // Encoded the same way as a map
+                    format!("Vec<({}, {})>", k, v)
+                }
+            }
+            Schema::Tuple(schemas) => self.tuple(schemas, indirect),
+            Schema::Ref(name) => {
+                let recursive = self.types.get(name.as_str()).is_some_and(|t| t.recursive);
+                if recursive && !indirect {
+                    format!("Box<{}>", self.type_ident(name))
+                } else {
+                    self.type_ident(name)
+                }
+            }
+            Schema::Struct(_)
+            | Schema::TupleStruct(..)
+            | Schema::NewtypeStruct(..)
+            | Schema::UnitStruct(_)
+            | Schema::Enum(_)
+            | Schema::Recursive { .. } => unreachable!("Declared types are referred to by name"),
+        }
+    }
+
+    fn tuple(&self, schemas: &'a [Schema], indirect: bool) -> String {
+        let types: Vec<String> = schemas.iter().map(|s| self.ty(s, indirect)).collect();
+        match types.len() {
+            1 => format!("({},)", types[0]),
+            _ => format!("({})", types.join(", ")),
+        }
+    }
+
+    /// `pub a: A, pub b: B`, for the contents of a tuple struct
+    fn tuple_fields(&self, schemas: &'a [Schema], visibility: &str) -> String {
+        let fields: Vec<String> = schemas
+            .iter()
+            .map(|s| format!("{}{}", visibility, self.ty(s, false)))
+            .collect();
+        fields.join(", ")
+    }
+
+    /// A field, with a rename attribute if its name isn't an identifier, or its identifier is
+    /// taken by another field
+    fn field(
+        &self,
+        (name, schema): &'a (String, Schema),
+        taken: &mut HashSet<String>,
+        visibility: &str,
+        separator: &str,
+    ) -> String {
+        let (ident, rename) = unique_ident(name, taken);
+        let ty = self.ty(schema, false);
+        match rename {
+            Some(name) => format!(
+                "#[serde(rename = {:?})]{}{}{}: {}",
+                name, separator, visibility, ident, ty
+            ),
+            None => format!("{}{}: {}", visibility, ident, ty),
+        }
+    }
+
+    fn declaration(&self, declared: &'a DeclaredType, out: &mut String) {
+        let schema = &declared.schema;
+        out.push_str(if self.is_orderable(schema) {
+            "#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]\n"
+        } else {
+            "#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]\n"
+        });
+
+        let name = self.type_ident(&declared.name);
+        if let Some(type_name) = declared_name(schema) {
+            if ident(type_name) != (name.clone(), None) {
+                writeln!(out, "#[serde(rename = {:?})]", type_name).unwrap();
+            }
+        }
+
+        match schema {
+            Schema::Struct(s) if s.fields.is_empty() => writeln!(out, "pub struct {} {{}}", name),
+            Schema::Struct(s) => {
+                writeln!(out, "pub struct {} {{", name).unwrap();
+                let mut taken = HashSet::new();
+                for field in &s.fields {
+                    let field = self.field(field, &mut taken, "pub ", "\n    ");
+                    writeln!(out, "    {},", field).unwrap();
+                }
+                writeln!(out, "}}")
+            }
+            Schema::TupleStruct(_, schemas) => writeln!(
+                out,
+                "pub struct {}({});",
+                name,
+                self.tuple_fields(schemas, "pub ")
+            ),
+            Schema::NewtypeStruct(_, inner) => {
+                writeln!(out, "pub struct {}(pub {});", name, self.ty(inner, false))
+            }
+            Schema::UnitStruct(_) => writeln!(out, "pub struct {};", name),
+            Schema::Enum(e) => {
+                writeln!(out, "pub enum {} {{", name).unwrap();
+                let mut variants = HashSet::new();
+                for (name, variant) in &e.variants {
+                    let (ident, rename) = unique_ident(name, &mut variants);
+                    if let Some(name) = rename {
+                        writeln!(out, "    #[serde(rename = {:?})]", name).unwrap();
+                    }
+
+                    match variant {
+                        VariantSchema::Struct(s) if s.fields.is_empty() => {
+                            writeln!(out, "    {} {{}},", ident)
+                        }
+                        VariantSchema::Struct(s) => {
+                            let mut taken = HashSet::new();
+                            let fields: Vec<String> = s
+                                .fields
+                                .iter()
+                                .map(|field| self.field(field, &mut taken, "", " "))
+                                .collect();
+                            writeln!(out, "    {} {{ {} }},", ident, fields.join(", "))
+                        }
+                        VariantSchema::Tuple(schemas) => {
+                            writeln!(out, "    {}({}),", ident, self.tuple_fields(schemas, ""))
+                        }
+                        VariantSchema::Newtype(inner) => {
+                            writeln!(out, "    {}({}),", ident, self.ty(inner, false))
+                        }
+                        VariantSchema::Unit => writeln!(out, "    {},", ident),
+                    }
+                    .unwrap();
+                }
+                writeln!(out, "}}")
+            }
+            // A recursive definition which isn't a declared type
+            _ => writeln!(out, "pub type {} = {};", name, self.ty(schema, false)),
+        }
+        .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::rust;
+    use crate::fixtures::{Marker, Meters, Person, Shape, Wrapper};
+    use crate::Schema;
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_struct_and_enum() {
+        assert_eq!(
+            rust(&Schema::infer::<Person>()),
+            "use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Person {
+    pub gender: Gender,
+    pub age: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Gender {
+    Truck,
+    Sedan,
+    Motorcycle,
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_every_kind_of_type() {
+        #[derive(Serialize, Deserialize)]
+        struct Layer {
+            shapes: HashMap<u32, Shape>,
+            heights: HashMap<Meters, Marker>,
+            #[serde(rename = "raw-blob", with = "serde_bytes")]
+            blob: Vec<u8>,
+            r#type: Option<()>,
+        }
+
+        assert_eq!(
+            rust(&Schema::infer::<Vec<Layer>>()),
+            "use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub type Root = Vec<Layer>;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Layer {
+    pub shapes: BTreeMap<u32, Shape>,
+    pub heights: Vec<(Meters, Marker)>,
+    #[serde(rename = \"raw-blob\")]
+    pub raw_blob: Vec<u8>,
+    pub r#type: Option<()>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Shape {
+    Circle { radius: Meters, center: Point },
+    Polygon(Vec<(i8, i8)>),
+    Pair(u16, (bool, char)),
+    Named(String),
+    Empty,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Meters(pub f32);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Point(pub i8, pub i8);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Marker;
+"
+        );
+    }
+
+    #[test]
+    fn test_recursive_types_are_boxed() {
+        #[derive(Serialize, Deserialize)]
+        enum Tree {
+            Branch(Box<Tree>, Box<Tree>),
+            Leaf(u8),
+            Forest(Vec<Tree>),
+        }
+
+        let source = rust(&Schema::infer::<Tree>());
+        assert!(source.contains("    Branch(Box<Tree>, Box<Tree>),\n"));
+        assert!(source.contains("    Forest(Vec<Tree>),\n"));
+    }
+
+    #[test]
+    fn test_names_are_unique() {
+        #[derive(Serialize, Deserialize)]
+        #[serde(rename = "type")]
+        struct Keyword {
+            #[serde(rename = "a-b")]
+            first: u8,
+            a_b: u16,
+        }
+
+        type Original = (Wrapper<u8>, Wrapper<String>, Keyword);
+        assert_eq!(
+            rust(&Schema::infer::<Original>()),
+            "use serde::{Deserialize, Serialize};
+
+pub type Root = (Wrapper, Wrapper_1, r#type);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Wrapper {
+    pub inner: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename = \"Wrapper\")]
+pub struct Wrapper_1 {
+    pub inner: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct r#type {
+    #[serde(rename = \"a-b\")]
+    pub a_b: u8,
+    #[serde(rename = \"a_b\")]
+    pub a_b_1: u16,
+}
+"
+        );
+
+        // The source above, which must encode the same way and have the same schema
+        #[allow(non_camel_case_types)]
+        mod generated {
+            use serde::{Deserialize, Serialize};
+
+            pub type Root = (Wrapper, Wrapper_1, r#type);
+
+            #[derive(Serialize, Deserialize)]
+            pub struct Wrapper {
+                pub inner: u8,
+            }
+
+            #[derive(Serialize, Deserialize)]
+            #[serde(rename = "Wrapper")]
+            pub struct Wrapper_1 {
+                pub inner: String,
+            }
+
+            #[derive(Serialize, Deserialize)]
+            pub struct r#type {
+                #[serde(rename = "a-b")]
+                pub a_b: u8,
+                #[serde(rename = "a_b")]
+                pub a_b_1: u16,
+            }
+        }
+
+        assert_eq!(
+            Schema::infer::<generated::Root>(),
+            Schema::infer::<Original>()
+        );
+        assert_eq!(
+            bincode::serialize(&(
+                generated::Wrapper { inner: 1 },
+                generated::Wrapper_1 {
+                    inner: "two".into()
+                },
+                generated::r#type { a_b: 3, a_b_1: 4 },
+            ))
+            .unwrap(),
+            bincode::serialize(&(
+                Wrapper { inner: 1u8 },
+                Wrapper {
+                    inner: "two".to_string()
+                },
+                Keyword { first: 3, a_b: 4 },
+            ))
+            .unwrap()
+        );
+    }
+}
@@ -0,0 +1,996 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use crate::idl::{Declarations, DeclaredType};
+use crate::{Schema, StructSchema, VariantSchema};
+
+/// Names declared types can't have: reserved words, TypeScript's own types, and the classes the
+/// generated code uses
+const RESERVED: &[&str] = &[
+    "any",
+    "Array",
+    "await",
+    "BigInt",
+    "bigint",
+    "boolean",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "DataView",
+    "debugger",
+    "decode",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "encode",
+    "enum",
+    "Error",
+    "export",
+    "extends",
+    "false",
+    "finally",
+    "for",
+    "function",
+    "if",
+    "implements",
+    "import",
+    "in",
+    "instanceof",
+    "interface",
+    "let",
+    "Map",
+    "Math",
+    "never",
+    "new",
+    "null",
+    "number",
+    "Number",
+    "object",
+    "Object",
+    "package",
+    "private",
+    "protected",
+    "public",
+    "Reader",
+    "return",
+    "static",
+    "string",
+    "super",
+    "switch",
+    "symbol",
+    "TextDecoder",
+    "textDecoder",
+    "TextEncoder",
+    "textEncoder",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "Uint8Array",
+    "undefined",
+    "unknown",
+    "var",
+    "void",
+    "while",
+    "with",
+    "Writer",
+    "yield",
+];
+
+/// Reads and writes the primitives of bincode's layout, with fixed-width integers
+const RUNTIME: &str = r#"const textEncoder = new TextEncoder();
+const textDecoder = new TextDecoder("utf-8", { fatal: true });
+
+export class Writer {
+  private buffer = new Uint8Array(64);
+  private view = new DataView(this.buffer.buffer);
+  private len = 0;
+
+  private reserve(n: number): DataView {
+    if (this.len + n > this.buffer.length) {
+      const buffer = new Uint8Array(Math.max(this.buffer.length * 2, this.len + n));
+      buffer.set(this.buffer);
+      this.buffer = buffer;
+      this.view = new DataView(buffer.buffer);
+    }
+    return this.view;
+  }
+
+  u8(v: number): void { this.reserve(1).setUint8(this.len, v); this.len += 1; }
+  i8(v: number): void { this.reserve(1).setInt8(this.len, v); this.len += 1; }
+  u16(v: number): void { this.reserve(2).setUint16(this.len, v, true); this.len += 2; }
+  i16(v: number): void { this.reserve(2).setInt16(this.len, v, true); this.len += 2; }
+  u32(v: number): void { this.reserve(4).setUint32(this.len, v, true); this.len += 4; }
+  i32(v: number): void { this.reserve(4).setInt32(this.len, v, true); this.len += 4; }
+  u64(v: bigint): void { this.reserve(8).setBigUint64(this.len, v, true); this.len += 8; }
+  i64(v: bigint): void { this.reserve(8).setBigInt64(this.len, v, true); this.len += 8; }
+  u128(v: bigint): void { this.u64(BigInt.asUintN(64, v)); this.u64(BigInt.asUintN(64, v >> 64n)); }
+  i128(v: bigint): void { this.u128(BigInt.asUintN(128, v)); }
+  f32(v: number): void { this.reserve(4).setFloat32(this.len, v, true); this.len += 4; }
+  f64(v: number): void { this.reserve(8).setFloat64(this.len, v, true); this.len += 8; }
+  bool(v: boolean): void { this.u8(v ? 1 : 0); }
+
+  raw(v: Uint8Array): void {
+    this.reserve(v.length);
+    this.buffer.set(v, this.len);
+    this.len += v.length;
+  }
+
+  char(v: string): void {
+    if ([...v].length !== 1) throw new Error(`expected a single character, found "${v}"`);
+    this.raw(textEncoder.encode(v));
+  }
+
+  bytes(v: Uint8Array): void { this.u64(BigInt(v.length)); this.raw(v); }
+  string(v: string): void { this.bytes(textEncoder.encode(v)); }
+
+  option<T>(v: T | null, f: (v: T) => void): void {
+    if (v === null) {
+      this.u8(0);
+    } else {
+      this.u8(1);
+      f(v);
+    }
+  }
+
+  seq<T>(v: T[], f: (v: T) => void): void {
+    this.u64(BigInt(v.length));
+    for (const item of v) f(item);
+  }
+
+  map<K, V>(v: Map<K, V>, k: (k: K) => void, f: (v: V) => void): void {
+    this.u64(BigInt(v.size));
+    for (const [key, value] of v) {
+      k(key);
+      f(value);
+    }
+  }
+
+  finish(): Uint8Array { return this.buffer.slice(0, this.len); }
+}
+
+export class Reader {
+  private buffer: Uint8Array;
+  private view: DataView;
+  private pos = 0;
+
+  constructor(bytes: Uint8Array) {
+    this.buffer = bytes;
+    this.view = new DataView(bytes.buffer, bytes.byteOffset, bytes.byteLength);
+  }
+
+  private advance(n: number): number {
+    const pos = this.pos;
+    if (pos + n > this.buffer.length) throw new Error("unexpected end of input");
+    this.pos += n;
+    return pos;
+  }
+
+  u8(): number { return this.view.getUint8(this.advance(1)); }
+  i8(): number { return this.view.getInt8(this.advance(1)); }
+  u16(): number { return this.view.getUint16(this.advance(2), true); }
+  i16(): number { return this.view.getInt16(this.advance(2), true); }
+  u32(): number { return this.view.getUint32(this.advance(4), true); }
+  i32(): number { return this.view.getInt32(this.advance(4), true); }
+  u64(): bigint { return this.view.getBigUint64(this.advance(8), true); }
+  i64(): bigint { return this.view.getBigInt64(this.advance(8), true); }
+  u128(): bigint { const low = this.u64(); return (this.u64() << 64n) | low; }
+  i128(): bigint { return BigInt.asIntN(128, this.u128()); }
+  f32(): number { return this.view.getFloat32(this.advance(4), true); }
+  f64(): number { return this.view.getFloat64(this.advance(8), true); }
+
+  bool(): boolean {
+    const v = this.u8();
+    if (v > 1) throw new Error(`invalid bool ${v}`);
+    return v === 1;
+  }
+
+  len(): number {
+    const n = this.u64();
+    if (n > BigInt(Number.MAX_SAFE_INTEGER)) throw new Error(`invalid length ${n}`);
+    return Number(n);
+  }
+
+  raw(n: number): Uint8Array {
+    const pos = this.advance(n);
+    return this.buffer.slice(pos, pos + n);
+  }
+
+  char(): string {
+    // The first byte of UTF-8 gives the length of the character
+    const first = this.buffer[this.pos];
+    const n = first < 0x80 ? 1 : first < 0xe0 ? 2 : first < 0xf0 ? 3 : 4;
+    return textDecoder.decode(this.raw(n));
+  }
+
+  bytes(): Uint8Array { return this.raw(this.len()); }
+  string(): string { return textDecoder.decode(this.bytes()); }
+
+  option<T>(f: () => T): T | null {
+    const tag = this.u8();
+    if (tag > 1) throw new Error(`invalid option tag ${tag}`);
+    return tag === 1 ? f() : null;
+  }
+
+  seq<T>(f: () => T): T[] {
+    const n = this.len();
+    const v: T[] = [];
+    for (let i = 0; i < n; i++) v.push(f());
+    return v;
+  }
+
+  map<K, V>(k: () => K, f: () => V): Map<K, V> {
+    const n = this.len();
+    const v = new Map<K, V>();
+    for (let i = 0; i < n; i++) {
+      const key = k();
+      v.set(key, f());
+    }
+    return v;
+  }
+}
+"#;
+
+/// TypeScript source declaring a type for each type used by the schema, along with functions to
+/// encode and decode them with the bincode layout the schema describes, using fixed-width
+/// integers. `encode` and `decode` handle the schema itself. Each type uses the name given in the
+/// schema, or one made from it if that isn't an identifier, or is taken by another type or by
+/// the runtime.
+///
+/// Integers wider than 32 bits are `bigint`s, maps are `Map`s, and bytes are `Uint8Array`s. Enums
+/// look as they do in JSON: unit variants are their name, and other variants are an object with
+/// the name as its only key. `None` is `null`, or `{ some: value }` is used for `Some` when the
+/// value could be `null` itself.
+///
+/// Decoding trusts the lengths it reads, as bincode does, so a sequence of elements which take no
+/// space is as long as its length says.
+pub fn typescript(schema: &Schema) -> String {
+    let declarations = Declarations::of(schema);
+    let generator = Generator::new(&declarations);
+
+    let mut out = RUNTIME.to_string();
+    let root = match (&declarations.root, &declarations.schema) {
+        (Some(root), schema) => {
+            writeln!(out, "\nexport type {} = {};", root, generator.ty(schema)).unwrap();
+            generator.functions(root, schema, &mut out);
+            root.as_str()
+        }
+        (None, Schema::Ref(name)) => generator.type_ident(name),
+        _ => unreachable!("Schemas without a name are given one"),
+    };
+
+    for declared in &declarations.types {
+        out.push('\n');
+        generator.declaration(declared, &mut out);
+    }
+
+    write!(
+        out,
+        "
+export function encode(v: {root}): Uint8Array {{
+  const w = new Writer();
+  encode{root}(w, v);
+  return w.finish();
+}}
+
+export function decode(bytes: Uint8Array): {root} {{
+  return decode{root}(new Reader(bytes));
+}}
+"
+    )
+    .unwrap();
+
+    out
+}
+
+/// An identifier for the name of a declared type, which isn't taken yet
+fn unique_ident(name: &str, taken: &mut HashSet<String>) -> String {
+    let mut ident: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '$' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if !is_ident(&ident) {
+        ident.insert(0, '_');
+    }
+
+    let mut unique = ident.clone();
+    let mut n = 0;
+    while !taken.insert(unique.clone()) {
+        n += 1;
+        unique = format!("{}_{}", ident, n);
+    }
+    unique
+}
+
+fn is_ident(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+/// A property name, quoted if need be
+fn key(name: &str) -> String {
+    if is_ident(name) {
+        name.to_string()
+    } else {
+        format!("{:?}", name)
+    }
+}
+
+/// An expression reading the property of an object
+fn property(object: &str, name: &str) -> String {
+    if is_ident(name) {
+        format!("{}.{}", object, name)
+    } else {
+        format!("{}[{:?}]", object, name)
+    }
+}
+
+/// `(x) => statement`, or a block for several statements
+fn lambda(param: &str, statements: &[String]) -> String {
+    match statements {
+        [statement] => format!("({}) => {}", param, statement),
+        _ => format!("({}) => {{ {} }}", param, statements.join("; ")),
+    }
+}
+
+struct Generator<'a> {
+    /// Declared types, by name
+    types: HashMap<&'a str, &'a DeclaredType>,
+    /// The identifier of each declared type, by name
+    idents: HashMap<&'a str, String>,
+}
+
+impl<'a> Generator<'a> {
+    fn new(declarations: &'a Declarations) -> Self {
+        let mut taken: HashSet<String> = RESERVED.iter().map(|name| name.to_string()).collect();
+        taken.extend(declarations.root.iter().cloned());
+        let idents = declarations
+            .types
+            .iter()
+            .map(|t| (t.name.as_str(), unique_ident(&t.name, &mut taken)))
+            .collect();
+
+        Self {
+            types: declarations.by_name(),
+            idents,
+        }
+    }
+
+    /// The identifier of a declared type
+    fn type_ident<'s>(&'s self, name: &'s str) -> &'s str {
+        self.idents.get(name).map_or(name, String::as_str)
+    }
+
+    /// Whether values may be `null`, and so can't be told apart from `None`
+    fn is_nullable(&self, schema: &Schema) -> bool {
+        match schema {
+            Schema::Unit | Schema::UnitStruct(_) | Schema::Option(_) => true,
+            Schema::NewtypeStruct(_, inner) => self.is_nullable(inner),
+            Schema::Recursive { root, .. } => self.is_nullable(root),
+            Schema::Ref(name) => self
+                .types
+                .get(name.as_str())
+                .is_some_and(|t| self.is_nullable(&t.schema)),
+            _ => false,
+        }
+    }
+
+    fn ty(&self, schema: &Schema) -> String {
+        match schema {
+            Schema::I8
+            | Schema::U8
+            | Schema::I16
+            | Schema::U16
+            | Schema::I32
+            | Schema::U32
+            | Schema::F32
+            | Schema::F64 => "number".into(),
+            Schema::I64 | Schema::U64 | Schema::I128 | Schema::U128 => "bigint".into(),
+            Schema::Bool => "boolean".into(),
+            Schema::Char | Schema::String => "string".into(),
+            Schema::Unit => "null".into(),
+            Schema::Bytes => "Uint8Array".into(),
+            Schema::Option(inner) => format!("{} | null", self.some_ty(inner)),
+            Schema::UniformSequence(inner) => {
+                let inner = self.ty(inner);
+                if inner.contains(' ') {
+                    format!("Array<{}>", inner)
+                } else {
+                    format!("{}[]", inner)
+                }
+            }
+            Schema::Map { key, value } => format!("Map<{}, {}>", self.ty(key), self.ty(value)),
+            Schema::Tuple(schemas) => self.tuple_ty(schemas),
+            Schema::Ref(name) => self.type_ident(name).to_string(),
+            Schema::Struct(_)
+            | Schema::TupleStruct(..)
+            | Schema::NewtypeStruct(..)
+            | Schema::UnitStruct(_)
+            | Schema::Enum(_)
+            | Schema::Recursive { .. } => unreachable!("Declared types are referred to by name"),
+        }
+    }
+
+    /// The type of the value in `Some`
+    fn some_ty(&self, inner: &Schema) -> String {
+        if self.is_nullable(inner) {
+            format!("{{ some: {} }}", self.ty(inner))
+        } else {
+            self.ty(inner)
+        }
+    }
+
+    fn tuple_ty(&self, schemas: &[Schema]) -> String {
+        let types: Vec<String> = schemas.iter().map(|s| self.ty(s)).collect();
+        format!("[{}]", types.join(", "))
+    }
+
+    fn object_ty(&self, s: &StructSchema) -> String {
+        if s.fields.is_empty() {
+            return "{}".into();
+        }
+
+        let fields: Vec<String> = s
+            .fields
+            .iter()
+            .map(|(name, schema)| format!("{}: {}", key(name), self.ty(schema)))
+            .collect();
+        format!("{{ {} }}", fields.join("; "))
+    }
+
+    /// Statements writing `value`. Parameters of nested functions are numbered by `depth`.
+    fn encode(&self, schema: &Schema, value: &str, depth: usize, out: &mut Vec<String>) {
+        let x = format!("x{}", depth);
+        let statement = match schema {
+            Schema::I8 => format!("w.i8({})", value),
+            Schema::U8 => format!("w.u8({})", value),
+            Schema::I16 => format!("w.i16({})", value),
+            Schema::U16 => format!("w.u16({})", value),
+            Schema::I32 => format!("w.i32({})", value),
+            Schema::U32 => format!("w.u32({})", value),
+            Schema::I64 => format!("w.i64({})", value),
+            Schema::U64 => format!("w.u64({})", value),
+            Schema::I128 => format!("w.i128({})", value),
+            Schema::U128 => format!("w.u128({})", value),
+            Schema::F32 => format!("w.f32({})", value),
+            Schema::F64 => format!("w.f64({})", value),
+            Schema::Bool => format!("w.bool({})", value),
+            Schema::Char => format!("w.char({})", value),
+            Schema::Bytes => format!("w.bytes({})", value),
+            Schema::String => format!("w.string({})", value),
+            Schema::Unit => return,
+            Schema::Option(inner) => {
+                let some = if self.is_nullable(inner) {
+                    format!("{}.some", x)
+                } else {
+                    x.clone()
+                };
+                let mut statements = vec![];
+                self.encode(inner, &some, depth + 1, &mut statements);
+                format!(
+                    "w.option<{}>({}, {})",
+                    self.some_ty(inner),
+                    value,
+                    lambda(&x, &statements)
+                )
+            }
+            Schema::UniformSequence(inner) => {
+                let mut statements = vec![];
+                self.encode(inner, &x, depth + 1, &mut statements);
+                format!(
+                    "w.seq<{}>({}, {})",
+                    self.ty(inner),
+                    value,
+                    lambda(&x, &statements)
+                )
+            }
+            Schema::Map { key, value: inner } => {
+                let k = format!("k{}", depth);
+                let (mut keys, mut values) = (vec![], vec![]);
+                self.encode(key, &k, depth + 1, &mut keys);
+                self.encode(inner, &x, depth + 1, &mut values);
+                format!(
+                    "w.map<{}, {}>({}, {}, {})",
+                    self.ty(key),
+                    self.ty(inner),
+                    value,
+                    lambda(&k, &keys),
+                    lambda(&x, &values)
+                )
+            }
+            Schema::Tuple(schemas) => {
+                for (i, schema) in schemas.iter().enumerate() {
+                    self.encode(schema, &format!("{}[{}]", value, i), depth, out);
+                }
+                return;
+            }
+            Schema::Ref(name) => format!("encode{}(w, {})", self.type_ident(name), value),
+            Schema::Struct(_)
+            | Schema::TupleStruct(..)
+            | Schema::NewtypeStruct(..)
+            | Schema::UnitStruct(_)
+            | Schema::Enum(_)
+            | Schema::Recursive { .. } => unreachable!("Declared types are referred to by name"),
+        };
+
+        out.push(statement);
+    }
+
+    fn encode_fields(&self, s: &StructSchema, value: &str, out: &mut Vec<String>) {
+        for (name, schema) in &s.fields {
+            self.encode(schema, &property(value, name), 1, out);
+        }
+    }
+
+    /// An expression reading a value
+    fn decode(&self, schema: &Schema) -> String {
+        match schema {
+            Schema::I8 => "r.i8()".into(),
+            Schema::U8 => "r.u8()".into(),
+            Schema::I16 => "r.i16()".into(),
+            Schema::U16 => "r.u16()".into(),
+            Schema::I32 => "r.i32()".into(),
+            Schema::U32 => "r.u32()".into(),
+            Schema::I64 => "r.i64()".into(),
+            Schema::U64 => "r.u64()".into(),
+            Schema::I128 => "r.i128()".into(),
+            Schema::U128 => "r.u128()".into(),
+            Schema::F32 => "r.f32()".into(),
+            Schema::F64 => "r.f64()".into(),
+            Schema::Bool => "r.bool()".into(),
+            Schema::Char => "r.char()".into(),
+            Schema::Bytes => "r.bytes()".into(),
+            Schema::String => "r.string()".into(),
+            Schema::Unit => "null".into(),
+            Schema::Option(inner) => {
+                let some = if self.is_nullable(inner) {
+                    format!("({{ some: {} }})", self.decode(inner))
+                } else {
+                    self.decode(inner)
+                };
+                format!("r.option<{}>(() => {})", self.some_ty(inner), some)
+            }
+            Schema::UniformSequence(inner) => {
+                format!("r.seq<{}>(() => {})", self.ty(inner), self.decode(inner))
+            }
+            Schema::Map { key, value } => format!(
+                "r.map<{}, {}>(() => {}, () => {})",
+                self.ty(key),
+                self.ty(value),
+                self.decode(key),
+                self.decode(value)
+            ),
+            Schema::Tuple(schemas) => self.decode_tuple(schemas),
+            Schema::Ref(name) => format!("decode{}(r)", self.type_ident(name)),
+            Schema::Struct(_)
+            | Schema::TupleStruct(..)
+            | Schema::NewtypeStruct(..)
+            | Schema::UnitStruct(_)
+            | Schema::Enum(_)
+            | Schema::Recursive { .. } => unreachable!("Declared types are referred to by name"),
+        }
+    }
+
+    fn decode_tuple(&self, schemas: &[Schema]) -> String {
+        let values: Vec<String> = schemas.iter().map(|s| self.decode(s)).collect();
+        format!("[{}]", values.join(", "))
+    }
+
+    fn decode_object(&self, s: &StructSchema) -> String {
+        if s.fields.is_empty() {
+            return "{}".into();
+        }
+
+        let fields: Vec<String> = s
+            .fields
+            .iter()
+            .map(|(name, schema)| format!("{}: {}", key(name), self.decode(schema)))
+            .collect();
+        format!("{{ {} }}", fields.join(", "))
+    }
+
+    /// The encode and decode functions of a type, given statements writing `v`, and an
+    /// expression reading it
+    fn write_functions(&self, name: &str, encode: &[String], decode: &str, out: &mut String) {
+        writeln!(
+            out,
+            "\nexport function encode{}(w: Writer, v: {}): void {{",
+            name, name
+        )
+        .unwrap();
+        for statement in encode {
+            writeln!(out, "  {};", statement).unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+
+        writeln!(
+            out,
+            "\nexport function decode{}(r: Reader): {} {{\n  return {};\n}}",
+            name, name, decode
+        )
+        .unwrap();
+    }
+
+    /// The encode and decode functions of a type which isn't an enum
+    fn functions(&self, name: &str, schema: &Schema, out: &mut String) {
+        let mut encode = vec![];
+        self.encode(schema, "v", 1, &mut encode);
+        self.write_functions(name, &encode, &self.decode(schema), out);
+    }
+
+    fn declaration(&self, declared: &DeclaredType, out: &mut String) {
+        let name = self.type_ident(&declared.name);
+        match &declared.schema {
+            Schema::Struct(s) => {
+                writeln!(out, "export interface {} {{", name).unwrap();
+                for (name, schema) in &s.fields {
+                    writeln!(out, "  {}: {};", key(name), self.ty(schema)).unwrap();
+                }
+                writeln!(out, "}}").unwrap();
+
+                let mut encode = vec![];
+                self.encode_fields(s, "v", &mut encode);
+                let mut decode = String::from("{");
+                for (name, schema) in &s.fields {
+                    write!(decode, "\n    {}: {},", key(name), self.decode(schema)).unwrap();
+                }
+                if !s.fields.is_empty() {
+                    decode.push_str("\n  ");
+                }
+                decode.push('}');
+                self.write_functions(name, &encode, &decode, out);
+            }
+            Schema::TupleStruct(_, schemas) => {
+                writeln!(out, "export type {} = {};", name, self.tuple_ty(schemas)).unwrap();
+                let mut encode = vec![];
+                self.encode(&Schema::Tuple(schemas.clone()), "v", 1, &mut encode);
+                self.write_functions(name, &encode, &self.decode_tuple(schemas), out);
+            }
+            Schema::NewtypeStruct(_, inner) => {
+                writeln!(out, "export type {} = {};", name, self.ty(inner)).unwrap();
+                self.functions(name, inner, out);
+            }
+            Schema::UnitStruct(_) => {
+                writeln!(out, "export type {} = null;", name).unwrap();
+                self.functions(name, &Schema::Unit, out);
+            }
+            Schema::Enum(e) => self.enumeration(name, &e.variants, out),
+            // A recursive definition which isn't a declared type
+            schema => {
+                writeln!(out, "export type {} = {};", name, self.ty(schema)).unwrap();
+                self.functions(name, schema, out);
+            }
+        }
+    }
+
+    fn enumeration(&self, name: &str, variants: &[(String, VariantSchema)], out: &mut String) {
+        let is_unit = |variant: &VariantSchema| matches!(variant, VariantSchema::Unit);
+
+        // Each variant as it looks, and the value it holds
+        let mut types = vec![];
+        for (variant, schema) in variants {
+            types.push(match schema {
+                VariantSchema::Unit => format!("{:?}", variant),
+                VariantSchema::Struct(s) => {
+                    format!("{{ {}: {} }}", key(variant), self.object_ty(s))
+                }
+                VariantSchema::Tuple(schemas) => {
+                    format!("{{ {}: {} }}", key(variant), self.tuple_ty(schemas))
+                }
+                VariantSchema::Newtype(inner) => {
+                    format!("{{ {}: {} }}", key(variant), self.ty(inner))
+                }
+            });
+        }
+
+        if types.is_empty() {
+            writeln!(out, "export type {} = never;", name)
+        } else if variants.iter().all(|(_, v)| is_unit(v)) {
+            writeln!(out, "export type {} = {};", name, types.join(" | "))
+        } else {
+            writeln!(out, "export type {} =\n  | {};", name, types.join("\n  | "))
+        }
+        .unwrap();
+
+        // Unit variants are strings, and must be ruled out before looking inside objects
+        writeln!(
+            out,
+            "\nexport function encode{}(w: Writer, v: {}): void {{",
+            name, name
+        )
+        .unwrap();
+        let unit = variants.iter().enumerate().filter(|(_, (_, v))| is_unit(v));
+        let data = variants
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, v))| !is_unit(v));
+        for (n, (index, (variant, schema))) in unit.chain(data).enumerate() {
+            // Only an own property names the variant, not one inherited from the prototype
+            let condition = match schema {
+                VariantSchema::Unit => format!("v === {:?}", variant),
+                _ => format!(
+                    "typeof v === \"object\" && Object.prototype.hasOwnProperty.call(v, {:?})",
+                    variant
+                ),
+            };
+            let keyword = if n == 0 { "  if" } else { " else if" };
+            writeln!(out, "{} ({}) {{\n    w.u32({});", keyword, condition, index).unwrap();
+
+            let mut statements = vec![];
+            match schema {
+                VariantSchema::Unit => (),
+                VariantSchema::Struct(s) => self.encode_fields(s, "data", &mut statements),
+                VariantSchema::Tuple(schemas) => {
+                    self.encode(&Schema::Tuple(schemas.clone()), "data", 1, &mut statements)
+                }
+                VariantSchema::Newtype(inner) => self.encode(inner, "data", 1, &mut statements),
+            }
+            if !statements.is_empty() {
+                // The checks above don't tell the type checker which variant it is
+                let data = property(&format!("(v as {})", types[index]), variant);
+                writeln!(out, "    const data = {};", data).unwrap();
+            }
+            for statement in statements {
+                writeln!(out, "    {};", statement).unwrap();
+            }
+            write!(out, "  }}").unwrap();
+        }
+
+        let error = format!("throw new Error(\"invalid variant of {}\");", name);
+        if variants.is_empty() {
+            writeln!(out, "  {}\n}}", error).unwrap();
+        } else {
+            writeln!(out, " else {{\n    {}\n  }}\n}}", error).unwrap();
+        }
+
+        writeln!(
+            out,
+            "\nexport function decode{}(r: Reader): {} {{\n  const variant = r.u32();\n  switch (variant) {{",
+            name, name
+        )
+        .unwrap();
+        for (index, (variant, schema)) in variants.iter().enumerate() {
+            let value = match schema {
+                VariantSchema::Unit => format!("{:?}", variant),
+                VariantSchema::Struct(s) => {
+                    format!("{{ {}: {} }}", key(variant), self.decode_object(s))
+                }
+                VariantSchema::Tuple(schemas) => {
+                    format!("{{ {}: {} }}", key(variant), self.decode_tuple(schemas))
+                }
+                VariantSchema::Newtype(inner) => {
+                    format!("{{ {}: {} }}", key(variant), self.decode(inner))
+                }
+            };
+            writeln!(out, "    case {}:\n      return {};", index, value).unwrap();
+        }
+        writeln!(
+            out,
+            "    default:\n      throw new Error(`invalid variant ${{variant}} of {}`);\n  }}\n}}",
+            name
+        )
+        .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{typescript, RUNTIME};
+    use crate::fixtures::{Meters, Person, Point, Shape, Wrapper};
+    use crate::Schema;
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::process::Command;
+
+    #[test]
+    fn test_struct_and_enum() {
+        let source = typescript(&Schema::infer::<Person>());
+        assert_eq!(
+            source.strip_prefix(RUNTIME).unwrap(),
+            r#"
+export interface Person {
+  gender: Gender;
+  age: bigint;
+}
+
+export function encodePerson(w: Writer, v: Person): void {
+  encodeGender(w, v.gender);
+  w.u64(v.age);
+}
+
+export function decodePerson(r: Reader): Person {
+  return {
+    gender: decodeGender(r),
+    age: r.u64(),
+  };
+}
+
+export type Gender = "Truck" | "Sedan" | "Motorcycle";
+
+export function encodeGender(w: Writer, v: Gender): void {
+  if (v === "Truck") {
+    w.u32(0);
+  } else if (v === "Sedan") {
+    w.u32(1);
+  } else if (v === "Motorcycle") {
+    w.u32(2);
+  } else {
+    throw new Error("invalid variant of Gender");
+  }
+}
+
+export function decodeGender(r: Reader): Gender {
+  const variant = r.u32();
+  switch (variant) {
+    case 0:
+      return "Truck";
+    case 1:
+      return "Sedan";
+    case 2:
+      return "Motorcycle";
+    default:
+      throw new Error(`invalid variant ${variant} of Gender`);
+  }
+}
+
+export function encode(v: Person): Uint8Array {
+  const w = new Writer();
+  encodePerson(w, v);
+  return w.finish();
+}
+
+export function decode(bytes: Uint8Array): Person {
+  return decodePerson(new Reader(bytes));
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_anonymous_root() {
+        let source = typescript(&Schema::infer::<Vec<(Shape, Option<Option<u8>>)>>());
+        assert!(source.contains(
+            "
+export type Root = Array<[Shape, { some: number | null } | null]>;
+"
+        ));
+        assert!(source.contains(
+            "
+export type Shape =
+  | { Circle: { radius: Meters; center: Point } }
+  | { Polygon: Array<[number, number]> }
+  | { Pair: [number, [boolean, string]] }
+  | { Named: string }
+  | \"Empty\";
+"
+        ));
+        assert!(source.contains("export function decode(bytes: Uint8Array): Root {\n"));
+    }
+
+    #[test]
+    fn test_names_are_unique() {
+        #[derive(Serialize, Deserialize)]
+        #[serde(rename = "my-type")]
+        struct Hyphenated(u8);
+
+        #[derive(Serialize, Deserialize)]
+        #[serde(rename = "Map")]
+        struct Runtime(u16);
+
+        #[derive(Serialize, Deserialize)]
+        struct Writer;
+
+        type Original = (Wrapper<u8>, Wrapper<String>, Hyphenated, Runtime, Writer);
+        let source = typescript(&Schema::infer::<Original>());
+        assert!(source.contains(
+            "
+export type Root = [Wrapper, Wrapper_1, my_type, Map_1, Writer_1];
+"
+        ));
+        assert!(source.contains("export type my_type = number;\n"));
+        assert!(source.contains("export function encodeMap_1(w: Writer, v: Map_1): void {\n"));
+        assert!(source.contains("export type Writer_1 = null;\n"));
+        assert!(source.contains("export function decodeWrapper_1(r: Reader): Wrapper_1 {\n"));
+    }
+
+    /// Run a script alongside the generated source, returning what it prints. Needs a Node.js
+    /// able to run TypeScript.
+    fn run(source: &str, script: &str) -> String {
+        let node = |args: &[&str]| {
+            Command::new("node")
+                .args(["--experimental-strip-types", "--no-warnings"])
+                .args(args)
+                .output()
+                .expect("Node.js is installed")
+        };
+
+        let dir = std::env::temp_dir().join(format!("kobble-typescript-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("generated.mts"), source).unwrap();
+        fs::write(dir.join("main.mts"), script).unwrap();
+        let output = node(&[dir.join("main.mts").to_str().unwrap()]);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    #[ignore = "needs Node.js 22.6 or later, to run TypeScript"]
+    fn test_bincode_layout() {
+        #[derive(Serialize, Deserialize)]
+        struct Layer {
+            shapes: Vec<Shape>,
+            ids: BTreeMap<u64, Option<i8>>,
+            // Longer than the bytes left after its length
+            markers: Vec<()>,
+        }
+
+        let bytes = bincode::serialize(&Layer {
+            shapes: vec![
+                Shape::Circle {
+                    radius: Meters(1.5),
+                    center: Point(-1, 2),
+                },
+                Shape::Polygon(vec![(3, -4)]),
+                Shape::Pair(7, (true, 'é')),
+                Shape::Named("hi".into()),
+                Shape::Empty,
+            ],
+            ids: [(4, None), (9, Some(-3))].into_iter().collect(),
+            markers: vec![(); 100],
+        })
+        .unwrap();
+
+        // Decode the bytes, print the value, and encode it again
+        let script = format!(
+            "import {{ decode, encode }} from \"./generated.mts\";
+const bytes = new Uint8Array({:?});
+const value = decode(bytes);
+console.log(JSON.stringify(value, (_, v) =>
+  typeof v === \"bigint\" ? `${{v}}n` : v instanceof Map ? [...v] : v));
+console.log(JSON.stringify([...encode(value)]));
+",
+            bytes
+        );
+
+        let output = run(&typescript(&Schema::infer::<Layer>()), &script);
+        let mut lines = output.lines();
+        let value: serde_json::Value = serde_json::from_str(lines.next().unwrap()).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "shapes": [
+                    { "Circle": { "radius": 1.5, "center": [-1, 2] } },
+                    { "Polygon": [[3, -4]] },
+                    { "Pair": [7, [true, "é"]] },
+                    { "Named": "hi" },
+                    "Empty",
+                ],
+                "ids": [["4n", null], ["9n", -3]],
+                "markers": vec![(); 100],
+            })
+        );
+        let encoded: Vec<u8> = serde_json::from_str(lines.next().unwrap()).unwrap();
+        assert_eq!(encoded, bytes);
+    }
+}
@@ -0,0 +1,539 @@
+use std::collections::HashSet;
+use std::fmt::{self, Display};
+
+use crate::default_value::default_value_in;
+use crate::migrate::widens;
+use crate::path::{PathSegment, ValuePath};
+use crate::scope::Scopes;
+use crate::validate::{schema_kind, variant_kind};
+use crate::{EnumSchema, Schema, StructSchema, VariantSchema};
+
+/// How data described by one schema may be read by code expecting another, from best to worst
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Compatibility {
+    /// The schemas are the same
+    Identical,
+    /// The schemas differ, but bincode bytes written under one decode the same under the other,
+    /// e.g. when only type names have changed
+    WireCompatible,
+    /// Data written under one schema must be converted with `migrate` to be read under the other
+    Migratable,
+    /// Some data written under one schema cannot be read under the other
+    Incompatible,
+}
+
+/// The outcome of `Schema::check_compatibility`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompatReport {
+    pub compatibility: Compatibility,
+    /// Every difference found between the schemas
+    pub issues: Vec<CompatIssue>,
+}
+
+/// A single difference between two schemas
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompatIssue {
+    /// Location of the difference. Elements of sequences, maps and options share the path of
+    /// the sequence, map or option
+    pub path: ValuePath,
+    pub description: String,
+    /// Whether bytes decode the same in spite of this difference
+    pub wire_compatible: bool,
+    /// Whether `migrate` can convert data in spite of this difference
+    pub migratable: bool,
+}
+
+impl Schema {
+    /// Determine whether data written under this schema can be read by code expecting `other`.
+    /// Renamed struct fields are seen as one field being removed and another added.
+    pub fn check_compatibility(&self, other: &Schema) -> CompatReport {
+        if self == other {
+            return CompatReport {
+                compatibility: Compatibility::Identical,
+                issues: vec![],
+            };
+        }
+
+        let mut checker = Checker {
+            old_scopes: Scopes::default(),
+            new_scopes: Scopes::default(),
+            path: ValuePath::root(),
+            visiting: HashSet::new(),
+            issues: vec![],
+        };
+        checker.schema(self, other);
+
+        let compatibility = if checker.issues.iter().all(|i| i.wire_compatible) {
+            Compatibility::WireCompatible
+        } else if checker.issues.iter().all(|i| i.migratable) {
+            Compatibility::Migratable
+        } else {
+            Compatibility::Incompatible
+        };
+
+        CompatReport {
+            compatibility,
+            issues: checker.issues,
+        }
+    }
+}
+
+impl CompatReport {
+    /// The issues standing in the way of a better compatibility
+    pub fn offending(&self) -> impl Iterator<Item = &CompatIssue> {
+        self.issues
+            .iter()
+            .filter(move |issue| match self.compatibility {
+                Compatibility::Identical | Compatibility::WireCompatible => false,
+                Compatibility::Migratable => !issue.wire_compatible,
+                Compatibility::Incompatible => !issue.migratable,
+            })
+    }
+}
+
+impl Display for CompatIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.description)?;
+        if !self.path.is_root() {
+            write!(f, " (at {})", self.path)?;
+        }
+        Ok(())
+    }
+}
+
+struct Checker<'a> {
+    old_scopes: Scopes<'a>,
+    new_scopes: Scopes<'a>,
+    path: ValuePath,
+    /// Pairs of references being compared further up. Meeting one again means the types are
+    /// compatible so far as the rest of the comparison can tell
+    visiting: HashSet<(*const Schema, *const Schema)>,
+    issues: Vec<CompatIssue>,
+}
+
+impl<'a> Checker<'a> {
+    fn issue(&mut self, description: impl Into<String>, wire_compatible: bool, migratable: bool) {
+        self.issues.push(CompatIssue {
+            path: self.path.clone(),
+            description: description.into(),
+            wire_compatible,
+            migratable,
+        });
+    }
+
+    fn incompatible(&mut self, description: impl Into<String>) {
+        self.issue(description, false, false);
+    }
+
+    /// Note a change of type name, which neither bincode nor `migrate` cares about
+    fn name(&mut self, kind: &str, old: &str, new: &str) {
+        if old != new {
+            self.issue(format!("{} {} renamed to {}", kind, old, new), true, true);
+        }
+    }
+
+    /// Compare schemas nested one step further in
+    fn nested(&mut self, segment: PathSegment, old: &'a Schema, new: &'a Schema) {
+        self.path.push(segment);
+        self.schema(old, new);
+        self.path.0.pop();
+    }
+
+    fn schema(&mut self, old: &'a Schema, new: &'a Schema) {
+        match (old, new) {
+            (Schema::Recursive { definitions, root }, _) => {
+                self.old_scopes.push(definitions);
+                self.schema(root, new);
+                self.old_scopes.pop();
+            }
+            (_, Schema::Recursive { definitions, root }) => {
+                self.new_scopes.push(definitions);
+                self.schema(old, root);
+                self.new_scopes.pop();
+            }
+            (Schema::Ref(_), _) | (_, Schema::Ref(_)) => {
+                let (Some(old), Some(new)) = (self.resolve_old(old), self.resolve_new(new)) else {
+                    return;
+                };
+
+                let key = (old as *const Schema, new as *const Schema);
+                if self.visiting.insert(key) {
+                    self.schema(old, new);
+                    self.visiting.remove(&key);
+                }
+            }
+            (Schema::Option(old), Schema::Option(new))
+            | (Schema::UniformSequence(old), Schema::UniformSequence(new)) => self.schema(old, new),
+            (_, Schema::Option(new)) => {
+                self.issue("value became optional", false, true);
+                self.schema(old, new);
+            }
+            (
+                Schema::Map {
+                    key: old_key,
+                    value: old_value,
+                },
+                Schema::Map {
+                    key: new_key,
+                    value: new_value,
+                },
+            ) => {
+                self.schema(old_key, new_key);
+                self.schema(old_value, new_value);
+            }
+            (Schema::Struct(old), Schema::Struct(new)) => {
+                self.name("struct", &old.name, &new.name);
+                self.fields(old, new);
+            }
+            (Schema::Tuple(old), Schema::Tuple(new)) => self.elements(old, new),
+            (Schema::TupleStruct(old_name, old), Schema::TupleStruct(new_name, new)) => {
+                self.name("tuple struct", old_name, new_name);
+                self.elements(old, new);
+            }
+            (Schema::NewtypeStruct(old_name, old), Schema::NewtypeStruct(new_name, new)) => {
+                self.name("newtype struct", old_name, new_name);
+                self.nested(PathSegment::Index(0), old, new);
+            }
+            (Schema::UnitStruct(old_name), Schema::UnitStruct(new_name)) => {
+                self.name("unit struct", old_name, new_name)
+            }
+            (Schema::Enum(old), Schema::Enum(new)) => self.variants(old, new),
+            (old, new) if old == new => (),
+            (old, new) if widens(old, new) => self.issue(
+                format!("{} widened to {}", schema_kind(old), schema_kind(new)),
+                false,
+                true,
+            ),
+            (old, new) => self.incompatible(format!(
+                "{} changed to {}",
+                schema_kind(old),
+                schema_kind(new)
+            )),
+        }
+    }
+
+    fn resolve_old(&mut self, schema: &'a Schema) -> Option<&'a Schema> {
+        match schema {
+            Schema::Ref(name) => match self.old_scopes.get(name) {
+                Some(schema) => Some(schema),
+                None => {
+                    self.incompatible(format!("undefined type {}", name));
+                    None
+                }
+            },
+            other => Some(other),
+        }
+    }
+
+    fn resolve_new(&mut self, schema: &'a Schema) -> Option<&'a Schema> {
+        match schema {
+            Schema::Ref(name) => match self.new_scopes.get(name) {
+                Some(schema) => Some(schema),
+                None => {
+                    self.incompatible(format!("undefined type {}", name));
+                    None
+                }
+            },
+            other => Some(other),
+        }
+    }
+
+    fn elements(&mut self, old: &'a [Schema], new: &'a [Schema]) {
+        if old.len() != new.len() {
+            self.incompatible(format!("{} elements changed to {}", old.len(), new.len()));
+            return;
+        }
+
+        for (i, (old, new)) in old.iter().zip(new).enumerate() {
+            self.nested(PathSegment::Index(i), old, new);
+        }
+    }
+
+    fn fields(&mut self, old: &'a StructSchema, new: &'a StructSchema) {
+        let same_layout = old.fields.len() == new.fields.len()
+            && old
+                .fields
+                .iter()
+                .zip(&new.fields)
+                .all(|((old, _), (new, _))| old == new);
+
+        if same_layout {
+            for ((name, old), (_, new)) in old.fields.iter().zip(&new.fields) {
+                self.nested(PathSegment::Field(name.clone()), old, new);
+            }
+            return;
+        }
+
+        // Fields are matched by name when migrating
+        let mut changed = false;
+        for (name, old) in &old.fields {
+            match new.fields.iter().find(|(n, _)| n == name) {
+                Some((_, new)) => self.nested(PathSegment::Field(name.clone()), old, new),
+                None => {
+                    self.issue(format!("field {} removed", name), false, true);
+                    changed = true;
+                }
+            }
+        }
+
+        for (name, schema) in &new.fields {
+            if !old.fields.iter().any(|(n, _)| n == name) {
+                // Migrating fills the field in with its default value, if it has one
+                let migratable = default_value_in(schema, &self.new_scopes).is_ok();
+                self.issue(format!("field {} added", name), false, migratable);
+                changed = true;
+            }
+        }
+
+        if !changed {
+            self.issue("fields reordered", false, true);
+        }
+    }
+
+    fn variants(&mut self, old: &'a EnumSchema, new: &'a EnumSchema) {
+        self.name("enum", &old.name, &new.name);
+
+        for (index, (name, old_variant)) in old.variants.iter().enumerate() {
+            let Some((new_index, (_, new_variant))) = new
+                .variants
+                .iter()
+                .enumerate()
+                .find(|(_, (n, _))| n == name)
+            else {
+                // Bincode only records the index, so a variant which was renamed in place
+                // still decodes the same
+                let renamed = new
+                    .variants
+                    .get(index)
+                    .filter(|(_, new_variant)| new_variant == old_variant);
+                self.issue(
+                    format!("variant {} removed", name),
+                    renamed.is_some(),
+                    false,
+                );
+                continue;
+            };
+
+            if new_index != index {
+                self.issue(
+                    format!(
+                        "variant {} moved from index {} to {}",
+                        name, index, new_index
+                    ),
+                    false,
+                    true,
+                );
+            }
+
+            match (old_variant, new_variant) {
+                (VariantSchema::Unit, VariantSchema::Unit) => (),
+                (VariantSchema::Newtype(old), VariantSchema::Newtype(new)) => {
+                    self.nested(PathSegment::Index(0), old, new);
+                }
+                (VariantSchema::Tuple(old), VariantSchema::Tuple(new)) => {
+                    self.elements(old, new);
+                }
+                (VariantSchema::Struct(old), VariantSchema::Struct(new)) => {
+                    self.fields(old, new);
+                }
+                (old, new) => self.incompatible(format!(
+                    "variant {} changed from {} to {}",
+                    name,
+                    variant_kind(old),
+                    variant_kind(new)
+                )),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Compatibility, Schema, StructSchema, ValuePath};
+    use serde::{Deserialize, Serialize};
+
+    mod v1 {
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Serialize, Deserialize)]
+        pub struct Message {
+            pub sender: u32,
+            pub body: Body,
+        }
+
+        #[derive(Serialize, Deserialize)]
+        pub enum Body {
+            Text(String),
+            Ping,
+        }
+    }
+
+    #[test]
+    fn test_identical() {
+        let schema = Schema::infer::<v1::Message>();
+        let report = schema.check_compatibility(&schema);
+        assert_eq!(report.compatibility, Compatibility::Identical);
+        assert!(report.issues.is_empty());
+    }
+
+    #[test]
+    fn test_wire_compatible() {
+        #[derive(Serialize, Deserialize)]
+        struct Envelope {
+            sender: u32,
+            body: Content,
+        }
+
+        #[derive(Serialize, Deserialize)]
+        enum Content {
+            Text(String),
+            Pong,
+        }
+
+        let report =
+            Schema::infer::<v1::Message>().check_compatibility(&Schema::infer::<Envelope>());
+        assert_eq!(report.compatibility, Compatibility::WireCompatible);
+        assert_eq!(report.issues.len(), 3);
+        assert_eq!(report.offending().count(), 0);
+    }
+
+    #[test]
+    fn test_migratable() {
+        #[derive(Serialize, Deserialize)]
+        struct Message {
+            body: Body,
+            sender: u64,
+            sent_at: Option<u64>,
+        }
+
+        #[derive(Serialize, Deserialize)]
+        enum Body {
+            Image(Vec<u8>),
+            Ping,
+            Text(String),
+        }
+
+        let report =
+            Schema::infer::<v1::Message>().check_compatibility(&Schema::infer::<Message>());
+        assert_eq!(report.compatibility, Compatibility::Migratable);
+
+        let offending: Vec<String> = report.offending().map(|i| i.to_string()).collect();
+        assert_eq!(
+            offending,
+            [
+                "u32 widened to u64 (at sender)",
+                "variant Text moved from index 0 to 2 (at body)",
+                "field sent_at added",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_added_field() {
+        #[derive(Serialize, Deserialize)]
+        struct Old {
+            x: u8,
+        }
+
+        #[derive(Serialize, Deserialize)]
+        struct New {
+            x: u8,
+            tree: Tree,
+        }
+
+        #[derive(Serialize, Deserialize)]
+        enum Tree {
+            Leaf,
+            Branch(Box<Tree>, Box<Tree>),
+        }
+
+        // The new field's default is found through the definitions of the new schema
+        let old = Schema::infer::<Old>();
+        let report = old.check_compatibility(&Schema::infer::<New>());
+        assert_eq!(report.compatibility, Compatibility::Migratable);
+
+        // A field with no default can't be filled in
+        let new = Schema::Recursive {
+            definitions: [(
+                "Loop".to_string(),
+                Schema::Struct(StructSchema {
+                    name: "Loop".into(),
+                    fields: vec![("next".into(), Schema::Ref("Loop".into()))],
+                }),
+            )]
+            .into_iter()
+            .collect(),
+            root: Box::new(Schema::Struct(StructSchema {
+                name: "Old".into(),
+                fields: vec![
+                    ("x".into(), Schema::U8),
+                    ("tree".into(), Schema::Ref("Loop".into())),
+                ],
+            })),
+        };
+        let report = old.check_compatibility(&new);
+        assert_eq!(report.compatibility, Compatibility::Incompatible);
+        let issues: Vec<(String, bool)> = report
+            .offending()
+            .map(|i| (i.to_string(), i.migratable))
+            .collect();
+        assert_eq!(issues, [("field tree added".to_string(), false)]);
+    }
+
+    #[test]
+    fn test_incompatible() {
+        #[derive(Serialize, Deserialize)]
+        struct Message {
+            sender: i32,
+            body: Body,
+        }
+
+        #[derive(Serialize, Deserialize)]
+        enum Body {
+            Text(String, u8),
+        }
+
+        let report =
+            Schema::infer::<v1::Message>().check_compatibility(&Schema::infer::<Message>());
+        assert_eq!(report.compatibility, Compatibility::Incompatible);
+
+        let paths: Vec<(ValuePath, bool)> = report
+            .offending()
+            .map(|i| (i.path.clone(), i.migratable))
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                (ValuePath::root().field("sender"), false),
+                (ValuePath::root().field("body"), false),
+                (ValuePath::root().field("body"), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recursive() {
+        #[derive(Serialize, Deserialize)]
+        struct Node {
+            value: u8,
+            children: Vec<Node>,
+        }
+
+        mod v2 {
+            use serde::{Deserialize, Serialize};
+
+            #[derive(Serialize, Deserialize)]
+            pub struct Node {
+                pub value: u16,
+                pub children: Vec<Node>,
+            }
+        }
+
+        let old = Schema::infer::<Node>();
+        let new = Schema::infer::<v2::Node>();
+        let report = old.check_compatibility(&new);
+        assert_eq!(report.compatibility, Compatibility::Migratable);
+        assert_eq!(report.issues.len(), 1);
+        assert_eq!(report.issues[0].path, ValuePath::root().field("value"));
+    }
+}
@@ -0,0 +1,319 @@
+//! Self-describing stream of records: a header carrying the schema, followed by the records
+//! themselves encoded with bincode.
+//!
+//! Layout, with every integer little-endian:
+//! * Magic number `KOBL`
+//! * Format version, as a u32
+//! * The Schema, serialized with bincode
+//! * Any number of records, serialized with bincode
+//!
+//! The schema and each record are preceded by their length in bytes, as a u64.
+
+use bincode::Options;
+use serde::de::DeserializeSeed;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::marker::PhantomData;
+
+use crate::error::KobbleError;
+use crate::path::ValuePath;
+use crate::seed::LimitedSchemaSeed;
+use crate::{deserialize_dynamic, to_dynamic, DynamicValue, Schema};
+
+pub const MAGIC: [u8; 4] = *b"KOBL";
+pub const VERSION: u32 = 1;
+
+/// Bincode configuration used for everything in a container
+pub(crate) fn options() -> impl Options {
+    bincode::DefaultOptions::new().with_fixint_encoding()
+}
+
+pub(crate) fn invalid(message: impl Into<String>) -> KobbleError {
+    KobbleError::InvalidFormat {
+        path: ValuePath::root(),
+        message: message.into(),
+    }
+}
+
+/// Write a length-prefixed block of bytes
+pub(crate) fn write_block<W: Write>(writer: &mut W, bytes: &[u8]) -> Result<(), KobbleError> {
+    writer.write_all(&(bytes.len() as u64).to_le_bytes())?;
+    writer.write_all(bytes)?;
+    Ok(())
+}
+
+/// Read a length-prefixed block of bytes, or `None` at the end of the stream
+pub(crate) fn read_block<R: Read>(reader: &mut R) -> Result<Option<Vec<u8>>, KobbleError> {
+    let mut len = [0; 8];
+    let mut filled = 0;
+    while filled < len.len() {
+        match reader.read(&mut len[filled..])? {
+            0 if filled == 0 => return Ok(None),
+            0 => return Err(invalid("stream ends in the middle of a length")),
+            n => filled += n,
+        }
+    }
+
+    // Don't trust the length enough to allocate it all up front
+    let len = u64::from_le_bytes(len);
+    let mut bytes = vec![];
+    reader.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(invalid(format!(
+            "expected a block of {} bytes, found {}",
+            len,
+            bytes.len()
+        )));
+    }
+
+    Ok(Some(bytes))
+}
+
+/// Decode bytes described by the schema
+pub(crate) fn decode(schema: &Schema, bytes: &[u8]) -> Result<DynamicValue, KobbleError> {
+    let mut deser = bincode::Deserializer::from_slice(bytes, options());
+    deserialize_dynamic(schema, &mut deser)
+}
+
+/// Decode a value holding schemas, nested no deeper than `RECURSION_LIMIT`, with the seed which
+/// reads it
+pub(crate) fn decode_schema<'de, S: DeserializeSeed<'de>>(
+    seed: S,
+    bytes: &'de [u8],
+) -> Result<S::Value, KobbleError> {
+    let mut deser = bincode::Deserializer::from_slice(bytes, options());
+    seed.deserialize(&mut deser)
+        .map_err(|e| invalid(format!("invalid schema: {}", e)))
+}
+
+pub(crate) fn write_header<W: Write>(writer: &mut W, version: u32) -> Result<(), KobbleError> {
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&version.to_le_bytes())?;
+    Ok(())
+}
+
+/// Check the magic number, returning the format version
+pub(crate) fn read_header<R: Read>(reader: &mut R) -> Result<u32, KobbleError> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(invalid("not a Kobble container"));
+    }
+
+    let mut version = [0; 4];
+    reader.read_exact(&mut version)?;
+    Ok(u32::from_le_bytes(version))
+}
+
+/// Writes records of type `T` to a container
+pub struct Writer<W: Write, T: ?Sized> {
+    writer: W,
+    schema: Schema,
+    _marker: PhantomData<fn(&T)>,
+}
+
+impl<W: Write, T: Serialize> Writer<W, T> {
+    /// Start a container holding records of type `T`, with the schema inferred from `T`
+    pub fn new<'de>(writer: W) -> Result<Self, KobbleError>
+    where
+        T: Deserialize<'de>,
+    {
+        Self::with_schema(writer, &Schema::infer::<T>())
+    }
+}
+
+impl<W: Write, T: Serialize + ?Sized> Writer<W, T> {
+    /// Start a container holding records described by `schema`. This allows writing
+    /// DynamicValues, whose schema can't be inferred.
+    pub fn with_schema(mut writer: W, schema: &Schema) -> Result<Self, KobbleError> {
+        write_header(&mut writer, VERSION)?;
+        write_block(&mut writer, &options().serialize(schema)?)?;
+
+        Ok(Self {
+            writer,
+            schema: schema.clone(),
+            _marker: PhantomData,
+        })
+    }
+
+    /// Write a record, which must conform to the container's schema
+    pub fn write(&mut self, record: &T) -> Result<(), KobbleError> {
+        if let Err(errors) = self.schema.validate(&to_dynamic(record)?) {
+            return Err(KobbleError::InvalidValue {
+                path: ValuePath::root(),
+                errors,
+            });
+        }
+
+        write_block(&mut self.writer, &options().serialize(record)?)
+    }
+
+    pub fn flush(&mut self) -> Result<(), KobbleError> {
+        Ok(self.writer.flush()?)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Reads the records of a container as DynamicValues, without knowing their type
+pub struct Reader<R: Read> {
+    reader: R,
+    schema: Schema,
+}
+
+impl<R: Read> Reader<R> {
+    /// Read the header of a container
+    pub fn new(mut reader: R) -> Result<Self, KobbleError> {
+        let version = read_header(&mut reader)?;
+        if version != VERSION {
+            return Err(invalid(format!("unsupported version {}", version)));
+        }
+
+        let schema = read_block(&mut reader)?.ok_or_else(|| invalid("missing schema"))?;
+        let schema = decode_schema(LimitedSchemaSeed::default(), &schema)?;
+
+        Ok(Self { reader, schema })
+    }
+
+    /// The schema describing every record
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// The next record, or `None` at the end of the container
+    pub fn read(&mut self) -> Result<Option<DynamicValue>, KobbleError> {
+        match read_block(&mut self.reader)? {
+            Some(bytes) => decode(&self.schema, &bytes).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for Reader<R> {
+    type Item = Result<DynamicValue, KobbleError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{options, write_block, write_header, Reader, Writer, VERSION};
+    use crate::{from_dynamic, to_dynamic, DynamicValue, KobbleError, Schema};
+    use bincode::Options;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    enum Gender {
+        Truck,
+        Sedan,
+        Motorcycle,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    struct Person {
+        gender: Gender,
+        age: u64,
+    }
+
+    fn people() -> Vec<Person> {
+        vec![
+            Person {
+                gender: Gender::Sedan,
+                age: 30,
+            },
+            Person {
+                gender: Gender::Motorcycle,
+                age: 4,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut writer = Writer::<_, Person>::new(vec![]).unwrap();
+        for person in people() {
+            writer.write(&person).unwrap();
+        }
+        let bytes = writer.into_inner();
+
+        let reader = Reader::new(bytes.as_slice()).unwrap();
+        assert_eq!(reader.schema(), &Schema::infer::<Person>());
+
+        let read: Vec<Person> = reader
+            .map(|value| from_dynamic(&value.unwrap()).unwrap())
+            .collect();
+        assert_eq!(read, people());
+    }
+
+    #[test]
+    fn test_dynamic_records() {
+        let schema = Schema::infer::<Person>();
+        let mut writer = Writer::with_schema(vec![], &schema).unwrap();
+        writer.write(&to_dynamic(&people()[0]).unwrap()).unwrap();
+        let bytes = writer.into_inner();
+
+        let mut reader = Reader::new(bytes.as_slice()).unwrap();
+        assert_eq!(
+            reader.read().unwrap(),
+            Some(to_dynamic(&people()[0]).unwrap())
+        );
+        assert_eq!(reader.read().unwrap(), None);
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(matches!(
+            Reader::new(&b"JSON{}{}"[..]),
+            Err(KobbleError::InvalidFormat { .. })
+        ));
+
+        let mut writer = Writer::<_, Person>::new(vec![]).unwrap();
+        writer.write(&people()[0]).unwrap();
+        let mut bytes = writer.into_inner();
+        bytes.pop();
+
+        let mut reader = Reader::new(bytes.as_slice()).unwrap();
+        assert!(matches!(
+            reader.read(),
+            Err(KobbleError::InvalidFormat { .. })
+        ));
+    }
+
+    #[test]
+    fn test_deep_schema() {
+        let mut schema = Schema::Unit;
+        for _ in 0..200 {
+            schema = Schema::Option(Box::new(schema));
+        }
+
+        let mut bytes = vec![];
+        write_header(&mut bytes, VERSION).unwrap();
+        write_block(&mut bytes, &options().serialize(&schema).unwrap()).unwrap();
+        assert!(matches!(
+            Reader::new(bytes.as_slice()),
+            Err(KobbleError::InvalidFormat { .. })
+        ));
+    }
+
+    #[test]
+    fn test_mismatched_record() {
+        let schema = Schema::infer::<Person>();
+        let mut writer = Writer::with_schema(vec![], &schema).unwrap();
+        assert!(matches!(
+            writer.write(&DynamicValue::U64(30)),
+            Err(KobbleError::InvalidValue { .. })
+        ));
+
+        let bytes = writer.into_inner();
+        let mut reader = Reader::new(bytes.as_slice()).unwrap();
+        assert_eq!(reader.read().unwrap(), None);
+    }
+}
@@ -0,0 +1,422 @@
+use std::collections::BTreeMap;
+
+use crate::error::KobbleError;
+use crate::path::ValuePath;
+use crate::scope::Scopes;
+use crate::{Definitions, DynamicValue, Schema, StructSchema, VariantSchema, VariantValue};
+
+impl Schema {
+    /// Build an instance of this schema out of zeroes, `false`, `'\0'`, empty strings, bytes,
+    /// sequences and maps, and the first variant of each enum.
+    /// Options are `None`. Enum variants which can only be built from an infinitely nested value
+    /// are skipped, so that recursive types produce a finite value.
+    ///
+    /// # Panics
+    /// Panics if the schema has no finite instance, or refers to an undefined type. See
+    /// `try_default_value` for a version which reports these as errors.
+    pub fn default_value(&self) -> DynamicValue {
+        self.try_default_value()
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Build an instance of this schema, as `default_value` does
+    pub fn try_default_value(&self) -> Result<DynamicValue, KobbleError> {
+        DefaultBuilder::new(&Scopes::default()).value(self)
+    }
+}
+
+/// Default value of a schema which may refer to the definitions of `scopes`
+pub(crate) fn default_value_in<'a>(
+    schema: &'a Schema,
+    scopes: &Scopes<'a>,
+) -> Result<DynamicValue, KobbleError> {
+    DefaultBuilder::new(scopes).value(schema)
+}
+
+struct DefaultBuilder<'a> {
+    scopes: Scopes<'a>,
+    /// For each scope, the definitions known to have a finite instance, ranked by how many steps
+    /// it took to prove it: a definition only depends on definitions of a lower rank.
+    ranks: Vec<BTreeMap<&'a str, usize>>,
+    /// Only definitions ranked below this may be used, so that building a value always
+    /// makes progress towards a leaf
+    limit: usize,
+}
+
+impl<'a> DefaultBuilder<'a> {
+    /// A builder inside of the given scopes
+    fn new(scopes: &Scopes<'a>) -> Self {
+        let mut builder = DefaultBuilder {
+            scopes: Scopes::default(),
+            ranks: vec![],
+            limit: usize::MAX,
+        };
+
+        for definitions in scopes.iter() {
+            builder.enter(definitions);
+        }
+        builder
+    }
+
+    fn lookup(&self, name: &str) -> Result<(&'a Schema, Option<usize>), KobbleError> {
+        let (scope, schema) = self
+            .scopes
+            .find(name)
+            .ok_or_else(|| KobbleError::UndefinedType {
+                path: ValuePath::root(),
+                name: name.to_string(),
+            })?;
+        Ok((schema, self.ranks[scope].get(name).copied()))
+    }
+
+    /// Whether the schema can be instantiated using only definitions ranked below the limit
+    fn is_finite(&mut self, schema: &'a Schema) -> Result<bool, KobbleError> {
+        Ok(match schema {
+            Schema::Ref(name) => self.lookup(name)?.1.is_some_and(|rank| rank < self.limit),
+            Schema::Recursive { definitions, root } => {
+                let limit = self.enter(definitions);
+                let finite = self.is_finite(root);
+                self.exit(limit);
+                finite?
+            }
+            Schema::Struct(s) => self.all_finite(s.fields.iter().map(|(_, f)| f))?,
+            Schema::Tuple(fields) | Schema::TupleStruct(_, fields) => self.all_finite(fields)?,
+            Schema::NewtypeStruct(_, inner) => self.is_finite(inner)?,
+            Schema::Enum(e) => {
+                for (_, variant) in &e.variants {
+                    if self.is_variant_finite(variant)? {
+                        return Ok(true);
+                    }
+                }
+                false
+            }
+            _ => true,
+        })
+    }
+
+    fn is_variant_finite(&mut self, variant: &'a VariantSchema) -> Result<bool, KobbleError> {
+        match variant {
+            VariantSchema::Struct(s) => self.all_finite(s.fields.iter().map(|(_, f)| f)),
+            VariantSchema::Tuple(fields) => self.all_finite(fields),
+            VariantSchema::Newtype(inner) => self.is_finite(inner),
+            VariantSchema::Unit => Ok(true),
+        }
+    }
+
+    fn all_finite(
+        &mut self,
+        schemas: impl IntoIterator<Item = &'a Schema>,
+    ) -> Result<bool, KobbleError> {
+        for schema in schemas {
+            if !self.is_finite(schema)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Enter a `Schema::Recursive`, returning the limit to restore on exit
+    fn enter(&mut self, definitions: &'a Definitions) -> usize {
+        let limit = std::mem::replace(&mut self.limit, usize::MAX);
+        self.scopes.push(definitions);
+        self.ranks.push(BTreeMap::new());
+        self.rank_definitions();
+        limit
+    }
+
+    fn exit(&mut self, limit: usize) {
+        self.scopes.pop();
+        self.ranks.pop();
+        self.limit = limit;
+    }
+
+    /// Rank the definitions which have a finite instance, by growing the set until it stops
+    /// changing. Definitions which refer to undefined types are left unranked, and reported
+    /// when a value is built from them.
+    fn rank_definitions(&mut self) {
+        for rank in 0.. {
+            let definitions = self.scopes.iter().last().unwrap();
+            let mut found = vec![];
+            for (name, schema) in definitions {
+                if !self.ranks.last().unwrap().contains_key(name.as_str())
+                    && self.is_finite(schema) == Ok(true)
+                {
+                    found.push(name.as_str());
+                }
+            }
+
+            if found.is_empty() {
+                return;
+            }
+
+            let ranks = self.ranks.last_mut().unwrap();
+            ranks.extend(found.into_iter().map(|name| (name, rank)));
+        }
+    }
+
+    fn value(&mut self, schema: &'a Schema) -> Result<DynamicValue, KobbleError> {
+        Ok(match schema {
+            Schema::I8 => DynamicValue::I8(0),
+            Schema::U8 => DynamicValue::U8(0),
+            Schema::I16 => DynamicValue::I16(0),
+            Schema::U16 => DynamicValue::U16(0),
+            Schema::I32 => DynamicValue::I32(0),
+            Schema::U32 => DynamicValue::U32(0),
+            Schema::I64 => DynamicValue::I64(0),
+            Schema::U64 => DynamicValue::U64(0),
+            Schema::I128 => DynamicValue::I128(0),
+            Schema::U128 => DynamicValue::U128(0),
+            Schema::F32 => DynamicValue::F32(0.),
+            Schema::F64 => DynamicValue::F64(0.),
+            Schema::Bool => DynamicValue::Bool(false),
+            Schema::Char => DynamicValue::Char('\0'),
+            Schema::Unit => DynamicValue::Unit,
+            Schema::Bytes => DynamicValue::Bytes(vec![]),
+            Schema::String => DynamicValue::String(String::new()),
+            Schema::Option(_) => DynamicValue::Option(None),
+            Schema::Struct(s) => DynamicValue::Struct {
+                name: s.name.clone(),
+                fields: self.fields(s)?,
+            },
+            Schema::Tuple(fields) => DynamicValue::Tuple(self.elements(fields)?),
+            Schema::TupleStruct(name, fields) => {
+                DynamicValue::TupleStruct(name.clone(), self.elements(fields)?)
+            }
+            Schema::NewtypeStruct(name, inner) => {
+                DynamicValue::NewtypeStruct(name.clone(), Box::new(self.value(inner)?))
+            }
+            Schema::UnitStruct(name) => DynamicValue::UnitStruct(name.clone()),
+            Schema::UniformSequence(_) => DynamicValue::UniformSequence(vec![]),
+            Schema::Map { .. } => DynamicValue::Map(vec![]),
+            Schema::Enum(e) => {
+                let mut first = None;
+                for (index, (variant, schema)) in e.variants.iter().enumerate() {
+                    if self.is_variant_finite(schema)? {
+                        first = Some((index, variant, schema));
+                        break;
+                    }
+                }
+
+                let (index, variant, schema) =
+                    first.ok_or_else(|| KobbleError::NoFiniteInstance {
+                        path: ValuePath::root(),
+                        name: e.name.clone(),
+                    })?;
+
+                let value = match schema {
+                    VariantSchema::Struct(s) => VariantValue::Struct(self.fields(s)?),
+                    VariantSchema::Tuple(fields) => VariantValue::Tuple(self.elements(fields)?),
+                    VariantSchema::Newtype(inner) => {
+                        VariantValue::Newtype(Box::new(self.value(inner)?))
+                    }
+                    VariantSchema::Unit => VariantValue::Unit,
+                };
+
+                DynamicValue::Enum {
+                    name: e.name.clone(),
+                    variant: variant.clone(),
+                    index: index as u32,
+                    value,
+                }
+            }
+            Schema::Ref(name) => {
+                let (schema, rank) = self.lookup(name)?;
+                // An unranked definition may only have failed to rank because of an error
+                // inside of it, which is better to report than the lack of a finite instance
+                if rank.is_none() {
+                    self.is_finite(schema)?;
+                }
+
+                let rank = rank.filter(|&rank| rank < self.limit).ok_or_else(|| {
+                    KobbleError::NoFiniteInstance {
+                        path: ValuePath::root(),
+                        name: name.clone(),
+                    }
+                })?;
+
+                let limit = std::mem::replace(&mut self.limit, rank);
+                let value = self.value(schema);
+                self.limit = limit;
+                value?
+            }
+            Schema::Recursive { definitions, root } => {
+                let limit = self.enter(definitions);
+                let value = self.value(root);
+                self.exit(limit);
+                value?
+            }
+        })
+    }
+
+    fn fields(
+        &mut self,
+        schema: &'a StructSchema,
+    ) -> Result<Vec<(String, DynamicValue)>, KobbleError> {
+        schema
+            .fields
+            .iter()
+            .map(|(name, field)| Ok((name.clone(), self.value(field)?)))
+            .collect()
+    }
+
+    fn elements(&mut self, fields: &'a [Schema]) -> Result<Vec<DynamicValue>, KobbleError> {
+        fields.iter().map(|field| self.value(field)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        from_dynamic, DynamicValue, EnumSchema, KobbleError, Schema, ValuePath, VariantSchema,
+        VariantValue,
+    };
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Component {
+        name: String,
+        position: (f32, f32, f32),
+        parent: Option<u64>,
+        tags: Vec<String>,
+        properties: HashMap<String, i32>,
+        shape: Shape,
+        id: Id,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Shape {
+        Circle { radius: f32 },
+        Square(f32),
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Id(char);
+
+    #[test]
+    fn test_default_component() {
+        let schema = Schema::infer::<Component>();
+        let value = schema.default_value();
+        assert_eq!(schema.validate(&value), Ok(()));
+
+        assert_eq!(
+            from_dynamic::<Component>(&value).unwrap(),
+            Component {
+                name: String::new(),
+                position: (0., 0., 0.),
+                parent: None,
+                tags: vec![],
+                properties: HashMap::new(),
+                shape: Shape::Circle { radius: 0. },
+                id: Id('\0'),
+            }
+        );
+    }
+
+    #[test]
+    fn test_default_recursive() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        enum Expr {
+            Neg(Box<Expr>),
+            Add(Box<Expr>, Box<Expr>),
+            Call { args: Vec<Expr> },
+            Leaf(Wrapper),
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Wrapper(Option<Box<Expr>>);
+
+        let schema = Schema::infer::<Expr>();
+        let value = schema.default_value();
+        assert_eq!(schema.validate(&value), Ok(()));
+
+        // The first variants can never end, but a sequence may be empty
+        let DynamicValue::Enum { variant, value, .. } = &value else {
+            panic!()
+        };
+        assert_eq!(variant, "Call");
+        assert_eq!(
+            value,
+            &VariantValue::Struct(vec![("args".into(), DynamicValue::UniformSequence(vec![]))])
+        );
+    }
+
+    #[test]
+    fn test_default_mutually_recursive() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        enum Tree {
+            Branch(Box<Forest>),
+            Leaf(u8),
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        enum Forest {
+            More(Box<Tree>, Box<Forest>),
+            One(Box<Tree>),
+        }
+
+        let schema = Schema::infer::<Forest>();
+        let value = schema.default_value();
+        assert_eq!(schema.validate(&value), Ok(()));
+        assert_eq!(
+            from_dynamic::<Forest>(&value).unwrap(),
+            Forest::One(Box::new(Tree::Leaf(0)))
+        );
+    }
+
+    #[test]
+    fn test_default_nested_recursive() {
+        let list = Schema::Enum(EnumSchema {
+            name: "List".into(),
+            variants: vec![
+                (
+                    "Cons".into(),
+                    VariantSchema::Newtype(Box::new(Schema::Ref("List".into()))),
+                ),
+                ("Nil".into(), VariantSchema::Unit),
+            ],
+        });
+        let schema = Schema::Enum(EnumSchema {
+            name: "Outer".into(),
+            variants: vec![(
+                "Chain".into(),
+                VariantSchema::Newtype(Box::new(Schema::Recursive {
+                    definitions: [("List".to_string(), list)].into_iter().collect(),
+                    root: Box::new(Schema::Ref("List".into())),
+                })),
+            )],
+        });
+
+        let value = schema.default_value();
+        assert_eq!(schema.validate(&value), Ok(()));
+
+        let DynamicValue::Enum {
+            value: VariantValue::Newtype(inner),
+            ..
+        } = &value
+        else {
+            panic!()
+        };
+        assert!(matches!(&**inner, DynamicValue::Enum { variant, .. } if variant == "Nil"));
+    }
+
+    #[test]
+    fn test_no_default() {
+        assert_eq!(
+            Schema::Ref("Missing".into()).try_default_value(),
+            Err(KobbleError::UndefinedType {
+                path: ValuePath::root(),
+                name: "Missing".into(),
+            })
+        );
+
+        let never = Schema::Enum(EnumSchema {
+            name: "Never".into(),
+            variants: vec![],
+        });
+        assert!(matches!(
+            never.try_default_value(),
+            Err(KobbleError::NoFiniteInstance { name, .. }) if name == "Never"
+        ));
+    }
+}
@@ -1,110 +1,317 @@
-use serde::de::SeqAccess;
-use serde::{de::Visitor, Deserialize, Deserializer};
+use serde::de::{self, DeserializeSeed, EnumAccess, MapAccess, SeqAccess, VariantAccess};
+use serde::{de::Visitor, Deserialize};
 use std::cell::RefCell;
 use std::fmt;
 
-use crate::{string_to_static, DynamicValue, EnumSchema, Schema, StructSchema, TupleSchema};
-
-/// A struct which pretends to be the schema set with set_schema.
-/// Note that schema are set on a per-thread basis!
-pub struct SchemaDeserializer(pub DynamicValue);
+use crate::error::KobbleError;
+use crate::path::{PathSegment, ValuePath};
+use crate::scope::Scopes;
+use crate::{
+    names_to_static, string_to_static, DynamicValue, EnumSchema, Schema, StructSchema,
+    VariantSchema, VariantValue,
+};
+
+/// Default maximum depth of nested references to recursive types. Guards against running out of
+/// stack on corrupt or malicious data
+pub const RECURSION_LIMIT: usize = 64;
+
+/// Deserializes a DynamicValue described by the given schema.
+///
+/// e.g. `bincode::DefaultOptions::new().deserialize_seed(SchemaSeed::new(&schema), &bytes)`
+#[derive(Debug, Clone, Copy)]
+pub struct SchemaSeed<'a> {
+    schema: &'a Schema,
+    recursion_limit: Option<usize>,
+}
 
-impl SchemaDeserializer {
-    thread_local! {
-        static SCHEMA: RefCell<Option<Schema>> = RefCell::new(None);
+impl<'a> SchemaSeed<'a> {
+    pub fn new(schema: &'a Schema) -> Self {
+        Self {
+            schema,
+            recursion_limit: Some(RECURSION_LIMIT),
+        }
     }
 
-    /// Set the schema (for the current thread!)
-    pub fn set_schema(schema: Schema) {
-        Self::SCHEMA.with(|f| *f.borrow_mut() = Some(schema));
+    /// Allow references to recursive types to nest `limit` deep rather than `RECURSION_LIMIT`,
+    /// or however deep they go with `None`. Each reference takes more stack, so the thread
+    /// deserializing must have enough for the data it reads.
+    pub fn with_recursion_limit(self, limit: Option<usize>) -> Self {
+        Self {
+            recursion_limit: limit,
+            ..self
+        }
     }
 }
 
-impl<'de> Deserialize<'de> for SchemaDeserializer {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+impl<'de> DeserializeSeed<'de> for SchemaSeed<'_> {
+    type Value = DynamicValue;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
     where
-        D: Deserializer<'de>,
+        D: serde::Deserializer<'de>,
     {
-        let schema = Self::SCHEMA.with(|f| f.take()).expect("Schema not set!");
-        deserialize_dynamic(schema, deserializer).map(SchemaDeserializer)
+        let context = Context::new(self.recursion_limit);
+        let scopes = Scopes::default();
+        DynamicSeed::new(self.schema, &scopes, &context).deserialize(deserializer)
     }
 }
 
 /// Construct a DynamicValue based on `schema` using the given deserializer
-pub fn deserialize_dynamic<'de, D>(schema: Schema, deser: D) -> Result<DynamicValue, D::Error>
+pub fn deserialize_dynamic<'de, D>(schema: &Schema, deser: D) -> Result<DynamicValue, KobbleError>
 where
     D: serde::Deserializer<'de>,
 {
-    match schema {
-        Schema::UniformSequence(schema) => deser.deserialize_seq(UniformSequenceVisitor(*schema)),
-        Schema::Struct(schema) => {
-            // Make field names static so serde is happy
-            let field_names: Vec<&'static str> = schema
-                .fields
-                .iter()
-                .map(|(name, _)| string_to_static(name.clone()))
-                .collect();
-
-            let field_names: &'static [&'static str] = Box::leak(field_names.into_boxed_slice());
-
-            // Deserialize the struct
-            deser.deserialize_struct(
-                string_to_static(schema.name.clone()),
-                field_names,
-                StructVisitor(schema),
-            )
+    let context = Context::new(Some(RECURSION_LIMIT));
+    let scopes = Scopes::default();
+    DynamicSeed::new(schema, &scopes, &context)
+        .deserialize(deser)
+        .map_err(|e| {
+            context
+                .error
+                .take()
+                .unwrap_or_else(|| KobbleError::custom(e.to_string()))
+        })
+}
+
+/// State shared by every seed over the course of a deserialization
+struct Context {
+    /// The first error to occur, along with where it occurred
+    error: RefCell<Option<KobbleError>>,
+    recursion_limit: Option<usize>,
+}
+
+impl Context {
+    fn new(recursion_limit: Option<usize>) -> Self {
+        Self {
+            error: RefCell::new(None),
+            recursion_limit,
+        }
+    }
+}
+
+/// Path from the outermost value to the one being deserialized, from the innermost step outwards
+struct PathNode<'a> {
+    parent: Option<&'a PathNode<'a>>,
+    step: Step<'a>,
+}
+
+enum Step<'a> {
+    Field(&'a str),
+    Index(usize),
+    Key(usize),
+}
+
+/// A schema, along with the definitions that references within it resolve to
+#[derive(Clone, Copy)]
+struct DynamicSeed<'a> {
+    schema: &'a Schema,
+    scopes: &'a Scopes<'a>,
+    path: Option<&'a PathNode<'a>>,
+    context: &'a Context,
+    /// Number of references followed to get here
+    depth: usize,
+}
+
+impl<'a> DynamicSeed<'a> {
+    fn new(schema: &'a Schema, scopes: &'a Scopes<'a>, context: &'a Context) -> Self {
+        Self {
+            schema,
+            scopes,
+            path: None,
+            context,
+            depth: 0,
+        }
+    }
+
+    /// Seed for a schema nested within this one, at the same location
+    fn child(self, schema: &'a Schema) -> Self {
+        Self { schema, ..self }
+    }
+
+    /// Seed for a schema nested within this one, at the given location
+    fn nested<'b>(self, schema: &'b Schema, node: &'b PathNode<'b>) -> DynamicSeed<'b>
+    where
+        'a: 'b,
+    {
+        DynamicSeed {
+            schema,
+            path: Some(node),
+            ..self
         }
-        Schema::NewtypeStruct(name, schema) => {
-            let struct_ = deser.deserialize_tuple(1, TupleVisitor(vec![*schema]))?;
-            let DynamicValue::Tuple(mut tuple) = struct_ else { panic!() };
-            Ok(DynamicValue::NewtypeStruct(name, Box::new(tuple.remove(0))))
+    }
+
+    /// Step from this seed's location into one of its elements
+    fn step(&self, step: Step<'a>) -> PathNode<'a> {
+        PathNode {
+            parent: self.path,
+            step,
         }
-        Schema::Tuple(schema) => deser.deserialize_tuple(schema.len(), TupleVisitor(schema)),
-        Schema::TupleStruct(name, schema) => {
-            let tuple = deser.deserialize_tuple(schema.len(), TupleVisitor(schema))?;
-            let DynamicValue::Tuple(tuple) = tuple else { panic!() };
-            Ok(DynamicValue::TupleStruct(name, tuple))
+    }
+
+    fn path(&self) -> ValuePath {
+        let mut segments = vec![];
+        let mut node = self.path;
+        while let Some(PathNode { parent, step }) = node {
+            segments.push(match step {
+                Step::Field(name) => PathSegment::Field(name.to_string()),
+                Step::Index(idx) => PathSegment::Index(*idx),
+                Step::Key(idx) => PathSegment::Key(*idx),
+            });
+            node = *parent;
+        }
+
+        segments.reverse();
+        ValuePath(segments)
+    }
+
+    /// Raise an error at this seed's location
+    fn error<E: de::Error>(&self, error: KobbleError) -> E {
+        let error = error.at(self.path());
+        let e = E::custom(&error);
+        self.context.error.borrow_mut().get_or_insert(error);
+        e
+    }
+
+    /// Attach this seed's location to an error raised by the underlying format, unless a more
+    /// deeply nested seed has already done so
+    fn locate<E: de::Error>(&self, e: E) -> E {
+        let mut error = self.context.error.borrow_mut();
+        if error.is_some() {
+            return e;
         }
-        Schema::UnitStruct(name) => Ok(DynamicValue::UnitStruct(name)),
-        Schema::Enum(schema) => {
-            // Make field names static so serde is happy
-            let variant_names: Vec<&'static str> = schema
-                .variants
-                .iter()
-                .map(|name| string_to_static(name.clone()))
-                .collect();
-            let variant_names: &'static [&'static str] =
-                Box::leak(variant_names.into_boxed_slice());
-
-            deser.deserialize_enum(
-                string_to_static(schema.name.clone()),
-                variant_names,
-                EnumVisitor(schema),
-            )
+
+        let located = KobbleError::Custom {
+            path: self.path(),
+            message: e.to_string(),
+        };
+        let e = E::custom(&located);
+        *error = Some(located);
+        e
+    }
+
+    fn deserialize_unlocated<'de, D>(self, deser: D) -> Result<DynamicValue, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        match self.schema {
+            Schema::UniformSequence(schema) => {
+                deser.deserialize_seq(UniformSequenceVisitor(self.child(schema)))
+            }
+            Schema::Map { key, value } => {
+                deser.deserialize_map(MapVisitor(self.child(key), self.child(value)))
+            }
+            Schema::Struct(schema) => {
+                let name = string_to_static(schema.name.clone());
+                let field_names = static_field_names(schema);
+
+                // Deserialize the struct
+                let fields =
+                    deser.deserialize_struct(name, field_names, StructVisitor(self, schema))?;
+                Ok(DynamicValue::Struct {
+                    name: schema.name.clone(),
+                    fields,
+                })
+            }
+            Schema::NewtypeStruct(name, schema) => {
+                let mut tuple =
+                    deser.deserialize_tuple(1, TupleVisitor(self, std::slice::from_ref(schema)))?;
+                Ok(DynamicValue::NewtypeStruct(
+                    name.clone(),
+                    Box::new(tuple.remove(0)),
+                ))
+            }
+            Schema::Tuple(schema) => Ok(DynamicValue::Tuple(
+                deser.deserialize_tuple(schema.len(), TupleVisitor(self, schema))?,
+            )),
+            Schema::TupleStruct(name, schema) => {
+                let tuple = deser.deserialize_tuple(schema.len(), TupleVisitor(self, schema))?;
+                Ok(DynamicValue::TupleStruct(name.clone(), tuple))
+            }
+            Schema::UnitStruct(name) => Ok(DynamicValue::UnitStruct(name.clone())),
+            Schema::Option(schema) => deser.deserialize_option(OptionVisitor(self.child(schema))),
+            Schema::Enum(schema) => {
+                // Make variant names static so serde is happy
+                let variant_names =
+                    names_to_static(schema.variants.iter().map(|(name, _)| name.as_str()));
+
+                deser.deserialize_enum(
+                    string_to_static(schema.name.clone()),
+                    variant_names,
+                    EnumVisitor(self, schema),
+                )
+            }
+            Schema::U8 => Ok(DynamicValue::U8(u8::deserialize(deser)?)),
+            Schema::I8 => Ok(DynamicValue::I8(i8::deserialize(deser)?)),
+            Schema::U16 => Ok(DynamicValue::U16(u16::deserialize(deser)?)),
+            Schema::I16 => Ok(DynamicValue::I16(i16::deserialize(deser)?)),
+            Schema::U32 => Ok(DynamicValue::U32(u32::deserialize(deser)?)),
+            Schema::I32 => Ok(DynamicValue::I32(i32::deserialize(deser)?)),
+            Schema::U64 => Ok(DynamicValue::U64(u64::deserialize(deser)?)),
+            Schema::I64 => Ok(DynamicValue::I64(i64::deserialize(deser)?)),
+            Schema::U128 => Ok(DynamicValue::U128(u128::deserialize(deser)?)),
+            Schema::I128 => Ok(DynamicValue::I128(i128::deserialize(deser)?)),
+            Schema::F32 => Ok(DynamicValue::F32(f32::deserialize(deser)?)),
+            Schema::F64 => Ok(DynamicValue::F64(f64::deserialize(deser)?)),
+            Schema::Bool => Ok(DynamicValue::Bool(bool::deserialize(deser)?)),
+            Schema::Char => Ok(DynamicValue::Char(char::deserialize(deser)?)),
+            Schema::Unit => Ok(DynamicValue::Unit),
+            Schema::String => Ok(DynamicValue::String(String::deserialize(deser)?)),
+            Schema::Bytes => deser.deserialize_byte_buf(BytesVisitor),
+            Schema::Recursive { definitions, root } => {
+                let mut scopes = self.scopes.clone();
+                scopes.push(definitions);
+                DynamicSeed {
+                    scopes: &scopes,
+                    ..self.child(root)
+                }
+                .deserialize_unlocated(deser)
+            }
+            Schema::Ref(name) => {
+                if self
+                    .context
+                    .recursion_limit
+                    .is_some_and(|limit| self.depth >= limit)
+                {
+                    return Err(self.error(KobbleError::RecursionLimit {
+                        path: ValuePath::root(),
+                        name: name.clone(),
+                    }));
+                }
+
+                // References are only resolved once they are reached, as recursive types expand
+                // indefinitely
+                let schema = self.scopes.lookup(name).map_err(|e| self.error(e))?;
+                DynamicSeed {
+                    depth: self.depth + 1,
+                    ..self.child(schema)
+                }
+                .deserialize_unlocated(deser)
+            }
         }
-        Schema::U8 => Ok(DynamicValue::U8(u8::deserialize(deser)?)),
-        Schema::I8 => Ok(DynamicValue::I8(i8::deserialize(deser)?)),
-        Schema::U16 => Ok(DynamicValue::U16(u16::deserialize(deser)?)),
-        Schema::I16 => Ok(DynamicValue::I16(i16::deserialize(deser)?)),
-        Schema::U32 => Ok(DynamicValue::U32(u32::deserialize(deser)?)),
-        Schema::I32 => Ok(DynamicValue::I32(i32::deserialize(deser)?)),
-        Schema::U64 => Ok(DynamicValue::U64(u64::deserialize(deser)?)),
-        Schema::I64 => Ok(DynamicValue::I64(i64::deserialize(deser)?)),
-        Schema::U128 => Ok(DynamicValue::U128(u128::deserialize(deser)?)),
-        Schema::I128 => Ok(DynamicValue::I128(i128::deserialize(deser)?)),
-        Schema::F32 => Ok(DynamicValue::F32(f32::deserialize(deser)?)),
-        Schema::F64 => Ok(DynamicValue::F64(f64::deserialize(deser)?)),
-        Schema::Bool => Ok(DynamicValue::Bool(bool::deserialize(deser)?)),
-        Schema::Char => Ok(DynamicValue::Char(char::deserialize(deser)?)),
-        Schema::Unit => Ok(DynamicValue::Unit),
-        Schema::String => Ok(DynamicValue::String(String::deserialize(deser)?)),
     }
 }
 
+impl<'de> DeserializeSeed<'de> for DynamicSeed<'_> {
+    type Value = DynamicValue;
+
+    fn deserialize<D>(self, deser: D) -> Result<Self::Value, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        self.deserialize_unlocated(deser)
+            .map_err(|e| self.locate(e))
+    }
+}
+
+/// Make field names static so serde is happy
+fn static_field_names(schema: &StructSchema) -> &'static [&'static str] {
+    names_to_static(schema.fields.iter().map(|(name, _)| name.as_str()))
+}
+
 /// Visitor for uniform sequences (e.g. vectors)
-struct UniformSequenceVisitor(Schema);
+struct UniformSequenceVisitor<'a>(DynamicSeed<'a>);
 
-impl<'de> Visitor<'de> for UniformSequenceVisitor {
+impl<'de> Visitor<'de> for UniformSequenceVisitor<'_> {
     type Value = DynamicValue;
 
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
@@ -115,26 +322,130 @@ impl<'de> Visitor<'de> for UniformSequenceVisitor {
     where
         A: SeqAccess<'de>,
     {
+        let UniformSequenceVisitor(seed) = self;
         let mut values = vec![];
 
-        let UniformSequenceVisitor(schema) = self;
-
-        SchemaDeserializer::set_schema(schema.clone());
-        while let Some(SchemaDeserializer(dynamic)) = seq.next_element()? {
-            values.push(dynamic);
-            SchemaDeserializer::set_schema(schema.clone());
+        loop {
+            let node = seed.step(Step::Index(values.len()));
+            match seq.next_element_seed(seed.nested(seed.schema, &node))? {
+                Some(dynamic) => values.push(dynamic),
+                None => break,
+            }
         }
 
         Ok(DynamicValue::UniformSequence(values))
     }
 }
 
-/// Visitor for structs; converts a StructSchema into a DynamicValue under the given deserializer
-struct StructVisitor(StructSchema);
+/// Visitor for maps; entries are kept in the order they are visited
+struct MapVisitor<'a>(DynamicSeed<'a>, DynamicSeed<'a>);
 
-impl<'de> Visitor<'de> for StructVisitor {
+impl<'de> Visitor<'de> for MapVisitor<'_> {
     type Value = DynamicValue;
 
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("Map")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut entries = vec![];
+
+        let MapVisitor(key_seed, value_seed) = self;
+
+        loop {
+            let node = key_seed.step(Step::Key(entries.len()));
+            let Some(key) = map.next_key_seed(key_seed.nested(key_seed.schema, &node))? else {
+                break;
+            };
+            let node = value_seed.step(Step::Index(entries.len()));
+            let value = map.next_value_seed(value_seed.nested(value_seed.schema, &node))?;
+            entries.push((key, value));
+        }
+
+        Ok(DynamicValue::Map(entries))
+    }
+}
+
+/// Visitor for byte buffers
+struct BytesVisitor;
+
+impl<'de> Visitor<'de> for BytesVisitor {
+    type Value = DynamicValue;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("Bytes")
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(DynamicValue::Bytes(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(DynamicValue::Bytes(v))
+    }
+
+    // Formats without native byte buffers (e.g. JSON) represent them as sequences
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+
+        Ok(DynamicValue::Bytes(bytes))
+    }
+}
+
+/// Visitor for optional values
+struct OptionVisitor<'a>(DynamicSeed<'a>);
+
+impl<'de> Visitor<'de> for OptionVisitor<'_> {
+    type Value = DynamicValue;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("Option")
+    }
+
+    fn visit_none<E>(self) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(DynamicValue::Option(None))
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(DynamicValue::Option(None))
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let value = self.0.deserialize(deserializer)?;
+        Ok(DynamicValue::Option(Some(Box::new(value))))
+    }
+}
+
+/// Visitor for structs; converts a StructSchema into fields under the given deserializer
+struct StructVisitor<'a>(DynamicSeed<'a>, &'a StructSchema);
+
+impl<'de> Visitor<'de> for StructVisitor<'_> {
+    type Value = Vec<(String, DynamicValue)>;
+
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
         formatter.write_str("Struct")
     }
@@ -143,27 +454,31 @@ impl<'de> Visitor<'de> for StructVisitor {
     where
         A: SeqAccess<'de>,
     {
+        let StructVisitor(seed, schema) = self;
         let mut fields = vec![];
 
-        for (name, schema) in self.0.fields {
-            SchemaDeserializer::set_schema(schema);
-            let SchemaDeserializer(dynamic) = seq.next_element()?.expect("Schema mismatch");
-
-            fields.push((name, dynamic));
+        for (name, field_schema) in &schema.fields {
+            let node = seed.step(Step::Field(name));
+            let Some(dynamic) = seq.next_element_seed(seed.nested(field_schema, &node))? else {
+                return Err(seed.error(KobbleError::UnexpectedEndOfSequence {
+                    path: ValuePath::root(),
+                    expected: schema.fields.len(),
+                    found: fields.len(),
+                }));
+            };
+
+            fields.push((name.clone(), dynamic));
         }
 
-        Ok(DynamicValue::Struct {
-            name: self.0.name,
-            fields,
-        })
+        Ok(fields)
     }
 }
 
-/// Visitor for structs; converts a StructSchema into a DynamicValue under the given deserializer
-struct TupleVisitor(TupleSchema);
+/// Visitor for tuples; converts a TupleSchema into values under the given deserializer
+struct TupleVisitor<'a>(DynamicSeed<'a>, &'a [Schema]);
 
-impl<'de> Visitor<'de> for TupleVisitor {
-    type Value = DynamicValue;
+impl<'de> Visitor<'de> for TupleVisitor<'_> {
+    type Value = Vec<DynamicValue>;
 
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
         formatter.write_str("Tuple")
@@ -173,25 +488,30 @@ impl<'de> Visitor<'de> for TupleVisitor {
     where
         A: SeqAccess<'de>,
     {
+        let TupleVisitor(seed, schema) = self;
         let mut fields = vec![];
 
-        for schema in self.0 {
-            SchemaDeserializer::set_schema(schema);
-            let SchemaDeserializer(dynamic) = seq
-                .next_element::<SchemaDeserializer>()?
-                .expect("Schema mismatch");
+        for element_schema in schema {
+            let node = seed.step(Step::Index(fields.len()));
+            let Some(dynamic) = seq.next_element_seed(seed.nested(element_schema, &node))? else {
+                return Err(seed.error(KobbleError::UnexpectedEndOfSequence {
+                    path: ValuePath::root(),
+                    expected: schema.len(),
+                    found: fields.len(),
+                }));
+            };
 
             fields.push(dynamic);
         }
 
-        Ok(DynamicValue::Tuple(fields))
+        Ok(fields)
     }
 }
 
-/// Visitor for structs; converts a StructSchema into a DynamicValue under the given deserializer
-struct EnumVisitor(EnumSchema);
+/// Visitor for enums; converts an EnumSchema into a DynamicValue under the given deserializer
+struct EnumVisitor<'a>(DynamicSeed<'a>, &'a EnumSchema);
 
-impl<'de> Visitor<'de> for EnumVisitor {
+impl<'de> Visitor<'de> for EnumVisitor<'_> {
     type Value = DynamicValue;
 
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
@@ -200,9 +520,45 @@ impl<'de> Visitor<'de> for EnumVisitor {
 
     fn visit_enum<A>(self, data: A) -> Result<Self::Value, A::Error>
     where
-        A: serde::de::EnumAccess<'de>,
+        A: EnumAccess<'de>,
     {
-        let (v, _) = data.variant()?;
-        Ok(DynamicValue::Enum(self.0, v))
+        let EnumVisitor(seed, schema) = self;
+        let (index, access): (u32, _) = data.variant()?;
+
+        let Some((variant, variant_schema)) = schema.variants.get(index as usize) else {
+            return Err(seed.error(KobbleError::InvalidEnumIndex {
+                path: ValuePath::root(),
+                name: schema.name.clone(),
+                index,
+            }));
+        };
+
+        let value = match variant_schema {
+            VariantSchema::Unit => {
+                access.unit_variant()?;
+                VariantValue::Unit
+            }
+            VariantSchema::Newtype(schema) => {
+                let node = seed.step(Step::Index(0));
+                let dynamic = access.newtype_variant_seed(seed.nested(schema, &node))?;
+                VariantValue::Newtype(Box::new(dynamic))
+            }
+            VariantSchema::Tuple(schema) => {
+                VariantValue::Tuple(access.tuple_variant(schema.len(), TupleVisitor(seed, schema))?)
+            }
+            VariantSchema::Struct(schema) => {
+                let field_names = static_field_names(schema);
+                VariantValue::Struct(
+                    access.struct_variant(field_names, StructVisitor(seed, schema))?,
+                )
+            }
+        };
+
+        Ok(DynamicValue::Enum {
+            name: schema.name.clone(),
+            variant: variant.clone(),
+            index,
+            value,
+        })
     }
 }
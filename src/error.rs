@@ -2,29 +2,214 @@ use serde::{de, ser};
 
 use std::fmt::{self, Display};
 
-// TODO: Make this more descriptive
-#[derive(Debug)]
-pub struct GenericError(pub String);
+use crate::path::{PathSegment, ValuePath};
 
-impl ser::Error for GenericError {
-    // Don't ask... This stuff just exists to make the compiler happy.
+/// Errors produced by Kobble. Each error records where in the value (or schema) it occurred.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KobbleError {
+    /// The type cannot be described by a schema
+    Unsupported { path: ValuePath, what: String },
+    /// The data does not have the shape described by the schema
+    SchemaMismatch {
+        path: ValuePath,
+        expected: String,
+        found: String,
+    },
+    /// A sequence ended before all of the elements described by the schema were found
+    UnexpectedEndOfSequence {
+        path: ValuePath,
+        expected: usize,
+        found: usize,
+    },
+    /// An enum variant index with no corresponding variant in the schema
+    InvalidEnumIndex {
+        path: ValuePath,
+        name: String,
+        index: u32,
+    },
+    /// A reference to a type which is not among the definitions of the schema
+    UndefinedType { path: ValuePath, name: String },
+    /// A recursive type nested deeper than Kobble is willing to go
+    RecursionLimit { path: ValuePath, name: String },
+    /// A recursive type with no finite value, e.g. `struct A { b: Box<A> }`
+    NoFiniteInstance { path: ValuePath, name: String },
+    /// Schema inference could not reach every variant of an enum
+    UndiscoveredVariants { path: ValuePath, name: String },
+    /// An ID which was never assigned to a schema
+    UnknownSchema { path: ValuePath, id: u32 },
+    /// There is no value at the path
+    PathNotFound { path: ValuePath },
+    /// Text which could not be parsed as a ValuePath
+    InvalidPath { path: ValuePath, text: String },
+    /// Reading or writing failed
+    Io { path: ValuePath, message: String },
+    /// Data which is not in the expected format, e.g. a file which is not a Kobble container
+    InvalidFormat { path: ValuePath, message: String },
+    /// A value which does not conform to its schema, with every mismatch found
+    InvalidValue {
+        path: ValuePath,
+        errors: Vec<KobbleError>,
+    },
+    /// Any other error, e.g. from serde
+    Custom { path: ValuePath, message: String },
+}
+
+impl KobbleError {
+    pub fn custom(message: impl Into<String>) -> Self {
+        Self::Custom {
+            path: ValuePath::root(),
+            message: message.into(),
+        }
+    }
+
+    /// Where the error occurred
+    pub fn path(&self) -> &ValuePath {
+        match self {
+            Self::Unsupported { path, .. }
+            | Self::SchemaMismatch { path, .. }
+            | Self::UnexpectedEndOfSequence { path, .. }
+            | Self::InvalidEnumIndex { path, .. }
+            | Self::UndefinedType { path, .. }
+            | Self::RecursionLimit { path, .. }
+            | Self::NoFiniteInstance { path, .. }
+            | Self::UndiscoveredVariants { path, .. }
+            | Self::UnknownSchema { path, .. }
+            | Self::PathNotFound { path }
+            | Self::InvalidPath { path, .. }
+            | Self::Io { path, .. }
+            | Self::InvalidFormat { path, .. }
+            | Self::InvalidValue { path, .. }
+            | Self::Custom { path, .. } => path,
+        }
+    }
+
+    fn path_mut(&mut self) -> &mut ValuePath {
+        match self {
+            Self::Unsupported { path, .. }
+            | Self::SchemaMismatch { path, .. }
+            | Self::UnexpectedEndOfSequence { path, .. }
+            | Self::InvalidEnumIndex { path, .. }
+            | Self::UndefinedType { path, .. }
+            | Self::RecursionLimit { path, .. }
+            | Self::NoFiniteInstance { path, .. }
+            | Self::UndiscoveredVariants { path, .. }
+            | Self::UnknownSchema { path, .. }
+            | Self::PathNotFound { path }
+            | Self::InvalidPath { path, .. }
+            | Self::Io { path, .. }
+            | Self::InvalidFormat { path, .. }
+            | Self::InvalidValue { path, .. }
+            | Self::Custom { path, .. } => path,
+        }
+    }
+
+    /// Add a step to the front of the path, as the error makes its way out of a nested value
+    pub(crate) fn prefixed(mut self, segment: PathSegment) -> Self {
+        self.path_mut().0.insert(0, segment);
+        self
+    }
+
+    /// Replace the path of the error
+    pub(crate) fn at(mut self, path: ValuePath) -> Self {
+        *self.path_mut() = path;
+        self
+    }
+}
+
+impl From<std::io::Error> for KobbleError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io {
+            path: ValuePath::root(),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<bincode::Error> for KobbleError {
+    fn from(error: bincode::Error) -> Self {
+        match *error {
+            bincode::ErrorKind::Io(error) => error.into(),
+            error => Self::custom(error.to_string()),
+        }
+    }
+}
+
+impl ser::Error for KobbleError {
     fn custom<T: Display>(msg: T) -> Self {
-        Self(msg.to_string())
+        Self::custom(msg.to_string())
     }
 }
 
-impl de::Error for GenericError {
-    // Don't ask... This stuff just exists to make the compiler happy.
+impl de::Error for KobbleError {
     fn custom<T: Display>(msg: T) -> Self {
-        Self(msg.to_string())
+        Self::custom(msg.to_string())
+    }
+
+    fn invalid_type(unexp: de::Unexpected, exp: &dyn de::Expected) -> Self {
+        Self::SchemaMismatch {
+            path: ValuePath::root(),
+            expected: exp.to_string(),
+            found: unexp.to_string(),
+        }
+    }
+
+    fn invalid_value(unexp: de::Unexpected, exp: &dyn de::Expected) -> Self {
+        Self::SchemaMismatch {
+            path: ValuePath::root(),
+            expected: exp.to_string(),
+            found: unexp.to_string(),
+        }
     }
 }
 
-impl Display for GenericError {
-    // Don't ask... This stuff just exists to make the compiler happy.
+impl Display for KobbleError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_str(&self.0)
+        match self {
+            Self::Unsupported { what, .. } => write!(f, "Unsupported: {}", what)?,
+            Self::SchemaMismatch {
+                expected, found, ..
+            } => write!(f, "Schema mismatch: expected {}, found {}", expected, found)?,
+            Self::UnexpectedEndOfSequence {
+                expected, found, ..
+            } => write!(
+                f,
+                "Unexpected end of sequence: expected {} elements, found {}",
+                expected, found
+            )?,
+            Self::InvalidEnumIndex { name, index, .. } => {
+                write!(f, "Invalid variant index {} for enum {}", index, name)?
+            }
+            Self::UndefinedType { name, .. } => write!(f, "Undefined type {}", name)?,
+            Self::RecursionLimit { name, .. } => {
+                write!(f, "Recursion limit reached in type {}", name)?
+            }
+            Self::NoFiniteInstance { name, .. } => {
+                write!(f, "Type {} has no finite instance", name)?
+            }
+            Self::UndiscoveredVariants { name, .. } => {
+                write!(f, "Failed to discover every variant of enum {}", name)?
+            }
+            Self::UnknownSchema { id, .. } => write!(f, "Unknown schema ID {}", id)?,
+            Self::PathNotFound { .. } => f.write_str("No such value")?,
+            Self::InvalidPath { text, .. } => write!(f, "Invalid path `{}`", text)?,
+            Self::Io { message, .. } => write!(f, "I/O error: {}", message)?,
+            Self::InvalidFormat { message, .. } => write!(f, "Invalid format: {}", message)?,
+            Self::InvalidValue { errors, .. } => {
+                f.write_str("Invalid value:")?;
+                for (i, error) in errors.iter().enumerate() {
+                    let separator = if i == 0 { " " } else { "; " };
+                    write!(f, "{}{}", separator, error)?;
+                }
+            }
+            Self::Custom { message, .. } => f.write_str(message)?,
+        }
+
+        if !self.path().is_root() {
+            write!(f, " (at {})", self.path())?;
+        }
+
+        Ok(())
     }
 }
 
-impl std::error::Error for GenericError {}
+impl std::error::Error for KobbleError {}
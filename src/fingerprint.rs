@@ -0,0 +1,207 @@
+use sha2::{Digest, Sha256};
+
+use crate::{Schema, StructSchema, VariantSchema};
+
+/// Written before the encoding of every schema. Changing the encoding in any way changes
+/// every fingerprint, and must come with a new version here.
+const ENCODING_VERSION: &[u8] = b"kobble-schema-v1";
+
+impl Schema {
+    /// SHA-256 of a canonical encoding of the schema. Unlike the derived `Hash`, this is the
+    /// same on every platform and with every version of Rust and Kobble, so it may be stored or
+    /// sent in place of the schema itself.
+    pub fn fingerprint(&self) -> [u8; 32] {
+        let mut bytes = ENCODING_VERSION.to_vec();
+        encode(self, &mut bytes);
+        Sha256::digest(&bytes).into()
+    }
+
+    /// The first 8 bytes of the fingerprint
+    pub fn fingerprint_u64(&self) -> u64 {
+        let fingerprint = self.fingerprint();
+        u64::from_le_bytes(fingerprint[..8].try_into().unwrap())
+    }
+
+    /// The first 16 bytes of the fingerprint
+    pub fn fingerprint_u128(&self) -> u128 {
+        let fingerprint = self.fingerprint();
+        u128::from_le_bytes(fingerprint[..16].try_into().unwrap())
+    }
+}
+
+fn encode_len(len: usize, out: &mut Vec<u8>) {
+    out.extend_from_slice(&(len as u64).to_le_bytes());
+}
+
+fn encode_str(s: &str, out: &mut Vec<u8>) {
+    encode_len(s.len(), out);
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn encode_all(schemas: &[Schema], out: &mut Vec<u8>) {
+    encode_len(schemas.len(), out);
+    for schema in schemas {
+        encode(schema, out);
+    }
+}
+
+fn encode_struct(schema: &StructSchema, out: &mut Vec<u8>) {
+    encode_str(&schema.name, out);
+    encode_len(schema.fields.len(), out);
+    for (name, field) in &schema.fields {
+        encode_str(name, out);
+        encode(field, out);
+    }
+}
+
+/// Each kind of schema is written as a fixed tag, followed by its contents. Lengths are
+/// little-endian u64s.
+fn encode(schema: &Schema, out: &mut Vec<u8>) {
+    match schema {
+        Schema::I8 => out.push(0),
+        Schema::U8 => out.push(1),
+        Schema::I16 => out.push(2),
+        Schema::U16 => out.push(3),
+        Schema::I32 => out.push(4),
+        Schema::U32 => out.push(5),
+        Schema::I64 => out.push(6),
+        Schema::U64 => out.push(7),
+        Schema::I128 => out.push(8),
+        Schema::U128 => out.push(9),
+        Schema::F32 => out.push(10),
+        Schema::F64 => out.push(11),
+        Schema::Bool => out.push(12),
+        Schema::Char => out.push(13),
+        Schema::Unit => out.push(14),
+        Schema::Bytes => out.push(15),
+        Schema::String => out.push(16),
+        Schema::Option(inner) => {
+            out.push(17);
+            encode(inner, out);
+        }
+        Schema::Struct(s) => {
+            out.push(18);
+            encode_struct(s, out);
+        }
+        Schema::Tuple(fields) => {
+            out.push(19);
+            encode_all(fields, out);
+        }
+        Schema::TupleStruct(name, fields) => {
+            out.push(20);
+            encode_str(name, out);
+            encode_all(fields, out);
+        }
+        Schema::NewtypeStruct(name, inner) => {
+            out.push(21);
+            encode_str(name, out);
+            encode(inner, out);
+        }
+        Schema::UnitStruct(name) => {
+            out.push(22);
+            encode_str(name, out);
+        }
+        Schema::UniformSequence(inner) => {
+            out.push(23);
+            encode(inner, out);
+        }
+        Schema::Map { key, value } => {
+            out.push(24);
+            encode(key, out);
+            encode(value, out);
+        }
+        Schema::Enum(e) => {
+            out.push(25);
+            encode_str(&e.name, out);
+            encode_len(e.variants.len(), out);
+            for (name, variant) in &e.variants {
+                encode_str(name, out);
+                match variant {
+                    VariantSchema::Struct(s) => {
+                        out.push(0);
+                        encode_struct(s, out);
+                    }
+                    VariantSchema::Tuple(fields) => {
+                        out.push(1);
+                        encode_all(fields, out);
+                    }
+                    VariantSchema::Newtype(inner) => {
+                        out.push(2);
+                        encode(inner, out);
+                    }
+                    VariantSchema::Unit => out.push(3),
+                }
+            }
+        }
+        Schema::Ref(name) => {
+            out.push(26);
+            encode_str(name, out);
+        }
+        Schema::Recursive { definitions, root } => {
+            out.push(27);
+            // Definitions are kept sorted by name
+            encode_len(definitions.len(), out);
+            for (name, definition) in definitions {
+                encode_str(name, out);
+                encode(definition, out);
+            }
+            encode(root, out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Schema, StructSchema};
+    use serde::{Deserialize, Serialize};
+
+    #[test]
+    fn test_fingerprint_is_stable() {
+        let schema = Schema::Struct(StructSchema {
+            name: "Person".into(),
+            fields: vec![("age".into(), Schema::U64), ("name".into(), Schema::String)],
+        });
+
+        // Must never change, or stored fingerprints will stop matching
+        let hex: String = schema
+            .fingerprint()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        assert_eq!(
+            hex,
+            "598c8f421d61b95aad07faa7fdd9b29d15a113f2090f59ea6a99840b6134adbd"
+        );
+    }
+
+    #[test]
+    fn test_fingerprint_distinguishes() {
+        #[derive(Serialize, Deserialize)]
+        struct A {
+            b: Vec<(u8, String)>,
+            c: Option<C>,
+        }
+
+        #[derive(Serialize, Deserialize)]
+        enum C {
+            D,
+            E(Box<C>),
+        }
+
+        let schema = Schema::infer::<A>();
+        assert_eq!(schema.fingerprint(), Schema::infer::<A>().fingerprint());
+        assert_eq!(schema.fingerprint(), schema.clone().fingerprint());
+
+        // Nesting is not confused with sequencing
+        let flat = Schema::Tuple(vec![Schema::Tuple(vec![]), Schema::U8]);
+        let nested = Schema::Tuple(vec![Schema::Tuple(vec![Schema::U8])]);
+        assert_ne!(flat.fingerprint(), nested.fingerprint());
+
+        let renamed = Schema::UnitStruct("A".into());
+        assert_ne!(
+            renamed.fingerprint(),
+            Schema::UnitStruct("B".into()).fingerprint()
+        );
+        assert_ne!(schema.fingerprint_u128(), renamed.fingerprint_u128());
+    }
+}
@@ -0,0 +1,88 @@
+//! Types shared by the tests of the text format, the schema language and the code generators
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub(crate) enum Gender {
+    Truck,
+    Sedan,
+    Motorcycle,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub(crate) struct Person {
+    pub(crate) gender: Gender,
+    pub(crate) age: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub(crate) struct Meters(pub(crate) f32);
+
+// Compared by bits, so that it can be a map key
+impl Eq for Meters {}
+
+impl Hash for Meters {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub(crate) struct Point(pub(crate) i8, pub(crate) i8);
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub(crate) struct Marker;
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub(crate) enum Shape {
+    Circle { radius: Meters, center: Point },
+    Polygon(Vec<(i8, i8)>),
+    Pair(u16, (bool, char)),
+    Named(String),
+    Empty,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub(crate) struct Wrapper<T> {
+    pub(crate) inner: T,
+}
+
+/// Has every kind of type
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub(crate) struct Everything {
+    shapes: Vec<Shape>,
+    tags: HashMap<String, Option<char>>,
+    #[serde(with = "serde_bytes")]
+    blob: Vec<u8>,
+    big: (u128, i128),
+    unit: (),
+    marker: Marker,
+    ratio: f64,
+    visible: bool,
+}
+
+pub(crate) fn everything() -> Everything {
+    Everything {
+        shapes: vec![
+            Shape::Circle {
+                radius: Meters(1.5),
+                center: Point(-1, 2),
+            },
+            Shape::Polygon(vec![(0, 0), (-128, 127)]),
+            Shape::Pair(7, (true, 'é')),
+            Shape::Named("say \"hi\"\n\u{1f980}".into()),
+            Shape::Empty,
+        ],
+        tags: [("a".to_string(), Some('\'')), ("b".to_string(), None)]
+            .into_iter()
+            .collect(),
+        blob: vec![0, b'k', 0xff, b'"'],
+        big: (u128::MAX, i128::MIN),
+        unit: (),
+        marker: Marker,
+        ratio: -1e-30,
+        visible: true,
+    }
+}
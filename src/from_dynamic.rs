@@ -0,0 +1,473 @@
+use crate::error::KobbleError;
+use crate::path::PathSegment;
+use crate::{DynamicValue, VariantValue};
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::forward_to_deserialize_any;
+
+/// Convert a DynamicValue back into a concrete type
+pub fn from_dynamic<T: DeserializeOwned>(value: &DynamicValue) -> Result<T, KobbleError> {
+    T::deserialize(value)
+}
+
+impl VariantValue {
+    /// Short description of the shape of this variant, for error messages
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            Self::Struct(_) => "struct variant",
+            Self::Tuple(_) => "tuple variant",
+            Self::Newtype(_) => "newtype variant",
+            Self::Unit => "unit variant",
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &DynamicValue {
+    type Error = KobbleError;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            DynamicValue::I8(v) => visitor.visit_i8(*v),
+            DynamicValue::U8(v) => visitor.visit_u8(*v),
+            DynamicValue::I16(v) => visitor.visit_i16(*v),
+            DynamicValue::U16(v) => visitor.visit_u16(*v),
+            DynamicValue::I32(v) => visitor.visit_i32(*v),
+            DynamicValue::U32(v) => visitor.visit_u32(*v),
+            DynamicValue::I64(v) => visitor.visit_i64(*v),
+            DynamicValue::U64(v) => visitor.visit_u64(*v),
+            DynamicValue::I128(v) => visitor.visit_i128(*v),
+            DynamicValue::U128(v) => visitor.visit_u128(*v),
+            DynamicValue::F32(v) => visitor.visit_f32(*v),
+            DynamicValue::F64(v) => visitor.visit_f64(*v),
+            DynamicValue::Bool(v) => visitor.visit_bool(*v),
+            DynamicValue::Char(v) => visitor.visit_char(*v),
+            DynamicValue::Unit | DynamicValue::UnitStruct(_) => visitor.visit_unit(),
+            DynamicValue::Bytes(v) => visitor.visit_bytes(v),
+            DynamicValue::String(v) => visitor.visit_str(v),
+            DynamicValue::Option(None) => visitor.visit_none(),
+            DynamicValue::Option(Some(v)) => visitor.visit_some(&**v),
+            DynamicValue::NewtypeStruct(_, v) => visitor
+                .visit_newtype_struct(&**v)
+                .map_err(|e| e.prefixed(PathSegment::Index(0))),
+            DynamicValue::TupleStruct(_, values)
+            | DynamicValue::Tuple(values)
+            | DynamicValue::UniformSequence(values) => visit_seq(values, visitor),
+            DynamicValue::Struct { fields, .. } => visit_fields(fields, visitor),
+            DynamicValue::Map(entries) => visitor.visit_map(MapAccess {
+                entries: entries.iter(),
+                index: 0,
+                value: None,
+            }),
+            DynamicValue::Enum { variant, value, .. } => {
+                visitor.visit_enum(EnumAccess { variant, value })
+            }
+        }
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            // Types like Vec<u8> ask for a sequence, even if the data was recorded as bytes
+            DynamicValue::Bytes(bytes) => {
+                let values: Vec<DynamicValue> =
+                    bytes.iter().copied().map(DynamicValue::U8).collect();
+                visit_seq(&values, visitor)
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de> de::Deserializer<'de> for DynamicValue {
+    type Error = KobbleError;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        (&self).deserialize_any(visitor)
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        (&self).deserialize_seq(visitor)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+/// Hand a list of values to a visitor, making sure all of them were used
+fn visit_seq<'de, V: Visitor<'de>>(
+    values: &[DynamicValue],
+    visitor: V,
+) -> Result<V::Value, KobbleError> {
+    let mut access = SeqAccess {
+        values: values.iter(),
+        index: 0,
+    };
+
+    let result = visitor.visit_seq(&mut access)?;
+    if access.values.len() > 0 {
+        return Err(de::Error::invalid_length(
+            values.len(),
+            &format!("{} elements", access.index).as_str(),
+        ));
+    }
+
+    Ok(result)
+}
+
+/// Hand the fields of a struct or struct variant to a visitor, as a map keyed by field name
+fn visit_fields<'de, V: Visitor<'de>>(
+    fields: &[(String, DynamicValue)],
+    visitor: V,
+) -> Result<V::Value, KobbleError> {
+    visitor.visit_map(FieldAccess {
+        fields: fields.iter(),
+        value: None,
+    })
+}
+
+struct SeqAccess<'a> {
+    values: std::slice::Iter<'a, DynamicValue>,
+    index: usize,
+}
+
+impl<'de, 'a> de::SeqAccess<'de> for SeqAccess<'a> {
+    type Error = KobbleError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        let Some(value) = self.values.next() else {
+            return Ok(None);
+        };
+
+        let index = self.index;
+        self.index += 1;
+        seed.deserialize(value)
+            .map(Some)
+            .map_err(|e| e.prefixed(PathSegment::Index(index)))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.values.len())
+    }
+}
+
+struct FieldAccess<'a> {
+    fields: std::slice::Iter<'a, (String, DynamicValue)>,
+    /// Field whose key has been visited, but not its value
+    value: Option<&'a (String, DynamicValue)>,
+}
+
+impl<'de, 'a> de::MapAccess<'de> for FieldAccess<'a> {
+    type Error = KobbleError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        let Some(field) = self.fields.next() else {
+            return Ok(None);
+        };
+
+        self.value = Some(field);
+        let key: de::value::StrDeserializer<KobbleError> = field.0.as_str().into_deserializer();
+        seed.deserialize(key).map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let (name, value) = self
+            .value
+            .take()
+            .ok_or_else(|| KobbleError::custom("next_value called before next_key"))?;
+
+        seed.deserialize(value)
+            .map_err(|e| e.prefixed(PathSegment::Field(name.clone())))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.fields.len())
+    }
+}
+
+struct MapAccess<'a> {
+    entries: std::slice::Iter<'a, (DynamicValue, DynamicValue)>,
+    index: usize,
+    /// Value of the entry whose key has been visited
+    value: Option<&'a DynamicValue>,
+}
+
+impl<'de, 'a> de::MapAccess<'de> for MapAccess<'a> {
+    type Error = KobbleError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        let Some((key, value)) = self.entries.next() else {
+            return Ok(None);
+        };
+
+        self.value = Some(value);
+        seed.deserialize(key)
+            .map(Some)
+            .map_err(|e| e.prefixed(PathSegment::Key(self.index)))
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let index = self.index;
+        self.index += 1;
+
+        let value = self
+            .value
+            .take()
+            .ok_or_else(|| KobbleError::custom("next_value called before next_key"))?;
+
+        seed.deserialize(value)
+            .map_err(|e| e.prefixed(PathSegment::Index(index)))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+struct EnumAccess<'a> {
+    variant: &'a str,
+    value: &'a VariantValue,
+}
+
+impl<'de, 'a> de::EnumAccess<'de> for EnumAccess<'a> {
+    type Error = KobbleError;
+    type Variant = &'a VariantValue;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        // Variants are matched by name, so that values survive reordering of the variants
+        let variant: de::value::StrDeserializer<KobbleError> = self.variant.into_deserializer();
+        Ok((seed.deserialize(variant)?, self.value))
+    }
+}
+
+/// Error for a variant which does not carry the kind of data the type expects
+fn variant_mismatch(expected: &str, found: &VariantValue) -> KobbleError {
+    KobbleError::SchemaMismatch {
+        path: Default::default(),
+        expected: expected.to_string(),
+        found: found.kind().to_string(),
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for &VariantValue {
+    type Error = KobbleError;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        match self {
+            VariantValue::Unit => Ok(()),
+            other => Err(variant_mismatch("unit variant", other)),
+        }
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        match self {
+            VariantValue::Newtype(value) => seed
+                .deserialize(&**value)
+                .map_err(|e| e.prefixed(PathSegment::Index(0))),
+            other => Err(variant_mismatch("newtype variant", other)),
+        }
+    }
+
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            VariantValue::Tuple(values) => visit_seq(values, visitor),
+            other => Err(variant_mismatch("tuple variant", other)),
+        }
+    }
+
+    fn struct_variant<V>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            VariantValue::Struct(fields) => visit_fields(fields, visitor),
+            other => Err(variant_mismatch("struct variant", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::from_dynamic;
+    use crate::{to_dynamic, DynamicValue, KobbleError, Schema, SchemaSeed, ValuePath};
+    use bincode::Options;
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Component {
+        name: String,
+        transform: glam::Mat4,
+        tags: BTreeMap<u16, Option<Tag>>,
+        shapes: Vec<Shape>,
+        id: Id,
+        unit: Marker,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Tag {
+        Hidden,
+        Layer(u8),
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Shape {
+        Circle { radius: f32 },
+        Polygon(Vec<(f32, f32)>, bool),
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Id(u64);
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Marker;
+
+    fn component() -> Component {
+        Component {
+            name: "Spoon".into(),
+            transform: glam::Mat4::IDENTITY,
+            tags: [(1, None), (5, Some(Tag::Layer(3))), (8, Some(Tag::Hidden))]
+                .into_iter()
+                .collect(),
+            shapes: vec![
+                Shape::Polygon(vec![(0., 1.), (2., 3.)], true),
+                Shape::Circle { radius: 4. },
+            ],
+            id: Id(99),
+            unit: Marker,
+        }
+    }
+
+    #[test]
+    fn test_from_to_dynamic() {
+        let dynamic = to_dynamic(&component()).unwrap();
+        assert_eq!(from_dynamic::<Component>(&dynamic).unwrap(), component());
+
+        // Owned values work too
+        assert_eq!(Component::deserialize(dynamic).unwrap(), component());
+    }
+
+    #[test]
+    fn test_from_decoded_bytes() {
+        let schema = Schema::infer::<Component>();
+        let bytes = bincode::serialize(&component()).unwrap();
+        let dynamic = bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .deserialize_seed(SchemaSeed::new(&schema), &bytes)
+            .unwrap();
+
+        assert_eq!(from_dynamic::<Component>(&dynamic).unwrap(), component());
+    }
+
+    #[test]
+    fn test_edited_fields() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Point {
+            x: i32,
+            y: i32,
+        }
+
+        // Fields are matched by name, regardless of order
+        let dynamic = DynamicValue::Struct {
+            name: "Point".into(),
+            fields: vec![
+                ("y".into(), DynamicValue::I32(2)),
+                ("x".into(), DynamicValue::I32(1)),
+            ],
+        };
+        assert_eq!(
+            from_dynamic::<Point>(&dynamic).unwrap(),
+            Point { x: 1, y: 2 }
+        );
+
+        // Bytes can become a Vec<u8>
+        let bytes = DynamicValue::Bytes(vec![1, 2, 3]);
+        assert_eq!(from_dynamic::<Vec<u8>>(&bytes).unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_mismatch_error_path() {
+        let mut dynamic = to_dynamic(&component()).unwrap();
+        let DynamicValue::Struct { fields, .. } = &mut dynamic else {
+            panic!()
+        };
+        let DynamicValue::UniformSequence(shapes) = &mut fields[3].1 else {
+            panic!()
+        };
+        shapes[1] = DynamicValue::String("Square".into());
+
+        let err = from_dynamic::<Component>(&dynamic).unwrap_err();
+        assert!(matches!(err, KobbleError::SchemaMismatch { .. }), "{}", err);
+        assert_eq!(err.path(), &ValuePath::root().field("shapes").index(1));
+    }
+
+    #[test]
+    fn test_variant_kind_mismatch() {
+        let dynamic = to_dynamic(&Tag::Hidden).unwrap();
+        let DynamicValue::Enum { name, index, .. } = dynamic else {
+            panic!()
+        };
+        let dynamic = DynamicValue::Enum {
+            name,
+            variant: "Layer".into(),
+            index,
+            value: crate::VariantValue::Unit,
+        };
+
+        let err = from_dynamic::<Tag>(&dynamic).unwrap_err();
+        assert_eq!(
+            err,
+            KobbleError::SchemaMismatch {
+                path: ValuePath::root(),
+                expected: "newtype variant".into(),
+                found: "unit variant".into(),
+            }
+        );
+    }
+}
@@ -0,0 +1,1036 @@
+//! Schema definition language, for writing schemas by hand:
+//!
+//! ```text
+//! struct Person { gender: Gender, age: u64 }
+//! enum Gender { Truck, Sedan, Motorcycle }
+//! ```
+//!
+//! A document is a list of declarations, the first of which is the schema described:
+//! * Structs `struct Name { field: Type, .. }`, tuple structs `struct Name(A, B);`, newtype
+//!   structs `struct Name(A);` and unit structs `struct Name;`. A tuple struct with a single
+//!   element is written with a trailing comma, `struct Name(A,);`
+//! * Enums `enum Name { Variant, .. }`, with the data of each variant written as for structs,
+//!   e.g. `Variant { field: Type }` or `Variant(A, B)`
+//! * Aliases `type Name = Type;`, which stand for the type they are given
+//!
+//! A declaration may start with `#[name = "Name"]`, giving the name of the type when it isn't the
+//! name declared. Schemas print this way when different types share a name, as `Wrapper<u8>` and
+//! `Wrapper<String>` do, declaring the second as `Wrapper_1`.
+//!
+//! Types are either declared ones, `()`, tuples `(A, B)`, `Option<T>`, `Vec<T>`, `Map<K, V>`,
+//! `String`, `Bytes`, `bool`, `char`, or the numbers `i8` to `u128`, `f32` and `f64`.
+//! Declarations may refer to each other in any order, and recursively. Semicolons and trailing
+//! commas are optional, and `//` comments are allowed.
+//!
+//! Schemas print in the same language with `Display`.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display, Write};
+
+use crate::error::KobbleError;
+use crate::path::ValuePath;
+use crate::scope::{reserve, Scopes};
+use crate::text::Parser;
+use crate::{Definitions, EnumSchema, Schema, StructSchema, VariantSchema};
+
+const BUILTINS: &[&str] = &[
+    "i8", "u8", "i16", "u16", "i32", "u32", "i64", "u64", "i128", "u128", "f32", "f64", "bool",
+    "char", "String", "Bytes", "Option", "Vec", "Map",
+];
+
+/// Parse a document, returning the schema of the type declared first
+pub fn parse(text: &str) -> Result<Schema, KobbleError> {
+    let document = Document::parse(text)?;
+    match document.order.first() {
+        Some(root) => document.resolve(root),
+        None => Err(document.error(0, "expected a declaration")),
+    }
+}
+
+/// Parse a document, returning the schema of the named type
+pub fn parse_type(text: &str, name: &str) -> Result<Schema, KobbleError> {
+    let document = Document::parse(text)?;
+    if !document.declarations.contains_key(name) {
+        return Err(KobbleError::UndefinedType {
+            path: ValuePath::root(),
+            name: name.to_string(),
+        });
+    }
+
+    document.resolve(name)
+}
+
+struct Declaration {
+    /// The declared type, referring to other declarations with `Schema::Ref`
+    schema: Schema,
+    alias: bool,
+    /// Where the name was declared
+    pos: usize,
+}
+
+struct Document<'a> {
+    text: &'a str,
+    declarations: HashMap<&'a str, Declaration>,
+    /// Names, in the order they were declared
+    order: Vec<&'a str>,
+}
+
+impl<'a> Document<'a> {
+    fn parse(text: &'a str) -> Result<Self, KobbleError> {
+        let mut p = Parser::new(text);
+        let mut declarations = HashMap::new();
+        let mut order = vec![];
+        // Every name used as a type, and where
+        let mut refs = vec![];
+
+        while !p.at_end() {
+            let (name, declaration) = declaration(&mut p, &mut refs)?;
+            if BUILTINS.contains(&name) {
+                p.pos = declaration.pos;
+                return Err(p.error(format_args!("{} is a built-in type", name)));
+            }
+            if declarations.contains_key(name) {
+                p.pos = declaration.pos;
+                return Err(p.error(format_args!("{} is declared twice", name)));
+            }
+
+            declarations.insert(name, declaration);
+            order.push(name);
+            p.eat(";");
+        }
+
+        for (name, pos) in refs {
+            if !declarations.contains_key(name) {
+                p.pos = pos;
+                return Err(p.error(format_args!("undefined type {}", name)));
+            }
+        }
+
+        Ok(Self {
+            text,
+            declarations,
+            order,
+        })
+    }
+
+    fn error(&self, pos: usize, message: impl Display) -> KobbleError {
+        let mut p = Parser::new(self.text);
+        p.pos = pos;
+        p.error(message)
+    }
+
+    /// Find the declared types which refer to themselves, walking from the named type as
+    /// `Schema::infer` does. A type counts only once it is reached again from within itself.
+    fn find_recursive<'s>(
+        &'s self,
+        name: &'s str,
+        stack: &mut Vec<&'s str>,
+        recursive: &mut HashSet<&'s str>,
+    ) {
+        if let Some(i) = stack.iter().position(|n| *n == name) {
+            if !self.declarations[name].alias {
+                recursive.insert(name);
+                return;
+            }
+            // Aliases are see-through, so keep going until the cycle reaches a declared type
+            if stack[i..].iter().all(|n| self.declarations[n].alias) {
+                return;
+            }
+        }
+
+        let mut names = vec![];
+        refs(&self.declarations[name].schema, &mut names);
+
+        stack.push(name);
+        for next in names {
+            self.find_recursive(next, stack, recursive);
+        }
+        stack.pop();
+    }
+
+    /// The schema of the named type, with every declaration it uses filled in. Types which refer
+    /// to themselves are moved into the definitions of a `Schema::Recursive`, as when inferred.
+    fn resolve(&self, name: &str) -> Result<Schema, KobbleError> {
+        let mut recursive = HashSet::new();
+        self.find_recursive(name, &mut vec![], &mut recursive);
+
+        let mut resolver = Resolver {
+            document: self,
+            recursive,
+            definitions: Definitions::new(),
+            aliases: vec![],
+        };
+        let root = resolver.expand(&Schema::Ref(name.to_string()))?;
+
+        Ok(if resolver.definitions.is_empty() {
+            root
+        } else {
+            Schema::Recursive {
+                definitions: resolver.definitions,
+                root: Box::new(root),
+            }
+        })
+    }
+}
+
+/// Names referred to by the schema
+fn refs<'s>(schema: &'s Schema, out: &mut Vec<&'s str>) {
+    match schema {
+        Schema::Ref(name) => out.push(name),
+        Schema::Option(inner)
+        | Schema::UniformSequence(inner)
+        | Schema::NewtypeStruct(_, inner) => refs(inner, out),
+        Schema::Tuple(schemas) | Schema::TupleStruct(_, schemas) => {
+            schemas.iter().for_each(|s| refs(s, out))
+        }
+        Schema::Map { key, value } => {
+            refs(key, out);
+            refs(value, out);
+        }
+        Schema::Struct(s) => s.fields.iter().for_each(|(_, s)| refs(s, out)),
+        Schema::Enum(e) => {
+            for (_, variant) in &e.variants {
+                match variant {
+                    VariantSchema::Struct(s) => s.fields.iter().for_each(|(_, s)| refs(s, out)),
+                    VariantSchema::Tuple(schemas) => schemas.iter().for_each(|s| refs(s, out)),
+                    VariantSchema::Newtype(inner) => refs(inner, out),
+                    VariantSchema::Unit => (),
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Fills in references to declarations
+struct Resolver<'d, 'a> {
+    document: &'d Document<'a>,
+    /// Declared types which refer to themselves
+    recursive: HashSet<&'d str>,
+    definitions: Definitions,
+    /// Aliases being expanded, innermost last
+    aliases: Vec<String>,
+}
+
+impl Resolver<'_, '_> {
+    fn expand(&mut self, schema: &Schema) -> Result<Schema, KobbleError> {
+        Ok(match schema {
+            Schema::Ref(name) => {
+                let declaration = &self.document.declarations[name.as_str()];
+                if declaration.alias {
+                    if self.aliases.contains(name) {
+                        return Err(self.document.error(
+                            declaration.pos,
+                            format_args!("type {} refers to itself", name),
+                        ));
+                    }
+
+                    self.aliases.push(name.clone());
+                    let schema = self.expand(&declaration.schema)?;
+                    self.aliases.pop();
+                    schema
+                } else if self.recursive.contains(name.as_str()) {
+                    if reserve(&mut self.definitions, name) {
+                        let aliases = std::mem::take(&mut self.aliases);
+                        let schema = self.expand(&declaration.schema)?;
+                        self.aliases = aliases;
+                        self.definitions.insert(name.clone(), schema);
+                    }
+
+                    Schema::Ref(name.clone())
+                } else {
+                    self.expand(&declaration.schema)?
+                }
+            }
+            Schema::Option(inner) => Schema::Option(Box::new(self.expand(inner)?)),
+            Schema::UniformSequence(inner) => {
+                Schema::UniformSequence(Box::new(self.expand(inner)?))
+            }
+            Schema::NewtypeStruct(name, inner) => {
+                Schema::NewtypeStruct(name.clone(), Box::new(self.expand(inner)?))
+            }
+            Schema::Tuple(schemas) => Schema::Tuple(self.expand_all(schemas)?),
+            Schema::TupleStruct(name, schemas) => {
+                Schema::TupleStruct(name.clone(), self.expand_all(schemas)?)
+            }
+            Schema::Map { key, value } => Schema::Map {
+                key: Box::new(self.expand(key)?),
+                value: Box::new(self.expand(value)?),
+            },
+            Schema::Struct(s) => Schema::Struct(self.expand_struct(s)?),
+            Schema::Enum(e) => {
+                let mut variants = vec![];
+                for (name, variant) in &e.variants {
+                    let variant = match variant {
+                        VariantSchema::Struct(s) => VariantSchema::Struct(self.expand_struct(s)?),
+                        VariantSchema::Tuple(schemas) => {
+                            VariantSchema::Tuple(self.expand_all(schemas)?)
+                        }
+                        VariantSchema::Newtype(inner) => {
+                            VariantSchema::Newtype(Box::new(self.expand(inner)?))
+                        }
+                        VariantSchema::Unit => VariantSchema::Unit,
+                    };
+                    variants.push((name.clone(), variant));
+                }
+
+                Schema::Enum(EnumSchema {
+                    name: e.name.clone(),
+                    variants,
+                })
+            }
+            other => other.clone(),
+        })
+    }
+
+    fn expand_all(&mut self, schemas: &[Schema]) -> Result<Vec<Schema>, KobbleError> {
+        schemas.iter().map(|s| self.expand(s)).collect()
+    }
+
+    fn expand_struct(&mut self, s: &StructSchema) -> Result<StructSchema, KobbleError> {
+        let mut fields = vec![];
+        for (name, schema) in &s.fields {
+            fields.push((name.clone(), self.expand(schema)?));
+        }
+
+        Ok(StructSchema {
+            name: s.name.clone(),
+            fields,
+        })
+    }
+}
+
+fn declaration<'a>(
+    p: &mut Parser<'a>,
+    refs: &mut Vec<(&'a str, usize)>,
+) -> Result<(&'a str, Declaration), KobbleError> {
+    let type_name = if p.eat("#[") {
+        p.expect("name")?;
+        p.expect("=")?;
+        let name = p.string()?;
+        p.expect("]")?;
+        Some(name)
+    } else {
+        None
+    };
+
+    p.skip_whitespace();
+    let start = p.pos;
+    let keyword = p.ident();
+    if !matches!(keyword, Some("struct" | "enum" | "type")) {
+        p.pos = start;
+        return Err(p.error("expected `struct`, `enum` or `type`"));
+    }
+    if keyword == Some("type") && type_name.is_some() {
+        p.pos = start;
+        return Err(p.error("an alias has no name of its own"));
+    }
+
+    p.skip_whitespace();
+    let pos = p.pos;
+    let declared = p.expect_ident("a type name")?;
+    let name = type_name.as_deref().unwrap_or(declared);
+
+    let schema = match keyword {
+        Some("struct") => {
+            if p.eat("{") {
+                Schema::Struct(StructSchema {
+                    name: name.to_string(),
+                    fields: fields(p, refs)?,
+                })
+            } else if p.eat("(") {
+                match elements(p, refs)? {
+                    (mut schemas, false) if schemas.len() == 1 => {
+                        Schema::NewtypeStruct(name.to_string(), Box::new(schemas.remove(0)))
+                    }
+                    (schemas, _) => Schema::TupleStruct(name.to_string(), schemas),
+                }
+            } else {
+                Schema::UnitStruct(name.to_string())
+            }
+        }
+        Some("enum") => {
+            p.expect("{")?;
+            let mut variants = vec![];
+            p.list("}", |p, _| {
+                let name = p.expect_ident("a variant")?;
+                let variant = if p.eat("{") {
+                    VariantSchema::Struct(StructSchema {
+                        name: name.to_string(),
+                        fields: fields(p, refs)?,
+                    })
+                } else if p.eat("(") {
+                    match elements(p, refs)? {
+                        (mut schemas, false) if schemas.len() == 1 => {
+                            VariantSchema::Newtype(Box::new(schemas.remove(0)))
+                        }
+                        (schemas, _) => VariantSchema::Tuple(schemas),
+                    }
+                } else {
+                    VariantSchema::Unit
+                };
+
+                variants.push((name.to_string(), variant));
+                Ok(())
+            })?;
+
+            Schema::Enum(EnumSchema {
+                name: name.to_string(),
+                variants,
+            })
+        }
+        _ => {
+            p.expect("=")?;
+            ty(p, refs)?
+        }
+    };
+
+    Ok((
+        declared,
+        Declaration {
+            alias: keyword == Some("type"),
+            schema,
+            pos,
+        },
+    ))
+}
+
+/// Parse the fields of a struct, after the opening brace
+fn fields<'a>(
+    p: &mut Parser<'a>,
+    refs: &mut Vec<(&'a str, usize)>,
+) -> Result<Vec<(String, Schema)>, KobbleError> {
+    let mut fields: Vec<(String, Schema)> = vec![];
+    p.list("}", |p, _| {
+        p.skip_whitespace();
+        let pos = p.pos;
+        let name = p.expect_ident("a field name")?;
+        if fields.iter().any(|(n, _)| n == name) {
+            p.pos = pos;
+            return Err(p.error(format_args!("field {} declared twice", name)));
+        }
+
+        p.expect(":")?;
+        fields.push((name.to_string(), ty(p, refs)?));
+        Ok(())
+    })?;
+
+    Ok(fields)
+}
+
+/// Parse a list of types, after the opening parenthesis, noting whether it ended in a comma
+fn elements<'a>(
+    p: &mut Parser<'a>,
+    refs: &mut Vec<(&'a str, usize)>,
+) -> Result<(Vec<Schema>, bool), KobbleError> {
+    let mut schemas = vec![];
+    let mut trailing = false;
+    while !p.eat(")") {
+        schemas.push(ty(p, refs)?);
+        trailing = p.eat(",");
+        if !trailing {
+            p.expect(")")?;
+            break;
+        }
+    }
+
+    Ok((schemas, trailing))
+}
+
+/// Parse `<T>`, the parameter of a generic type
+fn parameter<'a>(
+    p: &mut Parser<'a>,
+    refs: &mut Vec<(&'a str, usize)>,
+) -> Result<Box<Schema>, KobbleError> {
+    p.expect("<")?;
+    let schema = ty(p, refs)?;
+    p.expect(">")?;
+    Ok(Box::new(schema))
+}
+
+fn ty<'a>(p: &mut Parser<'a>, refs: &mut Vec<(&'a str, usize)>) -> Result<Schema, KobbleError> {
+    if p.eat("(") {
+        return Ok(match elements(p, refs)? {
+            (schemas, _) if schemas.is_empty() => Schema::Unit,
+            (mut schemas, false) if schemas.len() == 1 => schemas.remove(0),
+            (schemas, _) => Schema::Tuple(schemas),
+        });
+    }
+
+    p.skip_whitespace();
+    let pos = p.pos;
+    Ok(match p.expect_ident("a type")? {
+        "i8" => Schema::I8,
+        "u8" => Schema::U8,
+        "i16" => Schema::I16,
+        "u16" => Schema::U16,
+        "i32" => Schema::I32,
+        "u32" => Schema::U32,
+        "i64" => Schema::I64,
+        "u64" => Schema::U64,
+        "i128" => Schema::I128,
+        "u128" => Schema::U128,
+        "f32" => Schema::F32,
+        "f64" => Schema::F64,
+        "bool" => Schema::Bool,
+        "char" => Schema::Char,
+        "String" => Schema::String,
+        "Bytes" => Schema::Bytes,
+        "Option" => Schema::Option(parameter(p, refs)?),
+        "Vec" => Schema::UniformSequence(parameter(p, refs)?),
+        "Map" => {
+            p.expect("<")?;
+            let key = ty(p, refs)?;
+            p.expect(",")?;
+            let value = ty(p, refs)?;
+            p.eat(",");
+            p.expect(">")?;
+            Schema::Map {
+                key: Box::new(key),
+                value: Box::new(value),
+            }
+        }
+        name => {
+            refs.push((name, pos));
+            Schema::Ref(name.to_string())
+        }
+    })
+}
+
+impl Display for Schema {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let declarations = Declarations::of(self);
+        if let Some(root) = &declarations.root {
+            write!(f, "type {} = ", root)?;
+            write_type(&declarations.schema, f)?;
+            f.write_char(';')?;
+            if !declarations.types.is_empty() {
+                f.write_str("\n\n")?;
+            }
+        }
+
+        for (i, declaration) in declarations.types.iter().enumerate() {
+            if i > 0 {
+                f.write_str("\n\n")?;
+            }
+            write_declaration(declaration, f)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The types which must be declared to describe a schema
+pub(crate) struct Declarations {
+    /// Name given to the schema itself, if it isn't a declared type
+    pub(crate) root: Option<String>,
+    /// The schema, referring to each declared type with a `Schema::Ref` to its declaration
+    pub(crate) schema: Schema,
+    /// Every declared type used by the schema, in the order they are first used
+    pub(crate) types: Vec<DeclaredType>,
+}
+
+/// A type declared by its own name, or by a name made from it if another type has that name
+pub(crate) struct DeclaredType {
+    pub(crate) name: String,
+    /// The type, referring to other declarations as `Declarations::schema` does
+    pub(crate) schema: Schema,
+    /// Whether the type is defined by a `Schema::Recursive`, and so may contain itself
+    pub(crate) recursive: bool,
+}
+
+impl Declarations {
+    pub(crate) fn of(schema: &Schema) -> Self {
+        let mut collector = Collector::default();
+        let schema = collector.collect(schema);
+
+        let root = (!matches!(schema, Schema::Ref(_))).then(|| {
+            let mut name = "Root".to_string();
+            while collector.names.contains(&name) {
+                name.push('_');
+            }
+            name
+        });
+
+        Self {
+            root,
+            schema,
+            types: collector.declarations,
+        }
+    }
+
+    /// Declared types, by the name they are declared with
+    pub(crate) fn by_name(&self) -> HashMap<&str, &DeclaredType> {
+        self.types.iter().map(|t| (t.name.as_str(), t)).collect()
+    }
+}
+
+/// Finds every declared type used by a schema, in the order they are first used. Types are the
+/// same if their schemas are, and a type with the name of a different one is declared as
+/// `Name_1`, `Name_2` and so on.
+#[derive(Default)]
+struct Collector<'a> {
+    scopes: Scopes<'a>,
+    declarations: Vec<DeclaredType>,
+    /// Names declared so far
+    names: HashSet<String>,
+    /// Names declared for recursive types, by their definition
+    recursive: HashMap<*const Schema, String>,
+}
+
+impl<'a> Collector<'a> {
+    /// The schema, with the types it uses replaced by references to their declarations
+    fn collect(&mut self, schema: &'a Schema) -> Schema {
+        match schema {
+            Schema::Recursive { definitions, root } => {
+                self.scopes.push(definitions);
+                let root = self.collect(root);
+                self.scopes.pop();
+                root
+            }
+            Schema::Ref(name) => match self.scopes.find(name) {
+                Some((depth, definition)) => {
+                    Schema::Ref(self.declare_recursive(name, depth, definition))
+                }
+                None => schema.clone(),
+            },
+            _ if declared_name(schema).is_some() => Schema::Ref(self.declare(schema)),
+            _ => self.collect_within(schema),
+        }
+    }
+
+    /// The schema, with the types within it collected
+    fn collect_within(&mut self, schema: &'a Schema) -> Schema {
+        match schema {
+            Schema::Option(inner) => Schema::Option(Box::new(self.collect(inner))),
+            Schema::UniformSequence(inner) => {
+                Schema::UniformSequence(Box::new(self.collect(inner)))
+            }
+            Schema::NewtypeStruct(name, inner) => {
+                Schema::NewtypeStruct(name.clone(), Box::new(self.collect(inner)))
+            }
+            Schema::Tuple(schemas) => Schema::Tuple(self.collect_all(schemas)),
+            Schema::TupleStruct(name, schemas) => {
+                Schema::TupleStruct(name.clone(), self.collect_all(schemas))
+            }
+            Schema::Map { key, value } => Schema::Map {
+                key: Box::new(self.collect(key)),
+                value: Box::new(self.collect(value)),
+            },
+            Schema::Struct(s) => Schema::Struct(self.collect_struct(s)),
+            Schema::Enum(e) => {
+                let variants = e
+                    .variants
+                    .iter()
+                    .map(|(name, variant)| {
+                        let variant = match variant {
+                            VariantSchema::Struct(s) => {
+                                VariantSchema::Struct(self.collect_struct(s))
+                            }
+                            VariantSchema::Tuple(schemas) => {
+                                VariantSchema::Tuple(self.collect_all(schemas))
+                            }
+                            VariantSchema::Newtype(inner) => {
+                                VariantSchema::Newtype(Box::new(self.collect(inner)))
+                            }
+                            VariantSchema::Unit => VariantSchema::Unit,
+                        };
+                        (name.clone(), variant)
+                    })
+                    .collect();
+
+                Schema::Enum(EnumSchema {
+                    name: e.name.clone(),
+                    variants,
+                })
+            }
+            other => other.clone(),
+        }
+    }
+
+    fn collect_all(&mut self, schemas: &'a [Schema]) -> Vec<Schema> {
+        schemas.iter().map(|s| self.collect(s)).collect()
+    }
+
+    fn collect_struct(&mut self, s: &'a StructSchema) -> StructSchema {
+        StructSchema {
+            name: s.name.clone(),
+            fields: s
+                .fields
+                .iter()
+                .map(|(name, schema)| (name.clone(), self.collect(schema)))
+                .collect(),
+        }
+    }
+
+    /// Take a name which isn't declared yet, the given one if possible
+    fn take_name(&mut self, name: &str) -> String {
+        let mut unique = name.to_string();
+        let mut n = 0;
+        while self.names.contains(&unique) {
+            n += 1;
+            unique = format!("{}_{}", name, n);
+        }
+
+        self.names.insert(unique.clone());
+        unique
+    }
+
+    /// Declare a type, unless the same one is declared already, returning its name
+    fn declare(&mut self, schema: &'a Schema) -> String {
+        // Keep the place of the type ahead of the types it uses
+        let slot = self.declarations.len();
+        self.declarations.push(DeclaredType {
+            name: String::new(),
+            schema: Schema::Unit,
+            recursive: false,
+        });
+
+        let schema = self.collect_within(schema);
+        let existing = self
+            .declarations
+            .iter()
+            .find(|d| !d.recursive && d.schema == schema);
+        if let Some(declaration) = existing {
+            let name = declaration.name.clone();
+            self.declarations.remove(slot);
+            return name;
+        }
+
+        let name = self.take_name(declared_name(&schema).unwrap_or_default());
+        self.declarations[slot] = DeclaredType {
+            name: name.clone(),
+            schema,
+            recursive: false,
+        };
+        name
+    }
+
+    /// Declare a recursive type, found in the definitions at `depth`, returning its name
+    fn declare_recursive(&mut self, name: &str, depth: usize, definition: &'a Schema) -> String {
+        if let Some(name) = self.recursive.get(&(definition as *const Schema)) {
+            return name.clone();
+        }
+
+        // Named ahead of collecting its schema, which refers to it
+        let name = self.take_name(name);
+        self.recursive
+            .insert(definition as *const Schema, name.clone());
+        let slot = self.declarations.len();
+        self.declarations.push(DeclaredType {
+            name: name.clone(),
+            schema: Schema::Unit,
+            recursive: true,
+        });
+
+        // The definition can only see the scopes around its own
+        let inner = self.scopes.split_off(depth + 1);
+        self.declarations[slot].schema = self.collect_within(definition);
+        self.scopes.restore(inner);
+        name
+    }
+}
+
+/// The name of a type which is declared, rather than built in
+pub(crate) fn declared_name(schema: &Schema) -> Option<&str> {
+    match schema {
+        Schema::Struct(StructSchema { name, .. })
+        | Schema::Enum(EnumSchema { name, .. })
+        | Schema::TupleStruct(name, _)
+        | Schema::NewtypeStruct(name, _)
+        | Schema::UnitStruct(name) => Some(name),
+        _ => None,
+    }
+}
+
+/// Write a type as it is used, rather than declared
+fn write_type(schema: &Schema, f: &mut fmt::Formatter) -> fmt::Result {
+    match schema {
+        Schema::I8 => f.write_str("i8"),
+        Schema::U8 => f.write_str("u8"),
+        Schema::I16 => f.write_str("i16"),
+        Schema::U16 => f.write_str("u16"),
+        Schema::I32 => f.write_str("i32"),
+        Schema::U32 => f.write_str("u32"),
+        Schema::I64 => f.write_str("i64"),
+        Schema::U64 => f.write_str("u64"),
+        Schema::I128 => f.write_str("i128"),
+        Schema::U128 => f.write_str("u128"),
+        Schema::F32 => f.write_str("f32"),
+        Schema::F64 => f.write_str("f64"),
+        Schema::Bool => f.write_str("bool"),
+        Schema::Char => f.write_str("char"),
+        Schema::Unit => f.write_str("()"),
+        Schema::Bytes => f.write_str("Bytes"),
+        Schema::String => f.write_str("String"),
+        Schema::Option(inner) => {
+            f.write_str("Option<")?;
+            write_type(inner, f)?;
+            f.write_char('>')
+        }
+        Schema::UniformSequence(inner) => {
+            f.write_str("Vec<")?;
+            write_type(inner, f)?;
+            f.write_char('>')
+        }
+        Schema::Map { key, value } => {
+            f.write_str("Map<")?;
+            write_type(key, f)?;
+            f.write_str(", ")?;
+            write_type(value, f)?;
+            f.write_char('>')
+        }
+        Schema::Tuple(schemas) => write_tuple(schemas, f),
+        Schema::Struct(StructSchema { name, .. })
+        | Schema::Enum(EnumSchema { name, .. })
+        | Schema::TupleStruct(name, _)
+        | Schema::NewtypeStruct(name, _)
+        | Schema::UnitStruct(name)
+        | Schema::Ref(name) => f.write_str(name),
+        Schema::Recursive { root, .. } => write_type(root, f),
+    }
+}
+
+/// Write `(A, B)`, with a trailing comma after a single element
+fn write_tuple(schemas: &[Schema], f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_char('(')?;
+    for (i, schema) in schemas.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        write_type(schema, f)?;
+    }
+    if schemas.len() == 1 {
+        f.write_char(',')?;
+    }
+    f.write_char(')')
+}
+
+/// Write `{ a: A, b: B }`, with each field on its own line if `indent` is given
+fn write_fields(s: &StructSchema, indent: Option<&str>, f: &mut fmt::Formatter) -> fmt::Result {
+    if s.fields.is_empty() {
+        return f.write_str("{}");
+    }
+
+    f.write_char('{')?;
+    for (i, (name, schema)) in s.fields.iter().enumerate() {
+        match indent {
+            Some(indent) => write!(f, "\n{}", indent)?,
+            None if i > 0 => f.write_str(", ")?,
+            None => f.write_char(' ')?,
+        }
+        write!(f, "{}: ", name)?;
+        write_type(schema, f)?;
+        if indent.is_some() {
+            f.write_char(',')?;
+        }
+    }
+
+    f.write_str(if indent.is_some() { "\n}" } else { " }" })
+}
+
+fn write_declaration(declaration: &DeclaredType, f: &mut fmt::Formatter) -> fmt::Result {
+    let name = &declaration.name;
+    match declared_name(&declaration.schema) {
+        Some(type_name) if type_name != name => writeln!(f, "#[name = {:?}]", type_name)?,
+        Some(_) => (),
+        None => {
+            // Only a recursive definition can be other than a declared type
+            write!(f, "type {} = ", name)?;
+            write_type(&declaration.schema, f)?;
+            return f.write_char(';');
+        }
+    }
+
+    match &declaration.schema {
+        Schema::Struct(s) => {
+            write!(f, "struct {} ", name)?;
+            write_fields(s, Some("    "), f)
+        }
+        Schema::TupleStruct(_, schemas) => {
+            write!(f, "struct {}", name)?;
+            write_tuple(schemas, f)?;
+            f.write_char(';')
+        }
+        Schema::NewtypeStruct(_, inner) => {
+            write!(f, "struct {}(", name)?;
+            write_type(inner, f)?;
+            f.write_str(");")
+        }
+        Schema::UnitStruct(_) => write!(f, "struct {};", name),
+        Schema::Enum(e) => {
+            write!(f, "enum {} {{", name)?;
+            for (name, variant) in &e.variants {
+                write!(f, "\n    {}", name)?;
+                match variant {
+                    VariantSchema::Struct(s) => {
+                        f.write_char(' ')?;
+                        write_fields(s, None, f)?;
+                    }
+                    VariantSchema::Tuple(schemas) => write_tuple(schemas, f)?,
+                    VariantSchema::Newtype(inner) => {
+                        f.write_char('(')?;
+                        write_type(inner, f)?;
+                        f.write_char(')')?;
+                    }
+                    VariantSchema::Unit => (),
+                }
+                f.write_char(',')?;
+            }
+            if !e.variants.is_empty() {
+                f.write_char('\n')?;
+            }
+            f.write_char('}')
+        }
+        _ => unreachable!("Declared types have a name"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, parse_type};
+    use crate::fixtures::{Everything, Gender, Marker, Meters, Person, Wrapper};
+    use crate::{KobbleError, Schema};
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize, Deserialize)]
+    struct Scene {
+        root: Widget,
+        overlay: Option<Widget>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    enum Widget {
+        Label(String),
+        Panel(Panel),
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Panel {
+        children: BTreeMap<u32, Widget>,
+        parent: Option<Box<Panel>>,
+    }
+
+    #[test]
+    fn test_parse() {
+        let text =
+            "struct Person { gender: Gender, age: u64 } enum Gender { Truck, Sedan, Motorcycle }";
+        assert_eq!(parse(text).unwrap(), Schema::infer::<Person>());
+        assert_eq!(
+            parse_type(text, "Gender").unwrap(),
+            Schema::infer::<Gender>()
+        );
+    }
+
+    #[test]
+    fn test_print() {
+        assert_eq!(
+            Schema::infer::<Person>().to_string(),
+            "struct Person {
+    gender: Gender,
+    age: u64,
+}
+
+enum Gender {
+    Truck,
+    Sedan,
+    Motorcycle,
+}"
+        );
+
+        assert_eq!(
+            Schema::infer::<Vec<(Meters, Marker)>>().to_string(),
+            "type Root = Vec<(Meters, Marker)>;
+
+struct Meters(f32);
+
+struct Marker;"
+        );
+
+        // Different types with the same name are told apart
+        assert_eq!(
+            Schema::infer::<(Wrapper<u8>, Wrapper<String>, Wrapper<u8>)>().to_string(),
+            "type Root = (Wrapper, Wrapper_1, Wrapper);
+
+struct Wrapper {
+    inner: u8,
+}
+
+#[name = \"Wrapper\"]
+struct Wrapper_1 {
+    inner: String,
+}"
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for schema in [
+            Schema::infer::<Everything>(),
+            Schema::infer::<Scene>(),
+            Schema::infer::<Option<Vec<Panel>>>(),
+            Schema::infer::<(u8,)>(),
+            Schema::infer::<(Wrapper<u8>, Wrapper<String>)>(),
+            Schema::infer::<Wrapper<Wrapper<u8>>>(),
+        ] {
+            let text = schema.to_string();
+            assert_eq!(parse(&text).unwrap(), schema, "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_recursion_and_aliases() {
+        let text = "
+            // Aliases stand in for their type
+            type Forest = Vec<Tree>;
+            struct Tree { value: i32, children: Forest }
+        ";
+
+        #[derive(Serialize, Deserialize)]
+        struct Tree {
+            value: i32,
+            children: Vec<Tree>,
+        }
+
+        assert_eq!(parse(text).unwrap(), Schema::infer::<Vec<Tree>>());
+        assert_eq!(parse_type(text, "Tree").unwrap(), Schema::infer::<Tree>());
+    }
+
+    #[test]
+    fn test_errors() {
+        let message = |text| match parse(text) {
+            Err(KobbleError::InvalidFormat { message, .. }) => message,
+            other => panic!("unexpected {:?}", other),
+        };
+
+        assert_eq!(
+            message("struct A { b: B }"),
+            "undefined type B at line 1, column 15"
+        );
+        assert_eq!(
+            message("struct A;\nenum A {}"),
+            "A is declared twice at line 2, column 6"
+        );
+        assert_eq!(
+            message("type A = Vec<B>; type B = Option<A>;"),
+            "type A refers to itself at line 1, column 6"
+        );
+        assert_eq!(
+            message("struct A { b: u8, b: u8 }"),
+            "field b declared twice at line 1, column 19"
+        );
+        assert_eq!(
+            message("#[name = \"B\"] type A = u8;"),
+            "an alias has no name of its own at line 1, column 15"
+        );
+        assert_eq!(
+            message("fn main() {}"),
+            "expected `struct`, `enum` or `type` at line 1, column 1"
+        );
+        assert!(matches!(
+            parse_type("struct A;", "B"),
+            Err(KobbleError::UndefinedType { .. })
+        ));
+    }
+}
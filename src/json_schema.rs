@@ -0,0 +1,341 @@
+//! JSON Schema for the JSON form of values, as `serde_json` writes a DynamicValue.
+//!
+//! Declared types are written in place, with their name as the `title`. Recursive types are
+//! placed under `$defs` instead, by the name they are declared with in the schema's `Display`
+//! form, so that different types sharing a name each get their own definition.
+
+use std::collections::HashMap;
+
+use serde_json::{json, Map, Value};
+
+use crate::idl::{Declarations, DeclaredType};
+use crate::{Schema, StructSchema, VariantSchema};
+
+const DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+impl Schema {
+    /// JSON Schema describing values of this schema once serialized as JSON, e.g. a DynamicValue
+    /// passed to `serde_json`. Recursive types are placed under `$defs`.
+    pub fn to_json_schema(&self) -> Value {
+        let declarations = Declarations::of(self);
+        let describer = Describer {
+            types: declarations.by_name(),
+        };
+        let mut document = describer.describe(&declarations.schema);
+
+        let defs: Map<String, Value> = declarations
+            .types
+            .iter()
+            .filter(|t| t.recursive)
+            .map(|t| (t.name.clone(), describer.describe(&t.schema)))
+            .collect();
+
+        let object = document.as_object_mut().expect("Schemas are objects");
+        object.insert("$schema".into(), DIALECT.into());
+        if !defs.is_empty() {
+            object.insert("$defs".into(), defs.into());
+        }
+
+        document
+    }
+}
+
+fn titled(name: &str, mut schema: Value) -> Value {
+    schema
+        .as_object_mut()
+        .expect("Schemas are objects")
+        .insert("title".into(), name.into());
+    schema
+}
+
+/// The bounds of 128-bit integers are rounded to the nearest f64
+fn integer(minimum: impl Into<Value>, maximum: impl Into<Value>) -> Value {
+    json!({
+        "type": "integer",
+        "minimum": minimum.into(),
+        "maximum": maximum.into(),
+    })
+}
+
+/// Names of the variants of an enum whose variants are all unit variants
+fn unit_variants(schema: &Schema) -> Option<Vec<&str>> {
+    match schema {
+        Schema::Enum(e) => e
+            .variants
+            .iter()
+            .map(|(name, variant)| matches!(variant, VariantSchema::Unit).then_some(name.as_str()))
+            .collect(),
+        _ => None,
+    }
+}
+
+struct Describer<'a> {
+    /// Declared types, by name
+    types: HashMap<&'a str, &'a DeclaredType>,
+}
+
+impl Describer<'_> {
+    fn array(&self, schemas: &[Schema]) -> Value {
+        let items: Vec<Value> = schemas.iter().map(|s| self.describe(s)).collect();
+        json!({
+            "type": "array",
+            "prefixItems": items,
+            "minItems": schemas.len(),
+            "maxItems": schemas.len(),
+        })
+    }
+
+    fn object(&self, schema: &StructSchema) -> Value {
+        let properties: Map<String, Value> = schema
+            .fields
+            .iter()
+            .map(|(name, s)| (name.clone(), self.describe(s)))
+            .collect();
+        let required: Vec<&str> = schema
+            .fields
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+
+        json!({
+            "type": "object",
+            "properties": properties,
+            "required": required,
+            "additionalProperties": false,
+        })
+    }
+
+    /// JSON only has string keys, so other keys are written as strings
+    fn property_names(&self, key: &Schema) -> Option<Value> {
+        Some(match key {
+            Schema::U8 | Schema::U16 | Schema::U32 | Schema::U64 | Schema::U128 => {
+                json!({ "pattern": "^[0-9]+$" })
+            }
+            Schema::I8 | Schema::I16 | Schema::I32 | Schema::I64 | Schema::I128 => {
+                json!({ "pattern": "^-?[0-9]+$" })
+            }
+            Schema::Bool => json!({ "enum": ["true", "false"] }),
+            Schema::Char => json!({ "minLength": 1, "maxLength": 1 }),
+            Schema::Ref(name) => self.property_names(&self.types.get(name.as_str())?.schema)?,
+            _ => json!({ "enum": unit_variants(key)? }),
+        })
+    }
+
+    fn describe(&self, schema: &Schema) -> Value {
+        match schema {
+            Schema::I8 => integer(i8::MIN, i8::MAX),
+            Schema::U8 => integer(u8::MIN, u8::MAX),
+            Schema::I16 => integer(i16::MIN, i16::MAX),
+            Schema::U16 => integer(u16::MIN, u16::MAX),
+            Schema::I32 => integer(i32::MIN, i32::MAX),
+            Schema::U32 => integer(u32::MIN, u32::MAX),
+            Schema::I64 => integer(i64::MIN, i64::MAX),
+            Schema::U64 => integer(u64::MIN, u64::MAX),
+            Schema::I128 => integer(i128::MIN as f64, i128::MAX as f64),
+            Schema::U128 => integer(0, u128::MAX as f64),
+            Schema::F32 | Schema::F64 => json!({ "type": "number" }),
+            Schema::Bool => json!({ "type": "boolean" }),
+            Schema::Char => json!({ "type": "string", "minLength": 1, "maxLength": 1 }),
+            Schema::Unit => json!({ "type": "null" }),
+            Schema::Bytes => json!({ "type": "array", "items": self.describe(&Schema::U8) }),
+            Schema::String => json!({ "type": "string" }),
+            Schema::Option(inner) => json!({ "anyOf": [{ "type": "null" }, self.describe(inner)] }),
+            Schema::Struct(s) => titled(&s.name, self.object(s)),
+            Schema::Tuple(schemas) => self.array(schemas),
+            Schema::TupleStruct(name, schemas) => titled(name, self.array(schemas)),
+            // Newtypes are transparent in JSON
+            Schema::NewtypeStruct(_, inner) => self.describe(inner),
+            Schema::UnitStruct(name) => titled(name, json!({ "type": "null" })),
+            Schema::UniformSequence(inner) => {
+                json!({ "type": "array", "items": self.describe(inner) })
+            }
+            Schema::Map { key, value } => {
+                let mut map =
+                    json!({ "type": "object", "additionalProperties": self.describe(value) });
+                if let Some(names) = self.property_names(key) {
+                    map["propertyNames"] = names;
+                }
+                map
+            }
+            Schema::Enum(e) => {
+                // Unit variants are written as their name, and others as an object with the name
+                // as its only key
+                let units: Vec<&str> = e
+                    .variants
+                    .iter()
+                    .filter(|(_, variant)| matches!(variant, VariantSchema::Unit))
+                    .map(|(name, _)| name.as_str())
+                    .collect();
+
+                let mut one_of = vec![];
+                if !units.is_empty() {
+                    one_of.push(json!({ "type": "string", "enum": units }));
+                }
+
+                for (name, variant) in &e.variants {
+                    let data = match variant {
+                        VariantSchema::Struct(s) => self.object(s),
+                        VariantSchema::Tuple(schemas) => self.array(schemas),
+                        VariantSchema::Newtype(inner) => self.describe(inner),
+                        VariantSchema::Unit => continue,
+                    };
+
+                    one_of.push(json!({
+                        "type": "object",
+                        "properties": { name: data },
+                        "required": [name],
+                        "additionalProperties": false,
+                    }));
+                }
+
+                let schema = match one_of.len() {
+                    // Nothing is valid
+                    0 => json!({ "not": {} }),
+                    1 => one_of.remove(0),
+                    _ => json!({ "oneOf": one_of }),
+                };
+                titled(&e.name, schema)
+            }
+            Schema::Ref(name) => match self.types.get(name.as_str()) {
+                Some(t) if !t.recursive => self.describe(&t.schema),
+                _ => json!({ "$ref": format!("#/$defs/{}", name) }),
+            },
+            Schema::Recursive { .. } => unreachable!("Declarations have no recursive schemas"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::{Person, Shape, Wrapper};
+    use crate::{to_dynamic, Schema, StructSchema};
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_struct_and_enum() {
+        assert_eq!(
+            Schema::infer::<Person>().to_json_schema(),
+            json!({
+                "$schema": "https://json-schema.org/draft/2020-12/schema",
+                "title": "Person",
+                "type": "object",
+                "properties": {
+                    "gender": {
+                        "title": "Gender",
+                        "type": "string",
+                        "enum": ["Truck", "Sedan", "Motorcycle"],
+                    },
+                    "age": { "type": "integer", "minimum": 0, "maximum": u64::MAX },
+                },
+                "required": ["gender", "age"],
+                "additionalProperties": false,
+            })
+        );
+    }
+
+    #[test]
+    fn test_data_carrying_variants() {
+        #[derive(Serialize, Deserialize)]
+        struct Layer {
+            shapes: HashMap<u32, Shape>,
+            hidden: Option<bool>,
+        }
+
+        let schema = Schema::infer::<Layer>().to_json_schema();
+        let shapes = &schema["properties"]["shapes"];
+        assert_eq!(shapes["type"], "object");
+        assert_eq!(shapes["propertyNames"], json!({ "pattern": "^[0-9]+$" }));
+
+        let variants = &shapes["additionalProperties"]["oneOf"];
+        assert_eq!(variants[0], json!({ "type": "string", "enum": ["Empty"] }));
+        assert_eq!(variants[1]["required"], json!(["Circle"]));
+        assert_eq!(
+            variants[2]["properties"]["Polygon"]["items"]["prefixItems"][1],
+            json!({ "type": "integer", "minimum": -128, "maximum": 127 })
+        );
+
+        assert_eq!(
+            schema["properties"]["hidden"],
+            json!({ "anyOf": [{ "type": "null" }, { "type": "boolean" }] })
+        );
+
+        // The schema describes what serde_json makes of a DynamicValue
+        let value = serde_json::to_value(
+            to_dynamic(&Layer {
+                shapes: [(3, Shape::Polygon(vec![(1, -1)])), (4, Shape::Empty)]
+                    .into_iter()
+                    .collect(),
+                hidden: None,
+            })
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(value["shapes"]["3"], json!({ "Polygon": [[1, -1]] }));
+        assert_eq!(value["shapes"]["4"], json!("Empty"));
+        assert_eq!(value["hidden"], json!(null));
+    }
+
+    #[test]
+    fn test_recursive() {
+        #[derive(Serialize, Deserialize)]
+        struct Node {
+            value: i64,
+            children: Vec<Node>,
+        }
+
+        let schema = Schema::infer::<Node>().to_json_schema();
+        assert_eq!(schema["$ref"], "#/$defs/Node");
+        assert_eq!(
+            schema["$defs"]["Node"]["properties"]["children"],
+            json!({ "type": "array", "items": { "$ref": "#/$defs/Node" } })
+        );
+    }
+
+    #[test]
+    fn test_types_sharing_a_name() {
+        let schema = Schema::infer::<(Wrapper<u8>, Wrapper<String>)>().to_json_schema();
+        let items = &schema["prefixItems"];
+        assert_eq!(items[0]["properties"]["inner"]["type"], "integer");
+        assert_eq!(items[1]["properties"]["inner"]["type"], "string");
+
+        // Recursive types each get a definition
+        let node = |value: Schema| Schema::Recursive {
+            definitions: [(
+                "Node".to_string(),
+                Schema::Struct(StructSchema {
+                    name: "Node".into(),
+                    fields: vec![
+                        ("value".into(), value),
+                        (
+                            "next".into(),
+                            Schema::Option(Box::new(Schema::Ref("Node".into()))),
+                        ),
+                    ],
+                }),
+            )]
+            .into_iter()
+            .collect(),
+            root: Box::new(Schema::Ref("Node".into())),
+        };
+
+        let schema = Schema::Tuple(vec![node(Schema::Bool), node(Schema::Char)]).to_json_schema();
+        assert_eq!(
+            schema["prefixItems"],
+            json!([{ "$ref": "#/$defs/Node" }, { "$ref": "#/$defs/Node_1" }])
+        );
+        assert_eq!(
+            schema["$defs"]["Node"]["properties"]["next"]["anyOf"][1],
+            json!({ "$ref": "#/$defs/Node" })
+        );
+        assert_eq!(
+            schema["$defs"]["Node_1"]["properties"]["next"]["anyOf"][1],
+            json!({ "$ref": "#/$defs/Node_1" })
+        );
+        assert_eq!(
+            schema["$defs"]["Node_1"]["properties"]["value"]["type"],
+            "string"
+        );
+    }
+}
@@ -1,20 +1,52 @@
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
-use std::{cell::RefCell, collections::HashSet};
-
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashSet},
+};
+
+pub mod binary;
+pub mod codegen;
+mod compat;
+pub mod container;
+mod default_value;
 mod deserialize;
 mod error;
+mod fingerprint;
+#[cfg(test)]
+mod fixtures;
+mod from_dynamic;
+pub mod idl;
+mod json_schema;
+pub mod log;
+mod migrate;
+mod patch;
+mod path;
+mod registry;
 mod schema_recorder;
+mod scope;
+mod seed;
 mod serialize;
-
-pub use deserialize::{deserialize_dynamic, SchemaDeserializer};
+mod tagged;
+pub mod text;
+mod to_dynamic;
+mod validate;
+
+pub use compat::{CompatIssue, CompatReport, Compatibility};
+pub use deserialize::{deserialize_dynamic, SchemaSeed, RECURSION_LIMIT};
+pub use error::KobbleError;
+pub use from_dynamic::from_dynamic;
+pub use migrate::migrate;
+pub use patch::{diff, Patch, PatchOp};
+pub use path::{PathSegment, ValuePath};
+pub use registry::{SchemaId, SchemaRegistry};
 pub use schema_recorder::record_schema;
+pub use to_dynamic::to_dynamic;
+pub use validate::ValidationError;
 
 /// Representation of a data serde-compatible data structure
 #[derive(Debug, Clone, Deserialize, Serialize, Hash, PartialEq, Eq)]
 pub enum Schema {
-    //Seq,
-    //Map,
     I8,
     U8,
     I16,
@@ -30,10 +62,11 @@ pub enum Schema {
     Bool,
     Char,
     Unit,
-    //Bytes,
-    //Option,
-    //ByteBuf,
+    /// Byte buffer, e.g. `serde_bytes::ByteBuf` or `&[u8]`
+    Bytes,
     String,
+    /// Optional data, e.g. `Option<T>`
+    Option(Box<Schema>),
     Struct(StructSchema),
     Tuple(TupleSchema),
     /// (name, tuple)
@@ -44,18 +77,47 @@ pub enum Schema {
     UnitStruct(String),
     /// (name, data type)
     UniformSequence(Box<Schema>),
+    /// Key-value mapping, e.g. `HashMap<K, V>` or `BTreeMap<K, V>`
+    Map {
+        key: Box<Schema>,
+        value: Box<Schema>,
+    },
     /// (Enum name, variants)
-    /// NOTE This only describes data-less enums!
     Enum(EnumSchema),
+    /// Refers to the named type in the definitions of the enclosing `Schema::Recursive`
+    Ref(String),
+    /// A type which refers to itself. Each recursive type found in `root` is moved into
+    /// `definitions`, and is replaced by a `Schema::Ref` wherever it occurs
+    Recursive {
+        definitions: Definitions,
+        root: Box<Schema>,
+    },
 }
 
 pub type TupleSchema = Vec<Schema>;
 
+/// Named types, keyed by name
+pub type Definitions = BTreeMap<String, Schema>;
+
 /// Represents an enum
 #[derive(Debug, Clone, Deserialize, Serialize, Hash, PartialEq, Eq)]
 pub struct EnumSchema {
     pub name: String,
-    pub variants: Vec<String>,
+    /// (variant name, variant data)
+    pub variants: Vec<(String, VariantSchema)>,
+}
+
+/// Represents the data carried by an enum variant
+#[derive(Debug, Clone, Deserialize, Serialize, Hash, PartialEq, Eq)]
+pub enum VariantSchema {
+    /// e.g. `A::B { c: i32 }`. The name of the struct is the name of the variant
+    Struct(StructSchema),
+    /// e.g. `A::B(i32, f32)`
+    Tuple(TupleSchema),
+    /// e.g. `A::B(i32)`
+    Newtype(Box<Schema>),
+    /// e.g. `A::B`
+    Unit,
 }
 
 /// Represents a struct
@@ -71,28 +133,9 @@ impl Schema {
     }
 }
 
-/*
-/// Represents an enum
-#[derive(Debug, Clone)]
-pub struct EnumSchema {
-    name: String,
-    variants: Vec<(String, VariantSchema)>,
-}
-
-/// Represents an enum variant
-#[derive(Debug, Clone)]
-pub enum VariantSchema {
-    Struct(StructSchema),
-    Tuple(TupleSchema),
-    Unit,
-}
-*/
-
 /// Runtime-modifiable representation of a data structure
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum DynamicValue {
-    //Seq(Vec<DynamicValue>),
-    //Map(HashMap<String, DynamicValue>),
     I8(i8),
     U8(u8),
     I16(i16),
@@ -108,10 +151,9 @@ pub enum DynamicValue {
     Bool(bool),
     Char(char),
     Unit,
-    //Bytes(Vec<u8>),
-    //Option(Option<Box<DynamicValue>>),
-    //ByteBuf(Vec<u8>),
+    Bytes(Vec<u8>),
     String(String),
+    Option(Option<Box<DynamicValue>>),
     TupleStruct(String, Vec<DynamicValue>),
     NewtypeStruct(String, Box<DynamicValue>),
     Struct {
@@ -120,15 +162,81 @@ pub enum DynamicValue {
     },
     Tuple(Vec<DynamicValue>),
     UniformSequence(Vec<DynamicValue>),
+    /// (key, value) entries, in the order they were deserialized
+    Map(Vec<(DynamicValue, DynamicValue)>),
     UnitStruct(String),
-    Enum(EnumSchema, u32),
+    /// A single variant of an enum, along with its data
+    Enum {
+        name: String,
+        variant: String,
+        index: u32,
+        value: VariantValue,
+    },
+}
+
+/// Runtime-modifiable representation of the data carried by an enum variant
+#[derive(Debug, Clone, PartialEq)]
+pub enum VariantValue {
+    Struct(Vec<(String, DynamicValue)>),
+    Tuple(Vec<DynamicValue>),
+    Newtype(Box<DynamicValue>),
+    Unit,
+}
+
+/// Converts a string to a static string
+pub(crate) fn string_to_static(s: String) -> &'static str {
+    // String cache, so that we don't leak memory converting the same string over and over
+    // TODO: Use fxhash? This is non-crypto!
+    thread_local! {
+        static STRING_CACHE: RefCell<Lazy<HashSet<&'static str>>>
+            = RefCell::new(Lazy::new(HashSet::new));
+    }
+
+    STRING_CACHE.with(|cache| {
+        let maybe_cached: Option<&'static str> = cache.borrow().get(s.as_str()).copied();
+        if let Some(cached) = maybe_cached {
+            cached
+        } else {
+            let s: &'static str = Box::leak(s.into_boxed_str());
+            cache.borrow_mut().insert(s);
+            s
+        }
+    })
+}
+
+/// Converts a list of names to a static slice of static strings
+pub(crate) fn names_to_static<'a>(names: impl Iterator<Item = &'a str>) -> &'static [&'static str] {
+    // Slice cache, so that we don't leak memory converting the same names over and over
+    thread_local! {
+        static NAMES_CACHE: RefCell<Lazy<HashSet<&'static [&'static str]>>>
+            = RefCell::new(Lazy::new(HashSet::new));
+    }
+
+    let names: Vec<&'static str> = names.map(|s| string_to_static(s.to_string())).collect();
+
+    NAMES_CACHE.with(|cache| {
+        let maybe_cached = cache.borrow().get(names.as_slice()).copied();
+        if let Some(cached) = maybe_cached {
+            cached
+        } else {
+            let names: &'static [&'static str] = Box::leak(names.into_boxed_slice());
+            cache.borrow_mut().insert(names);
+            names
+        }
+    })
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{deserialize::SchemaDeserializer, Schema};
+    use crate::{
+        DynamicValue, EnumSchema, KobbleError, Schema, SchemaSeed, StructSchema, ValuePath,
+        VariantSchema,
+    };
+    use bincode::Options;
     use glam::{Vec3, Vec4};
     use serde::{Deserialize, Serialize};
+    use serde_bytes::ByteBuf;
+    use std::collections::{BTreeMap, HashMap};
 
     fn roundrip_test<'de, T: Serialize + Deserialize<'de>>(instance: T) {
         // Create a schema for the data type
@@ -138,8 +246,10 @@ mod tests {
         let bytes = bincode::serialize(&instance).unwrap();
 
         // Deserialize the bytes into a DynamicValue using the schema
-        SchemaDeserializer::set_schema(schema);
-        let SchemaDeserializer(dynamic) = bincode::deserialize(&bytes).unwrap();
+        let dynamic = bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .deserialize_seed(SchemaSeed::new(&schema), &bytes)
+            .unwrap();
 
         // Serialize the DynamicValue into bytes again
         let re_serialized = bincode::serialize(&dynamic).unwrap();
@@ -179,9 +289,7 @@ mod tests {
         ))
     }
 
-    // For now, we don't know how to do this!
     #[test]
-    #[should_panic]
     fn test_data_enum() {
         #[derive(Serialize, Deserialize)]
         enum A {
@@ -190,9 +298,9 @@ mod tests {
         }
 
         roundrip_test(A::B(23480));
+        roundrip_test(A::Fork);
     }
 
-    // For now, we don't know how to do this!
     #[test]
     fn test_unit_enum() {
         #[derive(Serialize, Deserialize)]
@@ -205,9 +313,7 @@ mod tests {
         roundrip_test(A::Spoon);
     }
 
-    /* TODO: Handle this corner case!
     #[test]
-    #[should_panic]
     fn test_data_enum_corner_case() {
         #[derive(Serialize, Deserialize)]
         enum A {
@@ -216,8 +322,76 @@ mod tests {
         }
 
         roundrip_test(A::Fork);
+        roundrip_test(A::B(-4));
+    }
+
+    #[test]
+    fn test_enum_variant_kinds() {
+        #[derive(Serialize, Deserialize)]
+        enum A {
+            Unit,
+            Newtype(String),
+            Tuple(u8, f32),
+            Struct { x: i64, y: B },
+        }
+
+        #[derive(Serialize, Deserialize)]
+        enum B {
+            Left,
+            Right(u16),
+        }
+
+        roundrip_test(A::Unit);
+        roundrip_test(A::Newtype("Spork".into()));
+        roundrip_test(A::Tuple(8, 1.5));
+        roundrip_test(A::Struct { x: -9, y: B::Left });
+        roundrip_test(A::Struct {
+            x: 9,
+            y: B::Right(300),
+        });
+    }
+
+    #[test]
+    fn test_enum_schema() {
+        #[derive(Serialize, Deserialize)]
+        enum A {
+            B(i32),
+            C { d: Vec<E> },
+        }
+
+        #[derive(Serialize, Deserialize)]
+        enum E {
+            F,
+            G(u8, u8),
+        }
+
+        let e = Schema::Enum(EnumSchema {
+            name: "E".into(),
+            variants: vec![
+                ("F".into(), VariantSchema::Unit),
+                (
+                    "G".into(),
+                    VariantSchema::Tuple(vec![Schema::U8, Schema::U8]),
+                ),
+            ],
+        });
+
+        let a = Schema::Enum(EnumSchema {
+            name: "A".into(),
+            variants: vec![
+                ("B".into(), VariantSchema::Newtype(Box::new(Schema::I32))),
+                (
+                    "C".into(),
+                    VariantSchema::Struct(StructSchema {
+                        name: "C".into(),
+                        fields: vec![("d".into(), Schema::UniformSequence(Box::new(e)))],
+                    }),
+                ),
+            ],
+        });
+
+        assert_eq!(Schema::infer::<A>(), a);
     }
-    */
 
     #[test]
     fn test_struct() {
@@ -262,6 +436,458 @@ mod tests {
         roundrip_test(A);
     }
 
+    #[test]
+    fn test_option() {
+        roundrip_test(Some(5u32));
+        roundrip_test::<Option<u32>>(None);
+        roundrip_test(Some(Some("Spoon".to_string())));
+        roundrip_test(vec![Some(1.0f32), None, Some(3.0)]);
+    }
+
+    #[test]
+    fn test_option_fields() {
+        #[derive(Serialize, Deserialize)]
+        struct A {
+            b: Option<B>,
+            c: Option<i16>,
+        }
+
+        #[derive(Serialize, Deserialize)]
+        enum B {
+            D,
+            E(Option<u8>),
+        }
+
+        assert_eq!(
+            Schema::infer::<A>(),
+            Schema::Struct(StructSchema {
+                name: "A".into(),
+                fields: vec![
+                    (
+                        "b".into(),
+                        Schema::Option(Box::new(Schema::Enum(EnumSchema {
+                            name: "B".into(),
+                            variants: vec![
+                                ("D".into(), VariantSchema::Unit),
+                                (
+                                    "E".into(),
+                                    VariantSchema::Newtype(Box::new(Schema::Option(Box::new(
+                                        Schema::U8
+                                    ))))
+                                ),
+                            ],
+                        })))
+                    ),
+                    ("c".into(), Schema::Option(Box::new(Schema::I16))),
+                ],
+            })
+        );
+
+        roundrip_test(A { b: None, c: None });
+        roundrip_test(A {
+            b: Some(B::D),
+            c: Some(-3),
+        });
+        roundrip_test(A {
+            b: Some(B::E(Some(9))),
+            c: None,
+        });
+        roundrip_test(A {
+            b: Some(B::E(None)),
+            c: Some(1),
+        });
+    }
+
+    #[test]
+    fn test_map() {
+        let mut map = HashMap::new();
+        map.insert("Spoon".to_string(), 8i32);
+        map.insert("Fork".to_string(), -3);
+        map.insert("Knife".to_string(), 0);
+
+        assert_eq!(
+            Schema::infer::<HashMap<String, i32>>(),
+            Schema::Map {
+                key: Box::new(Schema::String),
+                value: Box::new(Schema::I32),
+            }
+        );
+
+        roundrip_test(map);
+        roundrip_test(HashMap::<u8, f64>::new());
+    }
+
+    #[test]
+    fn test_map_fields() {
+        #[derive(Serialize, Deserialize)]
+        struct A {
+            b: BTreeMap<u32, Vec<C>>,
+        }
+
+        #[derive(Serialize, Deserialize)]
+        enum C {
+            D,
+            E(BTreeMap<char, bool>),
+        }
+
+        let mut b = BTreeMap::new();
+        b.insert(3, vec![C::D, C::E(BTreeMap::new())]);
+        b.insert(
+            1,
+            vec![C::E([('a', true), ('b', false)].into_iter().collect())],
+        );
+
+        roundrip_test(A { b });
+    }
+
+    #[test]
+    fn test_bytes() {
+        #[derive(Serialize, Deserialize)]
+        struct A<'a> {
+            #[serde(with = "serde_bytes")]
+            b: &'a [u8],
+            c: ByteBuf,
+            d: Vec<ByteBuf>,
+        }
+
+        assert_eq!(
+            Schema::infer::<A>(),
+            Schema::Struct(StructSchema {
+                name: "A".into(),
+                fields: vec![
+                    ("b".into(), Schema::Bytes),
+                    ("c".into(), Schema::Bytes),
+                    ("d".into(), Schema::UniformSequence(Box::new(Schema::Bytes))),
+                ],
+            })
+        );
+
+        roundrip_test(A {
+            b: &[1, 2, 3],
+            c: ByteBuf::from(vec![4, 5]),
+            d: vec![ByteBuf::new(), ByteBuf::from(vec![6; 300])],
+        });
+    }
+
+    #[test]
+    fn test_recursive_struct() {
+        #[derive(Serialize, Deserialize)]
+        struct Node {
+            value: i32,
+            children: Vec<Node>,
+        }
+
+        let node = Schema::Struct(StructSchema {
+            name: "Node".into(),
+            fields: vec![
+                ("value".into(), Schema::I32),
+                (
+                    "children".into(),
+                    Schema::UniformSequence(Box::new(Schema::Ref("Node".into()))),
+                ),
+            ],
+        });
+
+        assert_eq!(
+            Schema::infer::<Node>(),
+            Schema::Recursive {
+                definitions: [("Node".to_string(), node)].into_iter().collect(),
+                root: Box::new(Schema::Ref("Node".into())),
+            }
+        );
+
+        roundrip_test(Node {
+            value: 1,
+            children: vec![
+                Node {
+                    value: 2,
+                    children: vec![],
+                },
+                Node {
+                    value: 3,
+                    children: vec![Node {
+                        value: 4,
+                        children: vec![],
+                    }],
+                },
+            ],
+        });
+    }
+
+    #[test]
+    fn test_recursive_enum() {
+        #[derive(Serialize, Deserialize)]
+        enum Tree {
+            Branch(Box<Tree>, Box<Tree>),
+            Leaf(u8),
+        }
+
+        roundrip_test(Tree::Leaf(3));
+        roundrip_test(Tree::Branch(
+            Box::new(Tree::Leaf(1)),
+            Box::new(Tree::Branch(
+                Box::new(Tree::Leaf(2)),
+                Box::new(Tree::Leaf(3)),
+            )),
+        ));
+    }
+
+    #[test]
+    fn test_mutually_recursive() {
+        #[derive(Serialize, Deserialize)]
+        struct Scene {
+            root: Widget,
+            overlay: Option<Widget>,
+        }
+
+        #[derive(Serialize, Deserialize)]
+        enum Widget {
+            Label(String),
+            Panel(Panel),
+        }
+
+        #[derive(Serialize, Deserialize)]
+        struct Panel {
+            children: BTreeMap<u32, Widget>,
+            parent: Option<Box<Panel>>,
+        }
+
+        let schema = Schema::infer::<Scene>();
+        let Schema::Recursive { definitions, root } = schema else {
+            panic!("Expected a recursive schema")
+        };
+        assert!(matches!(*root, Schema::Struct(_)));
+        assert_eq!(
+            definitions.keys().collect::<Vec<_>>(),
+            vec!["Panel", "Widget"]
+        );
+
+        roundrip_test(Scene {
+            root: Widget::Panel(Panel {
+                children: [(4, Widget::Label("Spoon".into()))].into_iter().collect(),
+                parent: Some(Box::new(Panel {
+                    children: BTreeMap::new(),
+                    parent: None,
+                })),
+            }),
+            overlay: Some(Widget::Label("Fork".into())),
+        });
+    }
+
+    #[test]
+    fn test_nested_recursive() {
+        let inner = Schema::Struct(StructSchema {
+            name: "Inner".into(),
+            fields: vec![
+                (
+                    "next".into(),
+                    Schema::Option(Box::new(Schema::Ref("Inner".into()))),
+                ),
+                (
+                    "outer".into(),
+                    Schema::Option(Box::new(Schema::Ref("Outer".into()))),
+                ),
+            ],
+        });
+        let outer = Schema::Struct(StructSchema {
+            name: "Outer".into(),
+            fields: vec![(
+                "inner".into(),
+                Schema::Recursive {
+                    definitions: [("Inner".to_string(), inner)].into_iter().collect(),
+                    root: Box::new(Schema::Ref("Inner".into())),
+                },
+            )],
+        });
+        let schema = Schema::Recursive {
+            definitions: [("Outer".to_string(), outer)].into_iter().collect(),
+            root: Box::new(Schema::Ref("Outer".into())),
+        };
+
+        let inner =
+            |next: Option<DynamicValue>, outer: Option<DynamicValue>| DynamicValue::Struct {
+                name: "Inner".into(),
+                fields: vec![
+                    ("next".into(), DynamicValue::Option(next.map(Box::new))),
+                    ("outer".into(), DynamicValue::Option(outer.map(Box::new))),
+                ],
+            };
+        let outer = |inner: DynamicValue| DynamicValue::Struct {
+            name: "Outer".into(),
+            fields: vec![("inner".into(), inner)],
+        };
+
+        // References within the inner definitions may refer to the outer ones
+        let value = outer(inner(
+            Some(inner(None, None)),
+            Some(outer(inner(None, None))),
+        ));
+        schema.validate(&value).unwrap();
+
+        let bytes = bincode::serialize(&value).unwrap();
+        let mut deser = bincode::Deserializer::from_slice(
+            &bytes,
+            bincode::DefaultOptions::new().with_fixint_encoding(),
+        );
+        assert_eq!(
+            crate::deserialize_dynamic(&schema, &mut deser).unwrap(),
+            value
+        );
+    }
+
+    #[test]
+    fn test_infinite_type() {
+        #[derive(Serialize, Deserialize)]
+        struct A {
+            b: Box<A>,
+        }
+
+        assert_eq!(
+            crate::record_schema::<A>(),
+            Err(KobbleError::NoFiniteInstance {
+                path: ValuePath::root().field("b").field("b"),
+                name: "A".into(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_unsupported_error_path() {
+        #[derive(Serialize, Deserialize)]
+        struct A {
+            b: i32,
+            c: (u8, B),
+        }
+
+        #[derive(Serialize, Deserialize)]
+        #[serde(untagged)]
+        enum B {
+            D(i32),
+            E(String),
+        }
+
+        let err = crate::record_schema::<A>().unwrap_err();
+        assert!(matches!(err, KobbleError::Unsupported { .. }));
+        assert_eq!(err.path().to_string(), "c[1]");
+    }
+
+    #[test]
+    fn test_corrupt_data_error_path() {
+        #[derive(Serialize, Deserialize)]
+        struct A {
+            items: Vec<P>,
+            e: E,
+        }
+
+        #[derive(Serialize, Deserialize)]
+        struct P {
+            x: u8,
+            y: u32,
+        }
+
+        #[derive(Serialize, Deserialize)]
+        enum E {
+            F,
+            G,
+        }
+
+        let schema = Schema::infer::<A>();
+        let a = A {
+            items: vec![P { x: 1, y: 2 }, P { x: 3, y: 4 }],
+            e: E::G,
+        };
+        let mut bytes = bincode::serialize(&a).unwrap();
+
+        // Out of range variant index
+        let len = bytes.len();
+        bytes[len - 4] = 9;
+        let mut deser = bincode::Deserializer::from_slice(
+            &bytes,
+            bincode::DefaultOptions::new().with_fixint_encoding(),
+        );
+        let err = crate::deserialize_dynamic(&schema, &mut deser).unwrap_err();
+        assert_eq!(
+            err,
+            KobbleError::InvalidEnumIndex {
+                path: ValuePath::root().field("e"),
+                name: "E".into(),
+                index: 9,
+            }
+        );
+
+        // Truncated in the middle of the last item
+        bytes.truncate(len - 6);
+        let mut deser = bincode::Deserializer::from_slice(
+            &bytes,
+            bincode::DefaultOptions::new().with_fixint_encoding(),
+        );
+        let err = crate::deserialize_dynamic(&schema, &mut deser).unwrap_err();
+        assert_eq!(err.path().to_string(), "items[1].y");
+    }
+
+    #[test]
+    fn test_recursion_limit() {
+        #[derive(Serialize, Deserialize)]
+        struct Node {
+            next: Option<Box<Node>>,
+        }
+
+        let mut node = Node { next: None };
+        for _ in 0..crate::RECURSION_LIMIT + 1 {
+            node = Node {
+                next: Some(Box::new(node)),
+            };
+        }
+
+        let schema = Schema::infer::<Node>();
+        let bytes = bincode::serialize(&node).unwrap();
+        let mut deser = bincode::Deserializer::from_slice(
+            &bytes,
+            bincode::DefaultOptions::new().with_fixint_encoding(),
+        );
+        let err = crate::deserialize_dynamic(&schema, &mut deser).unwrap_err();
+        assert!(matches!(err, KobbleError::RecursionLimit { .. }));
+
+        // Validation rejects what deserializing would
+        let dynamic = crate::to_dynamic(&node).unwrap();
+        let errors = schema.validate(&dynamic).unwrap_err();
+        assert!(matches!(errors[..], [KobbleError::RecursionLimit { .. }]));
+
+        // Unless the limit is lifted
+        let seed = SchemaSeed::new(&schema).with_recursion_limit(None);
+        let options = bincode::DefaultOptions::new().with_fixint_encoding();
+        assert_eq!(options.deserialize_seed(seed, &bytes).unwrap(), dynamic);
+        assert!(schema.validate_with_recursion_limit(&dynamic, None).is_ok());
+    }
+
+    #[test]
+    fn test_seed_across_threads() {
+        #[derive(Serialize, Deserialize)]
+        struct A {
+            b: Vec<(u8, String)>,
+        }
+
+        let schema = Schema::infer::<A>();
+        let bytes = bincode::serialize(&A {
+            b: vec![(1, "Spoon".into()), (2, "Fork".into())],
+        })
+        .unwrap();
+
+        // The same schema may be shared by several threads at once
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let mut deser = bincode::Deserializer::from_slice(
+                        &bytes,
+                        bincode::DefaultOptions::new().with_fixint_encoding(),
+                    );
+                    let dynamic = crate::deserialize_dynamic(&schema, &mut deser).unwrap();
+                    assert_eq!(bincode::serialize(&dynamic).unwrap(), bytes);
+                });
+            }
+        });
+    }
+
     #[test]
     fn test_vector() {
         roundrip_test(Vec3::new(1., 2., 3.));
@@ -284,24 +910,3 @@ mod tests {
         ));
     }
 }
-
-/// Converts a string to a static string
-pub(crate) fn string_to_static(s: String) -> &'static str {
-    // String cache, so that we don't leak memory converting the same string over and over
-    // TODO: Use fxhash? This is non-crypto!
-    thread_local! {
-        static STRING_CACHE: RefCell<Lazy<HashSet<&'static str>>>
-            = RefCell::new(Lazy::new(HashSet::new));
-    }
-
-    STRING_CACHE.with(|cache| {
-        let maybe_cached: Option<&'static str> = cache.borrow().get(s.as_str()).copied();
-        if let Some(cached) = maybe_cached {
-            cached
-        } else {
-            let s: &'static str = Box::leak(s.into_boxed_str());
-            cache.borrow_mut().insert(s);
-            s
-        }
-    })
-}
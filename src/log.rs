@@ -0,0 +1,486 @@
+//! Append-only log of records, whose schema may change partway through.
+//!
+//! Starts with the same magic number as a container, with its own format version. The rest is
+//! a series of blocks, each a tag byte followed by a length-prefixed payload:
+//! * A schema along with its ID, serialized with bincode. IDs are assigned in order, starting
+//!   from 0, and the schema describes the records after it
+//! * A switch back to an earlier schema, given its ID as a u32
+//! * A record, serialized with bincode
+//! * The index, listing where each schema and record starts. Nothing but the trailer comes
+//!   after it
+//!
+//! The trailer is the position of the index as a u64, followed by `KOBLINDX`. A log whose
+//! writer never finished has no index, but can still be read from start to end.
+
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+
+use crate::container::{
+    decode, decode_schema, invalid, options, read_block, read_header, write_block, write_header,
+};
+use crate::error::KobbleError;
+use crate::path::ValuePath;
+use crate::seed::{LimitedSchemaSeed, Pair};
+use crate::{DynamicValue, Schema, SchemaId, SchemaRegistry};
+
+pub const VERSION: u32 = 2;
+
+const TRAILER_MAGIC: [u8; 8] = *b"KOBLINDX";
+
+const SCHEMA: u8 = 0;
+const SWITCH: u8 = 1;
+const RECORD: u8 = 2;
+const INDEX: u8 = 3;
+
+/// Where each schema and record starts
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+struct Index {
+    /// Position of each schema block, by ID
+    schemas: Vec<u64>,
+    /// Position of each record block, along with the ID of its schema
+    records: Vec<(u64, SchemaId)>,
+}
+
+/// Decode a schema block into the schema's ID and the schema
+fn decode_schema_block(bytes: &[u8]) -> Result<(SchemaId, Schema), KobbleError> {
+    decode_schema(Pair(PhantomData, LimitedSchemaSeed::default()), bytes)
+}
+
+/// Counts the bytes written, so that blocks can be indexed
+struct Counter<W> {
+    writer: W,
+    position: u64,
+}
+
+impl<W: Write> Write for Counter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.writer.write(buf)?;
+        self.position += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Writes records to a log, switching schemas as needed
+pub struct Writer<W: Write> {
+    writer: Counter<W>,
+    registry: SchemaRegistry,
+    current: Option<SchemaId>,
+    index: Index,
+}
+
+impl<W: Write> Writer<W> {
+    /// Start a log
+    pub fn new(writer: W) -> Result<Self, KobbleError> {
+        let mut writer = Counter {
+            writer,
+            position: 0,
+        };
+        write_header(&mut writer, VERSION)?;
+
+        Ok(Self {
+            writer,
+            registry: SchemaRegistry::new(),
+            current: None,
+            index: Index::default(),
+        })
+    }
+
+    fn block(&mut self, tag: u8, bytes: &[u8]) -> Result<u64, KobbleError> {
+        let position = self.writer.position;
+        self.writer.write_all(&[tag])?;
+        write_block(&mut self.writer, bytes)?;
+        Ok(position)
+    }
+
+    /// Describe the records written from now on with `schema`, returning its ID. Each
+    /// distinct schema is only written to the log once.
+    pub fn set_schema(&mut self, schema: &Schema) -> Result<SchemaId, KobbleError> {
+        let id = match self.registry.id_of(schema) {
+            Some(id) if self.current == Some(id) => return Ok(id),
+            Some(id) => {
+                self.block(SWITCH, &id.to_le_bytes())?;
+                id
+            }
+            None => {
+                let id = self.registry.len() as SchemaId;
+                let position = self.block(SCHEMA, &options().serialize(&(id, schema))?)?;
+                self.index.schemas.push(position);
+                self.registry.register(schema.clone())
+            }
+        };
+
+        self.current = Some(id);
+        Ok(id)
+    }
+
+    /// Describe the records written from now on with the schema of `T`
+    pub fn set_type<'de, T: Deserialize<'de>>(&mut self) -> Result<SchemaId, KobbleError> {
+        self.set_schema(&Schema::infer::<T>())
+    }
+
+    /// Write a record, which must conform to the current schema
+    pub fn write(&mut self, record: &DynamicValue) -> Result<(), KobbleError> {
+        let id = self
+            .current
+            .ok_or_else(|| KobbleError::custom("No schema set for the records of the log"))?;
+
+        let schema = self
+            .registry
+            .get(id)
+            .expect("The current schema is registered");
+        if let Err(errors) = schema.validate(record) {
+            return Err(KobbleError::InvalidValue {
+                path: ValuePath::root(),
+                errors,
+            });
+        }
+
+        let position = self.block(RECORD, &options().serialize(record)?)?;
+        self.index.records.push((position, id));
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), KobbleError> {
+        Ok(self.writer.flush()?)
+    }
+
+    /// Write the index, so that the log can be read from any record onwards
+    pub fn finish(mut self) -> Result<W, KobbleError> {
+        let index = options().serialize(&self.index)?;
+        let position = self.block(INDEX, &index)?;
+
+        self.writer.write_all(&position.to_le_bytes())?;
+        self.writer.write_all(&TRAILER_MAGIC)?;
+        self.writer.flush()?;
+        Ok(self.writer.writer)
+    }
+}
+
+/// Reads the records of a log as DynamicValues, along with the ID of their schema
+pub struct Reader<R: Read> {
+    reader: R,
+    schemas: Vec<Schema>,
+    current: Option<SchemaId>,
+    index: Option<Index>,
+    /// Set once the index is reached
+    done: bool,
+}
+
+impl<R: Read> Reader<R> {
+    /// Read the header of a log
+    pub fn new(mut reader: R) -> Result<Self, KobbleError> {
+        let version = read_header(&mut reader)?;
+        if version != VERSION {
+            return Err(invalid(format!("unsupported version {}", version)));
+        }
+
+        Ok(Self {
+            reader,
+            schemas: vec![],
+            current: None,
+            index: None,
+            done: false,
+        })
+    }
+
+    /// The schema with the given ID, if it has been read yet
+    pub fn schema(&self, id: SchemaId) -> Option<&Schema> {
+        self.schemas.get(id as usize)
+    }
+
+    /// Read a block, returning its tag and contents
+    fn block(&mut self) -> Result<Option<(u8, Vec<u8>)>, KobbleError> {
+        let mut tag = [0];
+        if self.reader.read(&mut tag)? == 0 {
+            return Ok(None);
+        }
+
+        let bytes = read_block(&mut self.reader)?.ok_or_else(|| invalid("missing block"))?;
+        Ok(Some((tag[0], bytes)))
+    }
+
+    /// The next record along with the ID of its schema, or `None` at the end of the log
+    pub fn read(&mut self) -> Result<Option<(SchemaId, DynamicValue)>, KobbleError> {
+        while !self.done {
+            let Some((tag, bytes)) = self.block()? else {
+                break;
+            };
+
+            match tag {
+                SCHEMA => {
+                    let (id, schema) = decode_schema_block(&bytes)?;
+                    // Schemas may be known already, from the index
+                    if id as usize == self.schemas.len() {
+                        self.schemas.push(schema);
+                    } else if id as usize > self.schemas.len() {
+                        return Err(invalid(format!("schema {} out of order", id)));
+                    }
+                    self.current = Some(id);
+                }
+                SWITCH => {
+                    let id = bytes
+                        .try_into()
+                        .map(SchemaId::from_le_bytes)
+                        .map_err(|_| invalid("malformed schema switch"))?;
+                    self.current = Some(id);
+                }
+                RECORD => {
+                    let id = self
+                        .current
+                        .ok_or_else(|| invalid("record before any schema"))?;
+                    let schema = self
+                        .schema(id)
+                        .ok_or_else(|| invalid(format!("switch to unknown schema {}", id)))?;
+                    return Ok(Some((id, decode(schema, &bytes)?)));
+                }
+                INDEX => self.done = true,
+                tag => return Err(invalid(format!("unknown block {}", tag))),
+            }
+        }
+
+        Ok(None)
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read + Seek> Reader<R> {
+    /// Load the index, if the log has one, along with every schema. Returns whether there was
+    /// an index. Afterwards, reading continues from the same place.
+    pub fn load_index(&mut self) -> Result<bool, KobbleError> {
+        let resume = self.reader.stream_position()?;
+
+        let mut trailer = [0; 16];
+        if self.reader.seek(SeekFrom::End(-16)).is_err() {
+            self.reader.seek(SeekFrom::Start(resume))?;
+            return Ok(false);
+        }
+        self.reader.read_exact(&mut trailer)?;
+        if trailer[8..] != TRAILER_MAGIC {
+            self.reader.seek(SeekFrom::Start(resume))?;
+            return Ok(false);
+        }
+
+        let position = u64::from_le_bytes(trailer[..8].try_into().unwrap());
+        self.reader.seek(SeekFrom::Start(position))?;
+        let index: Index = match self.block()? {
+            Some((INDEX, bytes)) => options().deserialize(&bytes)?,
+            _ => return Err(invalid("trailer does not point to an index")),
+        };
+
+        let mut schemas = vec![];
+        for &position in &index.schemas {
+            self.reader.seek(SeekFrom::Start(position))?;
+            match self.block()? {
+                Some((SCHEMA, bytes)) => {
+                    let (_, schema) = decode_schema_block(&bytes)?;
+                    schemas.push(schema);
+                }
+                _ => return Err(invalid("index does not point to a schema")),
+            }
+        }
+
+        self.schemas = schemas;
+        self.index = Some(index);
+        self.reader.seek(SeekFrom::Start(resume))?;
+        Ok(true)
+    }
+
+    /// Number of records in the log, if its index has been loaded
+    pub fn record_count(&self) -> Option<usize> {
+        self.index.as_ref().map(|index| index.records.len())
+    }
+
+    /// Continue reading from the record with the given index. The log's index must have been
+    /// loaded.
+    pub fn seek(&mut self, record: usize) -> Result<(), KobbleError> {
+        let index = self
+            .index
+            .as_ref()
+            .ok_or_else(|| KobbleError::custom("The index of the log has not been loaded"))?;
+
+        match index.records.get(record) {
+            Some(&(position, schema)) => {
+                self.reader.seek(SeekFrom::Start(position))?;
+                self.current = Some(schema);
+                self.done = false;
+            }
+            None => {
+                self.done = true;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for Reader<R> {
+    type Item = Result<(SchemaId, DynamicValue), KobbleError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Reader, Writer};
+    use crate::{from_dynamic, to_dynamic, DynamicValue, KobbleError, Schema};
+    use serde::{Deserialize, Serialize};
+    use std::io::Cursor;
+
+    mod v1 {
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        pub struct Transform {
+            pub x: f32,
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Transform {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Event {
+        Spawn(u32),
+        Despawn(u32),
+    }
+
+    /// Records alternating between three schemas
+    fn session() -> Writer<Vec<u8>> {
+        let mut writer = Writer::new(vec![]).unwrap();
+
+        assert_eq!(writer.set_type::<v1::Transform>().unwrap(), 0);
+        writer
+            .write(&to_dynamic(&v1::Transform { x: 1. }).unwrap())
+            .unwrap();
+        writer
+            .write(&to_dynamic(&v1::Transform { x: 2. }).unwrap())
+            .unwrap();
+
+        assert_eq!(writer.set_type::<Event>().unwrap(), 1);
+        writer
+            .write(&to_dynamic(&Event::Spawn(7)).unwrap())
+            .unwrap();
+
+        assert_eq!(writer.set_type::<Transform>().unwrap(), 2);
+        writer
+            .write(&to_dynamic(&Transform { x: 3., y: 4. }).unwrap())
+            .unwrap();
+
+        assert_eq!(writer.set_type::<Event>().unwrap(), 1);
+        writer
+            .write(&to_dynamic(&Event::Despawn(7)).unwrap())
+            .unwrap();
+
+        writer
+    }
+
+    fn expected() -> Vec<(u32, DynamicValue)> {
+        vec![
+            (0, to_dynamic(&v1::Transform { x: 1. }).unwrap()),
+            (0, to_dynamic(&v1::Transform { x: 2. }).unwrap()),
+            (1, to_dynamic(&Event::Spawn(7)).unwrap()),
+            (2, to_dynamic(&Transform { x: 3., y: 4. }).unwrap()),
+            (1, to_dynamic(&Event::Despawn(7)).unwrap()),
+        ]
+    }
+
+    #[test]
+    fn test_sequential() {
+        let bytes = session().finish().unwrap();
+        let mut reader = Reader::new(bytes.as_slice()).unwrap();
+
+        let records: Vec<_> = reader.by_ref().map(Result::unwrap).collect();
+        assert_eq!(records, expected());
+        assert_eq!(reader.schema(1), Some(&Schema::infer::<Event>()));
+
+        let transform: Transform = from_dynamic(&records[3].1).unwrap();
+        assert_eq!(transform, Transform { x: 3., y: 4. });
+    }
+
+    #[test]
+    fn test_unfinished() {
+        // A log which was cut off before the index was written can still be read
+        let bytes = session().writer.writer;
+        let records: Vec<_> = Reader::new(bytes.as_slice())
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(records, expected());
+
+        let mut reader = Reader::new(Cursor::new(bytes)).unwrap();
+        assert!(!reader.load_index().unwrap());
+        assert_eq!(reader.record_count(), None);
+        assert!(reader.seek(2).is_err());
+    }
+
+    #[test]
+    fn test_seek() {
+        let bytes = session().finish().unwrap();
+        let mut reader = Reader::new(Cursor::new(bytes)).unwrap();
+        assert!(reader.load_index().unwrap());
+        assert_eq!(reader.record_count(), Some(5));
+
+        for start in [3, 0, 4, 2] {
+            reader.seek(start).unwrap();
+            let records: Vec<_> = reader.by_ref().map(Result::unwrap).collect();
+            assert_eq!(records, expected()[start..]);
+        }
+
+        reader.seek(5).unwrap();
+        assert_eq!(reader.read().unwrap(), None);
+    }
+
+    #[test]
+    fn test_write_mismatch() {
+        let mut writer = Writer::new(vec![]).unwrap();
+        assert!(writer.write(&DynamicValue::Unit).is_err());
+
+        writer.set_type::<Event>().unwrap();
+        let err = writer
+            .write(&to_dynamic(&Transform { x: 3., y: 4. }).unwrap())
+            .unwrap_err();
+        assert!(matches!(err, KobbleError::InvalidValue { .. }));
+
+        // Nothing was written for the rejected record
+        let bytes = writer.finish().unwrap();
+        assert_eq!(Reader::new(bytes.as_slice()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_deep_schema() {
+        let mut schema = Schema::Unit;
+        for _ in 0..200 {
+            schema = Schema::Option(Box::new(schema));
+        }
+
+        let mut writer = Writer::new(vec![]).unwrap();
+        writer.set_schema(&schema).unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let mut reader = Reader::new(bytes.as_slice()).unwrap();
+        assert!(matches!(
+            reader.read(),
+            Err(KobbleError::InvalidFormat { .. })
+        ));
+
+        let mut reader = Reader::new(Cursor::new(bytes)).unwrap();
+        assert!(matches!(
+            reader.load_index(),
+            Err(KobbleError::InvalidFormat { .. })
+        ));
+    }
+}
@@ -0,0 +1,527 @@
+use crate::default_value::default_value_in;
+use crate::error::KobbleError;
+use crate::path::{PathSegment, ValuePath};
+use crate::scope::Scopes;
+use crate::validate::{schema_kind, value_kind, variant_kind};
+use crate::{DynamicValue, Schema, StructSchema, VariantSchema, VariantValue};
+
+/// Convert a value written under the schema `from` into one conforming to the schema `to`.
+///
+/// * Struct fields are matched by name. Fields missing from `value` are filled in with their
+///   default value (see `Schema::default_value`), and fields missing from `to` are dropped
+/// * Enum variants are matched by name, and take on their index in `to`
+/// * Integers may be widened to types which can hold every value of the old type, and `f32`
+///   may become `f64`
+/// * A value may become `Some` of itself
+///
+/// Anything else must be the same in both schemas, apart from type names.
+pub fn migrate(
+    value: DynamicValue,
+    from: &Schema,
+    to: &Schema,
+) -> Result<DynamicValue, KobbleError> {
+    if let Err(errors) = from.validate(&value) {
+        return Err(KobbleError::InvalidValue {
+            path: ValuePath::root(),
+            errors,
+        });
+    }
+
+    let mut migrator = Migrator {
+        scopes: Scopes::default(),
+        path: ValuePath::root(),
+    };
+    migrator.value(to, value)
+}
+
+struct Migrator<'a> {
+    /// Scopes of the recursive schemas in `to`
+    scopes: Scopes<'a>,
+    path: ValuePath,
+}
+
+impl<'a> Migrator<'a> {
+    fn error(&self, error: KobbleError) -> KobbleError {
+        error.at(self.path.clone())
+    }
+
+    fn mismatch(&self, expected: impl Into<String>, found: impl Into<String>) -> KobbleError {
+        self.error(KobbleError::SchemaMismatch {
+            path: ValuePath::root(),
+            expected: expected.into(),
+            found: found.into(),
+        })
+    }
+
+    /// Migrate a value nested one step further in
+    fn nested(
+        &mut self,
+        segment: PathSegment,
+        schema: &'a Schema,
+        value: DynamicValue,
+    ) -> Result<DynamicValue, KobbleError> {
+        self.path.push(segment);
+        let result = self.value(schema, value);
+        self.path.0.pop();
+        result
+    }
+
+    fn lookup(&self, name: &str) -> Result<&'a Schema, KobbleError> {
+        self.scopes.lookup(name).map_err(|e| self.error(e))
+    }
+
+    /// Default value of a schema which may refer to the definitions we are inside of
+    fn default_value(&self, schema: &'a Schema) -> Result<DynamicValue, KobbleError> {
+        default_value_in(schema, &self.scopes).map_err(|e| self.error(e))
+    }
+
+    fn value(
+        &mut self,
+        schema: &'a Schema,
+        value: DynamicValue,
+    ) -> Result<DynamicValue, KobbleError> {
+        Ok(match (schema, value) {
+            (Schema::Recursive { definitions, root }, value) => {
+                self.scopes.push(definitions);
+                let result = self.value(root, value);
+                self.scopes.pop();
+                return result;
+            }
+            (Schema::Ref(name), value) => return self.value(self.lookup(name)?, value),
+            (Schema::Option(inner), DynamicValue::Option(value)) => DynamicValue::Option(
+                value
+                    .map(|value| self.value(inner, *value).map(Box::new))
+                    .transpose()?,
+            ),
+            (Schema::Option(inner), value) => {
+                DynamicValue::Option(Some(Box::new(self.value(inner, value)?)))
+            }
+            (Schema::Struct(schema), DynamicValue::Struct { fields, .. }) => DynamicValue::Struct {
+                name: schema.name.clone(),
+                fields: self.fields(schema, fields)?,
+            },
+            (Schema::Tuple(schemas), DynamicValue::Tuple(values)) => {
+                DynamicValue::Tuple(self.elements(schemas, values)?)
+            }
+            (Schema::TupleStruct(name, schemas), DynamicValue::TupleStruct(_, values)) => {
+                DynamicValue::TupleStruct(name.clone(), self.elements(schemas, values)?)
+            }
+            (Schema::NewtypeStruct(name, inner), DynamicValue::NewtypeStruct(_, value)) => {
+                DynamicValue::NewtypeStruct(
+                    name.clone(),
+                    Box::new(self.nested(PathSegment::Index(0), inner, *value)?),
+                )
+            }
+            (Schema::UnitStruct(name), DynamicValue::UnitStruct(_)) => {
+                DynamicValue::UnitStruct(name.clone())
+            }
+            (Schema::UniformSequence(inner), DynamicValue::UniformSequence(values)) => {
+                DynamicValue::UniformSequence(
+                    values
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, value)| self.nested(PathSegment::Index(i), inner, value))
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            (Schema::Map { key, value: inner }, DynamicValue::Map(entries)) => DynamicValue::Map(
+                entries
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, (k, v))| {
+                        Ok((
+                            self.nested(PathSegment::Key(i), key, k)?,
+                            self.nested(PathSegment::Index(i), inner, v)?,
+                        ))
+                    })
+                    .collect::<Result<_, KobbleError>>()?,
+            ),
+            (Schema::Enum(schema), DynamicValue::Enum { variant, value, .. }) => {
+                let Some((index, (_, variant_schema))) = schema
+                    .variants
+                    .iter()
+                    .enumerate()
+                    .find(|(_, (name, _))| *name == variant)
+                else {
+                    return Err(self.mismatch(
+                        format!("a variant of enum {}", schema.name),
+                        format!("variant {}", variant),
+                    ));
+                };
+
+                let value = match (variant_schema, value) {
+                    (VariantSchema::Unit, VariantValue::Unit) => VariantValue::Unit,
+                    (VariantSchema::Newtype(inner), VariantValue::Newtype(value)) => {
+                        VariantValue::Newtype(Box::new(self.nested(
+                            PathSegment::Index(0),
+                            inner,
+                            *value,
+                        )?))
+                    }
+                    (VariantSchema::Tuple(schemas), VariantValue::Tuple(values)) => {
+                        VariantValue::Tuple(self.elements(schemas, values)?)
+                    }
+                    (VariantSchema::Struct(schema), VariantValue::Struct(fields)) => {
+                        VariantValue::Struct(self.fields(schema, fields)?)
+                    }
+                    (schema, value) => {
+                        return Err(self.mismatch(variant_kind(schema), value.kind()))
+                    }
+                };
+
+                DynamicValue::Enum {
+                    name: schema.name.clone(),
+                    variant,
+                    index: index as u32,
+                    value,
+                }
+            }
+            (Schema::I8, value @ DynamicValue::I8(_))
+            | (Schema::U8, value @ DynamicValue::U8(_))
+            | (Schema::I16, value @ DynamicValue::I16(_))
+            | (Schema::U16, value @ DynamicValue::U16(_))
+            | (Schema::I32, value @ DynamicValue::I32(_))
+            | (Schema::U32, value @ DynamicValue::U32(_))
+            | (Schema::I64, value @ DynamicValue::I64(_))
+            | (Schema::U64, value @ DynamicValue::U64(_))
+            | (Schema::I128, value @ DynamicValue::I128(_))
+            | (Schema::U128, value @ DynamicValue::U128(_))
+            | (Schema::F32, value @ DynamicValue::F32(_))
+            | (Schema::F64, value @ DynamicValue::F64(_))
+            | (Schema::Bool, value @ DynamicValue::Bool(_))
+            | (Schema::Char, value @ DynamicValue::Char(_))
+            | (Schema::Unit, value @ DynamicValue::Unit)
+            | (Schema::Bytes, value @ DynamicValue::Bytes(_))
+            | (Schema::String, value @ DynamicValue::String(_)) => value,
+            (schema, value) => widen(schema, &value)
+                .ok_or_else(|| self.mismatch(schema_kind(schema), value_kind(&value)))?,
+        })
+    }
+
+    fn fields(
+        &mut self,
+        schema: &'a StructSchema,
+        mut fields: Vec<(String, DynamicValue)>,
+    ) -> Result<Vec<(String, DynamicValue)>, KobbleError> {
+        schema
+            .fields
+            .iter()
+            .map(|(name, field)| {
+                let value = match fields.iter().position(|(n, _)| n == name) {
+                    Some(i) => {
+                        let value = fields.swap_remove(i).1;
+                        self.nested(PathSegment::Field(name.clone()), field, value)?
+                    }
+                    None => {
+                        self.path.push(PathSegment::Field(name.clone()));
+                        let value = self.default_value(field);
+                        self.path.0.pop();
+                        value?
+                    }
+                };
+                Ok((name.clone(), value))
+            })
+            .collect()
+    }
+
+    fn elements(
+        &mut self,
+        schemas: &'a [Schema],
+        values: Vec<DynamicValue>,
+    ) -> Result<Vec<DynamicValue>, KobbleError> {
+        if schemas.len() != values.len() {
+            return Err(self.mismatch(
+                format!("{} elements", schemas.len()),
+                format!("{} elements", values.len()),
+            ));
+        }
+
+        schemas
+            .iter()
+            .zip(values)
+            .enumerate()
+            .map(|(i, (schema, value))| self.nested(PathSegment::Index(i), schema, value))
+            .collect()
+    }
+}
+
+/// Whether the integer type is signed, and its width in bits
+fn int_type(schema: &Schema) -> Option<(bool, u32)> {
+    Some(match schema {
+        Schema::I8 => (true, 8),
+        Schema::U8 => (false, 8),
+        Schema::I16 => (true, 16),
+        Schema::U16 => (false, 16),
+        Schema::I32 => (true, 32),
+        Schema::U32 => (false, 32),
+        Schema::I64 => (true, 64),
+        Schema::U64 => (false, 64),
+        Schema::I128 => (true, 128),
+        Schema::U128 => (false, 128),
+        _ => return None,
+    })
+}
+
+/// Whether every value of the type `from` can be converted to the type `to` without losing
+/// information
+pub(crate) fn widens(from: &Schema, to: &Schema) -> bool {
+    if let (Some((from_signed, from_bits)), Some((to_signed, to_bits))) =
+        (int_type(from), int_type(to))
+    {
+        return match (from_signed, to_signed) {
+            (true, true) | (false, false) => to_bits >= from_bits,
+            (false, true) => to_bits > from_bits,
+            (true, false) => false,
+        };
+    }
+
+    matches!((from, to), (Schema::F32, Schema::F64))
+}
+
+/// Convert an integer or float to the given type, if no information is lost in doing so
+fn widen(schema: &Schema, value: &DynamicValue) -> Option<DynamicValue> {
+    let (from, v): (Schema, i128) = match *value {
+        DynamicValue::I8(v) => (Schema::I8, v.into()),
+        DynamicValue::U8(v) => (Schema::U8, v.into()),
+        DynamicValue::I16(v) => (Schema::I16, v.into()),
+        DynamicValue::U16(v) => (Schema::U16, v.into()),
+        DynamicValue::I32(v) => (Schema::I32, v.into()),
+        DynamicValue::U32(v) => (Schema::U32, v.into()),
+        DynamicValue::I64(v) => (Schema::I64, v.into()),
+        DynamicValue::U64(v) => (Schema::U64, v.into()),
+        DynamicValue::F32(v) if *schema == Schema::F64 => return Some(DynamicValue::F64(v.into())),
+        _ => return None,
+    };
+
+    if !widens(&from, schema) {
+        return None;
+    }
+
+    Some(match schema {
+        Schema::I8 => DynamicValue::I8(v.try_into().ok()?),
+        Schema::U8 => DynamicValue::U8(v.try_into().ok()?),
+        Schema::I16 => DynamicValue::I16(v.try_into().ok()?),
+        Schema::U16 => DynamicValue::U16(v.try_into().ok()?),
+        Schema::I32 => DynamicValue::I32(v.try_into().ok()?),
+        Schema::U32 => DynamicValue::U32(v.try_into().ok()?),
+        Schema::I64 => DynamicValue::I64(v.try_into().ok()?),
+        Schema::U64 => DynamicValue::U64(v.try_into().ok()?),
+        Schema::I128 => DynamicValue::I128(v),
+        Schema::U128 => DynamicValue::U128(v.try_into().ok()?),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::migrate;
+    use crate::{from_dynamic, to_dynamic, KobbleError, Schema, StructSchema, ValuePath};
+    use serde::{Deserialize, Serialize};
+
+    mod v1 {
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Serialize, Deserialize)]
+        pub struct Save {
+            pub name: String,
+            pub health: u8,
+            pub debug: bool,
+            pub class: Class,
+            pub items: Vec<Item>,
+        }
+
+        #[derive(Serialize, Deserialize)]
+        pub enum Class {
+            Warrior,
+            Mage { mana: u16 },
+        }
+
+        #[derive(Serialize, Deserialize)]
+        pub struct Item(pub u32, pub f32);
+    }
+
+    mod v2 {
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        pub struct Save {
+            pub class: Class,
+            pub name: String,
+            pub health: i32,
+            pub items: Vec<Item>,
+            pub friends: Vec<String>,
+            pub title: Option<String>,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        pub enum Class {
+            Rogue,
+            Mage { mana: u64, school: Option<u8> },
+            Warrior,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        pub struct Item(pub u64, pub f64);
+    }
+
+    #[test]
+    fn test_migrate_save() {
+        let old = v1::Save {
+            name: "Spoon".into(),
+            health: 200,
+            debug: true,
+            class: v1::Class::Mage { mana: 300 },
+            items: vec![v1::Item(4, 0.5), v1::Item(9, 2.)],
+        };
+
+        let value = migrate(
+            to_dynamic(&old).unwrap(),
+            &Schema::infer::<v1::Save>(),
+            &Schema::infer::<v2::Save>(),
+        )
+        .unwrap();
+        assert_eq!(Schema::infer::<v2::Save>().validate(&value), Ok(()));
+
+        assert_eq!(
+            from_dynamic::<v2::Save>(&value).unwrap(),
+            v2::Save {
+                class: v2::Class::Mage {
+                    mana: 300,
+                    school: None
+                },
+                name: "Spoon".into(),
+                health: 200,
+                items: vec![v2::Item(4, 0.5), v2::Item(9, 2.)],
+                friends: vec![],
+                title: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_migrate_into_option() {
+        let value = migrate(
+            to_dynamic(&vec![1u16, 2]).unwrap(),
+            &Schema::infer::<Vec<u16>>(),
+            &Schema::infer::<Vec<Option<u32>>>(),
+        )
+        .unwrap();
+        assert_eq!(
+            from_dynamic::<Vec<Option<u32>>>(&value).unwrap(),
+            vec![Some(1), Some(2)]
+        );
+    }
+
+    #[test]
+    fn test_migrate_recursive() {
+        #[derive(Serialize, Deserialize)]
+        struct OldNode {
+            value: u8,
+            children: Vec<OldNode>,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Node {
+            value: u16,
+            children: Vec<Node>,
+            parent: Option<Box<Node>>,
+        }
+
+        let old = OldNode {
+            value: 1,
+            children: vec![OldNode {
+                value: 2,
+                children: vec![],
+            }],
+        };
+
+        let value = migrate(
+            to_dynamic(&old).unwrap(),
+            &Schema::infer::<OldNode>(),
+            &Schema::infer::<Node>(),
+        )
+        .unwrap();
+        assert_eq!(
+            from_dynamic::<Node>(&value).unwrap(),
+            Node {
+                value: 1,
+                children: vec![Node {
+                    value: 2,
+                    children: vec![],
+                    parent: None,
+                }],
+                parent: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_migrate_errors() {
+        // Narrowing loses information
+        let err = migrate(
+            to_dynamic(&(1u8, 2u32)).unwrap(),
+            &Schema::infer::<(u8, u32)>(),
+            &Schema::infer::<(u8, u16)>(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            KobbleError::SchemaMismatch {
+                path: ValuePath::root().index(1),
+                expected: "u16".into(),
+                found: "u32".into(),
+            }
+        );
+
+        // Signed integers can't become unsigned
+        assert!(migrate(to_dynamic(&1i8).unwrap(), &Schema::I8, &Schema::U64).is_err());
+
+        // Variants which no longer exist
+        #[derive(Serialize, Deserialize)]
+        enum Old {
+            Gone,
+        }
+        let err = migrate(
+            to_dynamic(&Old::Gone).unwrap(),
+            &Schema::infer::<Old>(),
+            &Schema::infer::<v2::Class>(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, KobbleError::SchemaMismatch { .. }));
+
+        // The value must be described by the old schema, and every way it isn't is reported
+        let err = migrate(
+            to_dynamic(&(1u8, 2u8)).unwrap(),
+            &Schema::infer::<(String, bool)>(),
+            &Schema::infer::<(u8, u8)>(),
+        )
+        .unwrap_err();
+        let KobbleError::InvalidValue { errors, .. } = err else {
+            panic!("{:?}", err)
+        };
+        let paths: Vec<String> = errors.iter().map(|e| e.path().to_string()).collect();
+        assert_eq!(paths, ["[0]", "[1]"]);
+
+        // New fields without a finite default
+        let new = Schema::Struct(StructSchema {
+            name: "Node".into(),
+            fields: vec![("next".into(), Schema::Ref("Node".into()))],
+        });
+        let new = Schema::Recursive {
+            definitions: [("Node".to_string(), new)].into_iter().collect(),
+            root: Box::new(Schema::Ref("Node".into())),
+        };
+        let old = Schema::Struct(StructSchema {
+            name: "Node".into(),
+            fields: vec![],
+        });
+        let err = migrate(old.default_value(), &old, &new).unwrap_err();
+        assert_eq!(
+            err,
+            KobbleError::NoFiniteInstance {
+                path: ValuePath::root().field("next"),
+                name: "Node".into(),
+            }
+        );
+    }
+}
@@ -0,0 +1,485 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::KobbleError;
+use crate::path::{PathSegment, ValuePath};
+use crate::validate::value_kind;
+use crate::{DynamicValue, VariantValue};
+
+/// Changes which turn one DynamicValue into another, applied in order
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+pub struct Patch(pub Vec<PatchOp>);
+
+/// A single change to a DynamicValue. Paths are relative to the value the patch is applied to,
+/// and indices refer to sequences as they are after the preceding operations.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub enum PatchOp {
+    /// Replace the value at the path
+    Set {
+        path: ValuePath,
+        #[serde(with = "crate::tagged")]
+        value: DynamicValue,
+    },
+    /// Insert an element into a sequence, shifting the elements after it along
+    Insert {
+        path: ValuePath,
+        #[serde(with = "crate::tagged")]
+        value: DynamicValue,
+    },
+    /// Remove an element from a sequence
+    Remove { path: ValuePath },
+    /// Switch the enum at the path to another of its variants
+    SetVariant {
+        path: ValuePath,
+        variant: String,
+        index: u32,
+        #[serde(with = "crate::tagged::variant")]
+        value: VariantValue,
+    },
+}
+
+/// Find the changes which turn `old` into `new`
+pub fn diff(old: &DynamicValue, new: &DynamicValue) -> Patch {
+    let mut differ = Differ {
+        path: ValuePath::root(),
+        ops: vec![],
+    };
+    differ.value(old, new);
+    Patch(differ.ops)
+}
+
+impl Patch {
+    /// Apply each operation to `value` in turn. If one of them fails, the operations before
+    /// it remain applied.
+    pub fn apply(&self, value: &mut DynamicValue) -> Result<(), KobbleError> {
+        self.0.iter().try_for_each(|op| op.apply(value))
+    }
+
+    pub fn ops(&self) -> &[PatchOp] {
+        &self.0
+    }
+
+    /// Whether the patch leaves values unchanged
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl PatchOp {
+    /// Where the operation takes place
+    pub fn path(&self) -> &ValuePath {
+        match self {
+            Self::Set { path, .. }
+            | Self::Insert { path, .. }
+            | Self::Remove { path }
+            | Self::SetVariant { path, .. } => path,
+        }
+    }
+
+    pub fn apply(&self, target: &mut DynamicValue) -> Result<(), KobbleError> {
+        let not_found = || KobbleError::PathNotFound {
+            path: self.path().clone(),
+        };
+
+        match self {
+            Self::Set { path, value } => target.set(path, value.clone()).map(|_| ()),
+            Self::Insert { path, value } => target.insert(path, value.clone()),
+            Self::Remove { path } => target.remove(path).map(|_| ()).ok_or_else(not_found),
+            Self::SetVariant {
+                path,
+                variant,
+                index,
+                value,
+            } => match target
+                .get_mut(path)
+                .ok_or_else(not_found)?
+                .unwrap_option_mut()
+            {
+                DynamicValue::Enum {
+                    variant: old_variant,
+                    index: old_index,
+                    value: old_value,
+                    ..
+                } => {
+                    *old_variant = variant.clone();
+                    *old_index = *index;
+                    *old_value = value.clone();
+                    Ok(())
+                }
+                other => Err(KobbleError::SchemaMismatch {
+                    path: path.clone(),
+                    expected: "enum".into(),
+                    found: value_kind(other),
+                }),
+            },
+        }
+    }
+}
+
+struct Differ {
+    path: ValuePath,
+    ops: Vec<PatchOp>,
+}
+
+impl Differ {
+    fn set(&mut self, value: &DynamicValue) {
+        self.ops.push(PatchOp::Set {
+            path: self.path.clone(),
+            value: value.clone(),
+        });
+    }
+
+    /// Diff values nested one step further in
+    fn nested(&mut self, segment: PathSegment, old: &DynamicValue, new: &DynamicValue) {
+        self.path.push(segment);
+        self.value(old, new);
+        self.path.0.pop();
+    }
+
+    fn value(&mut self, old: &DynamicValue, new: &DynamicValue) {
+        if old == new {
+            return;
+        }
+
+        match (old, new) {
+            (DynamicValue::Option(Some(old_inner)), DynamicValue::Option(Some(new_inner))) => {
+                // Paths look through options, so setting the inner value at this path would
+                // replace the option itself. Set the whole option instead.
+                let start = self.ops.len();
+                self.value(old_inner, new_inner);
+
+                let replaces_inner = self.ops[start..].iter().any(|op| {
+                    matches!(op, PatchOp::Set { path, .. } if path.0.len() == self.path.0.len())
+                });
+                if replaces_inner {
+                    self.ops.truncate(start);
+                    self.set(new);
+                }
+            }
+            (
+                DynamicValue::Struct {
+                    name: old_name,
+                    fields: old_fields,
+                },
+                DynamicValue::Struct {
+                    name: new_name,
+                    fields: new_fields,
+                },
+            ) if old_name == new_name && same_names(old_fields, new_fields) => {
+                self.fields(old_fields, new_fields)
+            }
+            (DynamicValue::Tuple(old_values), DynamicValue::Tuple(new_values))
+                if old_values.len() == new_values.len() =>
+            {
+                self.elements(old_values, new_values)
+            }
+            (
+                DynamicValue::TupleStruct(old_name, old_values),
+                DynamicValue::TupleStruct(new_name, new_values),
+            ) if old_name == new_name && old_values.len() == new_values.len() => {
+                self.elements(old_values, new_values)
+            }
+            (
+                DynamicValue::NewtypeStruct(old_name, old_inner),
+                DynamicValue::NewtypeStruct(new_name, new_inner),
+            ) if old_name == new_name => self.nested(PathSegment::Index(0), old_inner, new_inner),
+            (
+                DynamicValue::UniformSequence(old_values),
+                DynamicValue::UniformSequence(new_values),
+            ) => self.sequence(old_values, new_values),
+            (DynamicValue::Map(old_entries), DynamicValue::Map(new_entries))
+                if old_entries.len() == new_entries.len()
+                    && old_entries
+                        .iter()
+                        .zip(new_entries)
+                        .all(|((old_key, _), (new_key, _))| old_key == new_key) =>
+            {
+                for (i, ((_, old_value), (_, new_value))) in
+                    old_entries.iter().zip(new_entries).enumerate()
+                {
+                    self.nested(PathSegment::Index(i), old_value, new_value);
+                }
+            }
+            (
+                DynamicValue::Enum {
+                    name: old_name,
+                    variant: old_variant,
+                    index: old_index,
+                    value: old_value,
+                },
+                DynamicValue::Enum {
+                    name: new_name,
+                    variant: new_variant,
+                    index: new_index,
+                    value: new_value,
+                },
+            ) if old_name == new_name => match (old_value, new_value) {
+                _ if old_variant != new_variant || old_index != new_index => {
+                    self.set_variant(new_variant, *new_index, new_value)
+                }
+                (VariantValue::Struct(old_fields), VariantValue::Struct(new_fields))
+                    if same_names(old_fields, new_fields) =>
+                {
+                    self.fields(old_fields, new_fields)
+                }
+                (VariantValue::Tuple(old_values), VariantValue::Tuple(new_values))
+                    if old_values.len() == new_values.len() =>
+                {
+                    self.elements(old_values, new_values)
+                }
+                (VariantValue::Newtype(old_inner), VariantValue::Newtype(new_inner)) => {
+                    self.nested(PathSegment::Index(0), old_inner, new_inner)
+                }
+                _ => self.set_variant(new_variant, *new_index, new_value),
+            },
+            _ => self.set(new),
+        }
+    }
+
+    fn set_variant(&mut self, variant: &str, index: u32, value: &VariantValue) {
+        self.ops.push(PatchOp::SetVariant {
+            path: self.path.clone(),
+            variant: variant.to_string(),
+            index,
+            value: value.clone(),
+        });
+    }
+
+    fn fields(&mut self, old: &[(String, DynamicValue)], new: &[(String, DynamicValue)]) {
+        for ((name, old_value), (_, new_value)) in old.iter().zip(new) {
+            self.nested(PathSegment::Field(name.clone()), old_value, new_value);
+        }
+    }
+
+    fn elements(&mut self, old: &[DynamicValue], new: &[DynamicValue]) {
+        for (i, (old_value, new_value)) in old.iter().zip(new).enumerate() {
+            self.nested(PathSegment::Index(i), old_value, new_value);
+        }
+    }
+
+    /// Diff the elements which changed in place, then remove or insert the rest. Elements
+    /// shared by the start and end of both sequences are left alone, so that a single insertion
+    /// or removal anywhere produces a single operation.
+    fn sequence(&mut self, old: &[DynamicValue], new: &[DynamicValue]) {
+        let prefix = old.iter().zip(new).take_while(|(o, n)| o == n).count();
+        let suffix = old[prefix..]
+            .iter()
+            .rev()
+            .zip(new[prefix..].iter().rev())
+            .take_while(|(o, n)| o == n)
+            .count();
+
+        let old = &old[prefix..old.len() - suffix];
+        let new = &new[prefix..new.len() - suffix];
+        let common = old.len().min(new.len());
+
+        for (i, (old_value, new_value)) in old.iter().zip(new).enumerate() {
+            self.nested(PathSegment::Index(prefix + i), old_value, new_value);
+        }
+
+        for _ in common..old.len() {
+            self.ops.push(PatchOp::Remove {
+                path: self.path.clone().index(prefix + common),
+            });
+        }
+
+        for (i, value) in new.iter().enumerate().skip(common) {
+            self.ops.push(PatchOp::Insert {
+                path: self.path.clone().index(prefix + i),
+                value: value.clone(),
+            });
+        }
+    }
+}
+
+/// Whether both lists of fields have the same names, in the same order
+fn same_names(a: &[(String, DynamicValue)], b: &[(String, DynamicValue)]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|((a, _), (b, _))| a == b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff, Patch, PatchOp};
+    use crate::{to_dynamic, DynamicValue, ValuePath};
+    use serde::Serialize;
+
+    #[derive(Serialize, Clone)]
+    struct Player {
+        name: String,
+        position: (f32, f32),
+        inventory: Vec<Item>,
+        target: Option<Target>,
+        state: State,
+    }
+
+    #[derive(Serialize, Clone, PartialEq)]
+    struct Item {
+        id: u32,
+        count: u16,
+    }
+
+    #[derive(Serialize, Clone)]
+    enum Target {
+        Player(String),
+        Point { x: f32, y: f32 },
+    }
+
+    #[derive(Serialize, Clone)]
+    enum State {
+        Idle,
+        Walking(f32),
+    }
+
+    fn player() -> Player {
+        Player {
+            name: "Spoon".into(),
+            position: (1., 2.),
+            inventory: vec![
+                Item { id: 1, count: 3 },
+                Item { id: 2, count: 1 },
+                Item { id: 3, count: 9 },
+            ],
+            target: Some(Target::Point { x: 0., y: 0. }),
+            state: State::Idle,
+        }
+    }
+
+    fn check(old: &Player, new: &Player) -> Patch {
+        let old = to_dynamic(old).unwrap();
+        let new = to_dynamic(new).unwrap();
+        let patch = diff(&old, &new);
+
+        let mut patched = old;
+        patch.apply(&mut patched).unwrap();
+        assert_eq!(patched, new);
+
+        patch
+    }
+
+    #[test]
+    fn test_identical() {
+        assert!(check(&player(), &player()).is_empty());
+    }
+
+    #[test]
+    fn test_nested_fields() {
+        let mut new = player();
+        new.position.1 = 5.;
+        new.inventory[1].count = 2;
+        new.target = Some(Target::Point { x: 0., y: 4. });
+
+        let patch = check(&player(), &new);
+        let paths: Vec<String> = patch.ops().iter().map(|op| op.path().to_string()).collect();
+        assert_eq!(paths, ["position[1]", "inventory[1].count", "target.y"]);
+    }
+
+    #[test]
+    fn test_sequence_edits() {
+        let mut new = player();
+        new.inventory.remove(1);
+        let patch = check(&player(), &new);
+        assert_eq!(
+            patch.ops(),
+            [PatchOp::Remove {
+                path: ValuePath::root().field("inventory").index(1)
+            }]
+        );
+
+        let mut new = player();
+        new.inventory.insert(0, Item { id: 7, count: 7 });
+        new.inventory.push(Item { id: 8, count: 8 });
+        check(&player(), &new);
+
+        let mut new = player();
+        new.inventory.clear();
+        check(&player(), &new);
+        check(&new, &player());
+    }
+
+    #[test]
+    fn test_variants() {
+        let mut new = player();
+        new.state = State::Walking(2.);
+        new.target = Some(Target::Player("Fork".into()));
+        let patch = check(&player(), &new);
+        assert!(patch
+            .ops()
+            .iter()
+            .all(|op| matches!(op, PatchOp::SetVariant { .. })));
+
+        // Options are set as a whole
+        let mut new = player();
+        new.target = None;
+        check(&player(), &new);
+        check(&new, &player());
+
+        let mut old = player();
+        old.target = Some(Target::Player("Fork".into()));
+        let mut new = player();
+        new.target = Some(Target::Player("Knife".into()));
+        check(&old, &new);
+    }
+
+    #[test]
+    fn test_serialized_patch() {
+        let mut new = player();
+        new.name = "Spork".into();
+        new.inventory.insert(1, Item { id: 5, count: 1 });
+        new.state = State::Walking(1.5);
+
+        let old = to_dynamic(&player()).unwrap();
+        let new = to_dynamic(&new).unwrap();
+        let patch = diff(&old, &new);
+
+        let bytes = bincode::serialize(&patch).unwrap();
+        let received: Patch = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(received, patch);
+
+        // Formats which name fields and variants read the same patch
+        let json = serde_json::to_string(&patch).unwrap();
+        assert_eq!(serde_json::from_str::<Patch>(&json).unwrap(), patch);
+
+        let mut patched = old;
+        received.apply(&mut patched).unwrap();
+        assert_eq!(patched, new);
+    }
+
+    #[test]
+    fn test_serialized_depth() {
+        let mut value = DynamicValue::Unit;
+        for _ in 0..crate::RECURSION_LIMIT {
+            value = DynamicValue::Option(Some(Box::new(value)));
+        }
+        let patch = |value| {
+            Patch(vec![PatchOp::Set {
+                path: ValuePath::root(),
+                value,
+            }])
+        };
+
+        // Values nested deeper than the recursion limit aren't read, rather than overflowing the
+        // stack
+        let bytes = bincode::serialize(&patch(value.clone())).unwrap();
+        assert!(bincode::deserialize::<Patch>(&bytes).is_err());
+
+        let DynamicValue::Option(Some(inner)) = value else {
+            unreachable!()
+        };
+        let bytes = bincode::serialize(&patch(*inner)).unwrap();
+        assert!(bincode::deserialize::<Patch>(&bytes).is_ok());
+    }
+
+    #[test]
+    fn test_apply_errors() {
+        let mut value = to_dynamic(&player()).unwrap();
+        let patch = Patch(vec![PatchOp::Remove {
+            path: ValuePath::root().field("inventory").index(5),
+        }]);
+        assert!(patch.apply(&mut value).is_err());
+
+        let patch = Patch(vec![PatchOp::Insert {
+            path: ValuePath::root().field("name").index(0),
+            value: DynamicValue::Unit,
+        }]);
+        assert!(patch.apply(&mut value).is_err());
+    }
+}
@@ -0,0 +1,552 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+use std::ops::{Index, IndexMut};
+use std::str::FromStr;
+
+use crate::error::KobbleError;
+use crate::{DynamicValue, VariantValue};
+
+/// Location of a value nested within another, e.g. `items[3].pos.x`
+#[derive(Debug, Clone, Default, Deserialize, Serialize, Hash, PartialEq, Eq)]
+pub struct ValuePath(pub Vec<PathSegment>);
+
+/// A single step along a ValuePath
+#[derive(Debug, Clone, Deserialize, Serialize, Hash, PartialEq, Eq)]
+pub enum PathSegment {
+    /// Field of a struct or struct variant
+    Field(String),
+    /// Element of a sequence, tuple or tuple variant, or the value of an entry of a map
+    Index(usize),
+    /// Key of an entry of a map
+    Key(usize),
+}
+
+impl ValuePath {
+    /// The empty path, referring to the value itself
+    pub fn root() -> Self {
+        Self::default()
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.0
+    }
+
+    /// Extend the path by one step
+    pub fn push(&mut self, segment: PathSegment) {
+        self.0.push(segment);
+    }
+
+    /// This path, extended by one field
+    pub fn field(mut self, name: impl Into<String>) -> Self {
+        self.push(PathSegment::Field(name.into()));
+        self
+    }
+
+    /// This path, extended by one index
+    pub fn index(mut self, index: usize) -> Self {
+        self.push(PathSegment::Index(index));
+        self
+    }
+
+    /// This path, extended by the key of a map entry
+    pub fn key(mut self, index: usize) -> Self {
+        self.push(PathSegment::Key(index));
+        self
+    }
+
+    /// The path without its last step, along with that step
+    pub fn split_last(&self) -> Option<(ValuePath, &PathSegment)> {
+        let (last, parent) = self.0.split_last()?;
+        Some((ValuePath(parent.to_vec()), last))
+    }
+}
+
+impl From<Vec<PathSegment>> for ValuePath {
+    fn from(segments: Vec<PathSegment>) -> Self {
+        Self(segments)
+    }
+}
+
+impl Display for ValuePath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                // Names which would not parse back are quoted, like `["a.b"]`
+                PathSegment::Field(name) if name.is_empty() || name.contains(['.', '[', ']']) => {
+                    write!(f, "[\"")?;
+                    for c in name.chars() {
+                        if matches!(c, '"' | '\\') {
+                            write!(f, "\\")?;
+                        }
+                        write!(f, "{}", c)?;
+                    }
+                    write!(f, "\"]")?
+                }
+                PathSegment::Field(name) if i == 0 => write!(f, "{}", name)?,
+                PathSegment::Field(name) => write!(f, ".{}", name)?,
+                PathSegment::Index(idx) => write!(f, "[{}]", idx)?,
+                PathSegment::Key(idx) => write!(f, "[key {}]", idx)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for ValuePath {
+    type Err = KobbleError;
+
+    /// Parse a path like `transform.position[2]` or `properties["a.b"]`
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut path = ValuePath::root();
+        let invalid = |path: &ValuePath| KobbleError::InvalidPath {
+            path: path.clone(),
+            text: text.to_string(),
+        };
+
+        let mut rest = text;
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix("[\"") {
+                let mut name = String::new();
+                let mut chars = after.char_indices();
+                let end = loop {
+                    match chars.next().ok_or_else(|| invalid(&path))? {
+                        (i, '"') => break i,
+                        (_, '\\') => name.push(chars.next().ok_or_else(|| invalid(&path))?.1),
+                        (_, c) => name.push(c),
+                    }
+                };
+
+                rest = after[end + 1..]
+                    .strip_prefix(']')
+                    .ok_or_else(|| invalid(&path))?;
+                path.push(PathSegment::Field(name));
+            } else if let Some(after) = rest.strip_prefix('[') {
+                let (index, after) = after.split_once(']').ok_or_else(|| invalid(&path))?;
+                let segment = match index.strip_prefix("key ") {
+                    Some(index) => PathSegment::Key(index.parse().map_err(|_| invalid(&path))?),
+                    None => PathSegment::Index(index.parse().map_err(|_| invalid(&path))?),
+                };
+                path.push(segment);
+                rest = after;
+            } else {
+                // Only the first field may go without a leading dot
+                let after = match rest.strip_prefix('.') {
+                    Some(after) => after,
+                    None if path.is_root() => rest,
+                    None => return Err(invalid(&path)),
+                };
+
+                let end = after.find(['.', '[', ']']).unwrap_or(after.len());
+                if end == 0 {
+                    return Err(invalid(&path));
+                }
+
+                path.push(PathSegment::Field(after[..end].to_string()));
+                rest = &after[end..];
+            }
+        }
+
+        Ok(path)
+    }
+}
+
+impl DynamicValue {
+    /// The value one step inside this one. Options are transparent, so that the path to the
+    /// contents of `Some` is the same as the path to the option itself.
+    fn child(&self, segment: &PathSegment) -> Option<&DynamicValue> {
+        match (self, segment) {
+            (DynamicValue::Option(Some(inner)), _) => inner.child(segment),
+            (DynamicValue::Struct { fields, .. }, PathSegment::Field(name))
+            | (
+                DynamicValue::Enum {
+                    value: VariantValue::Struct(fields),
+                    ..
+                },
+                PathSegment::Field(name),
+            ) => fields.iter().find(|(n, _)| n == name).map(|(_, v)| v),
+            (DynamicValue::Tuple(values), PathSegment::Index(i))
+            | (DynamicValue::TupleStruct(_, values), PathSegment::Index(i))
+            | (DynamicValue::UniformSequence(values), PathSegment::Index(i))
+            | (
+                DynamicValue::Enum {
+                    value: VariantValue::Tuple(values),
+                    ..
+                },
+                PathSegment::Index(i),
+            ) => values.get(*i),
+            (DynamicValue::NewtypeStruct(_, inner), PathSegment::Index(0))
+            | (
+                DynamicValue::Enum {
+                    value: VariantValue::Newtype(inner),
+                    ..
+                },
+                PathSegment::Index(0),
+            ) => Some(inner),
+            (DynamicValue::Map(entries), PathSegment::Index(i)) => entries.get(*i).map(|(_, v)| v),
+            (DynamicValue::Map(entries), PathSegment::Key(i)) => entries.get(*i).map(|(k, _)| k),
+            (DynamicValue::Map(entries), PathSegment::Field(name)) => entries
+                .iter()
+                .find(|(k, _)| matches!(k, DynamicValue::String(k) if k == name))
+                .map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn child_mut(&mut self, segment: &PathSegment) -> Option<&mut DynamicValue> {
+        match (self, segment) {
+            (DynamicValue::Option(Some(inner)), _) => inner.child_mut(segment),
+            (DynamicValue::Struct { fields, .. }, PathSegment::Field(name))
+            | (
+                DynamicValue::Enum {
+                    value: VariantValue::Struct(fields),
+                    ..
+                },
+                PathSegment::Field(name),
+            ) => fields.iter_mut().find(|(n, _)| n == name).map(|(_, v)| v),
+            (DynamicValue::Tuple(values), PathSegment::Index(i))
+            | (DynamicValue::TupleStruct(_, values), PathSegment::Index(i))
+            | (DynamicValue::UniformSequence(values), PathSegment::Index(i))
+            | (
+                DynamicValue::Enum {
+                    value: VariantValue::Tuple(values),
+                    ..
+                },
+                PathSegment::Index(i),
+            ) => values.get_mut(*i),
+            (DynamicValue::NewtypeStruct(_, inner), PathSegment::Index(0))
+            | (
+                DynamicValue::Enum {
+                    value: VariantValue::Newtype(inner),
+                    ..
+                },
+                PathSegment::Index(0),
+            ) => Some(inner),
+            (DynamicValue::Map(entries), PathSegment::Index(i)) => {
+                entries.get_mut(*i).map(|(_, v)| v)
+            }
+            (DynamicValue::Map(entries), PathSegment::Key(i)) => {
+                entries.get_mut(*i).map(|(k, _)| k)
+            }
+            (DynamicValue::Map(entries), PathSegment::Field(name)) => entries
+                .iter_mut()
+                .find(|(k, _)| matches!(k, DynamicValue::String(k) if k == name))
+                .map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// The value at `path`, if there is one
+    pub fn get(&self, path: &ValuePath) -> Option<&DynamicValue> {
+        path.0
+            .iter()
+            .try_fold(self, |value, segment| value.child(segment))
+    }
+
+    /// The value at `path`, if there is one
+    pub fn get_mut(&mut self, path: &ValuePath) -> Option<&mut DynamicValue> {
+        path.0
+            .iter()
+            .try_fold(self, |value, segment| value.child_mut(segment))
+    }
+
+    /// Replace the value at `path`, returning the old one. Setting the index just past the end
+    /// of a sequence, or a string key missing from a map, adds a new element instead.
+    pub fn set(
+        &mut self,
+        path: &ValuePath,
+        value: DynamicValue,
+    ) -> Result<Option<DynamicValue>, KobbleError> {
+        if let Some(old) = self.get_mut(path) {
+            return Ok(Some(std::mem::replace(old, value)));
+        }
+
+        let not_found = || KobbleError::PathNotFound { path: path.clone() };
+        let (parent, last) = path.split_last().ok_or_else(not_found)?;
+        let parent = self.get_mut(&parent).ok_or_else(not_found)?;
+
+        match (parent.unwrap_option_mut(), last) {
+            (DynamicValue::UniformSequence(values), PathSegment::Index(i))
+                if *i == values.len() =>
+            {
+                values.push(value)
+            }
+            (DynamicValue::Map(entries), PathSegment::Field(name)) => {
+                entries.push((DynamicValue::String(name.clone()), value))
+            }
+            _ => return Err(not_found()),
+        }
+
+        Ok(None)
+    }
+
+    /// Insert an element into a sequence at `path`, shifting the elements after it along
+    pub fn insert(&mut self, path: &ValuePath, value: DynamicValue) -> Result<(), KobbleError> {
+        let not_found = || KobbleError::PathNotFound { path: path.clone() };
+        let (parent, last) = path.split_last().ok_or_else(not_found)?;
+        let parent = self.get_mut(&parent).ok_or_else(not_found)?;
+
+        match (parent.unwrap_option_mut(), last) {
+            (DynamicValue::UniformSequence(values), PathSegment::Index(i))
+                if *i <= values.len() =>
+            {
+                values.insert(*i, value);
+                Ok(())
+            }
+            _ => Err(not_found()),
+        }
+    }
+
+    /// Remove the element or map entry at `path`, returning its value.
+    /// Only elements of sequences and entries of maps can be removed.
+    pub fn remove(&mut self, path: &ValuePath) -> Option<DynamicValue> {
+        let (parent, last) = path.split_last()?;
+        let parent = self.get_mut(&parent)?;
+
+        match (parent.unwrap_option_mut(), last) {
+            (DynamicValue::UniformSequence(values), PathSegment::Index(i)) if *i < values.len() => {
+                Some(values.remove(*i))
+            }
+            (DynamicValue::Map(entries), PathSegment::Index(i)) if *i < entries.len() => {
+                Some(entries.remove(*i).1)
+            }
+            (DynamicValue::Map(entries), PathSegment::Field(name)) => {
+                let i = entries
+                    .iter()
+                    .position(|(k, _)| matches!(k, DynamicValue::String(k) if k == name))?;
+                Some(entries.remove(i).1)
+            }
+            _ => None,
+        }
+    }
+
+    /// Look through any number of `Some`s
+    pub(crate) fn unwrap_option_mut(&mut self) -> &mut DynamicValue {
+        match self {
+            DynamicValue::Option(Some(inner)) => inner.unwrap_option_mut(),
+            other => other,
+        }
+    }
+}
+
+impl Index<&str> for DynamicValue {
+    type Output = DynamicValue;
+
+    fn index(&self, field: &str) -> &DynamicValue {
+        self.child(&PathSegment::Field(field.to_string()))
+            .unwrap_or_else(|| panic!("No field `{}` in value", field))
+    }
+}
+
+impl IndexMut<&str> for DynamicValue {
+    fn index_mut(&mut self, field: &str) -> &mut DynamicValue {
+        self.child_mut(&PathSegment::Field(field.to_string()))
+            .unwrap_or_else(|| panic!("No field `{}` in value", field))
+    }
+}
+
+impl Index<usize> for DynamicValue {
+    type Output = DynamicValue;
+
+    fn index(&self, index: usize) -> &DynamicValue {
+        self.child(&PathSegment::Index(index))
+            .unwrap_or_else(|| panic!("No element {} in value", index))
+    }
+}
+
+impl IndexMut<usize> for DynamicValue {
+    fn index_mut(&mut self, index: usize) -> &mut DynamicValue {
+        self.child_mut(&PathSegment::Index(index))
+            .unwrap_or_else(|| panic!("No element {} in value", index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ValuePath;
+    use crate::{to_dynamic, DynamicValue, KobbleError};
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize)]
+    struct Entity {
+        transform: Transform,
+        children: Vec<u32>,
+        properties: BTreeMap<String, i32>,
+        light: Option<Light>,
+    }
+
+    #[derive(Serialize)]
+    struct Transform {
+        position: [f32; 3],
+    }
+
+    #[derive(Serialize)]
+    enum Light {
+        Spot { angle: f32 },
+    }
+
+    fn entity() -> DynamicValue {
+        to_dynamic(&Entity {
+            transform: Transform {
+                position: [1., 2., 3.],
+            },
+            children: vec![4, 5],
+            properties: [("health".to_string(), 10)].into_iter().collect(),
+            light: Some(Light::Spot { angle: 0.5 }),
+        })
+        .unwrap()
+    }
+
+    fn path(text: &str) -> ValuePath {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let parsed = path("transform.position[2]");
+        assert_eq!(
+            parsed,
+            ValuePath::root()
+                .field("transform")
+                .field("position")
+                .index(2)
+        );
+        assert_eq!(parsed.to_string(), "transform.position[2]");
+
+        assert_eq!(
+            path("[0][1].x"),
+            ValuePath::root().index(0).index(1).field("x")
+        );
+        assert_eq!(path(""), ValuePath::root());
+
+        let key = ValuePath::root().field("properties").key(0);
+        assert_eq!(key.to_string(), "properties[key 0]");
+        assert_eq!(path("properties[key 0]"), key);
+
+        // Names which contain separators are quoted
+        let quoted = ValuePath::root()
+            .field("a.b")
+            .field("c[0]")
+            .field("")
+            .field("say \"hi\"")
+            .field("\"hi\\.\"");
+        assert_eq!(
+            quoted.to_string(),
+            r#"["a.b"]["c[0]"][""].say "hi"["\"hi\\.\""]"#
+        );
+        assert_eq!(path(&quoted.to_string()), quoted);
+        assert_eq!(
+            path(r#"x["y"].z"#),
+            ValuePath::root().field("x").field("y").field("z")
+        );
+
+        for invalid in [
+            "a..b",
+            "a[\"b",
+            "a[\"b\"",
+            "a[\"b\"c]",
+            "a[",
+            "a[x]",
+            "a]",
+            ".",
+            "a[1]b",
+            "a[key]",
+            "a[key x]",
+        ] {
+            assert!(
+                matches!(
+                    invalid.parse::<ValuePath>(),
+                    Err(KobbleError::InvalidPath { .. })
+                ),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_get() {
+        let value = entity();
+        assert_eq!(
+            value.get(&path("transform.position[2]")),
+            Some(&DynamicValue::F32(3.))
+        );
+        assert_eq!(
+            value.get(&path("properties.health")),
+            Some(&DynamicValue::I32(10))
+        );
+        assert_eq!(
+            value.get(&path("light.angle")),
+            Some(&DynamicValue::F32(0.5))
+        );
+        assert_eq!(value.get(&path("children[2]")), None);
+        assert_eq!(value.get(&path("transform.rotation")), None);
+
+        assert_eq!(value["children"][1], DynamicValue::U32(5));
+        assert_eq!(value["transform"]["position"][0], DynamicValue::F32(1.));
+    }
+
+    #[test]
+    fn test_mutate() {
+        let mut value = entity();
+
+        value["transform"]["position"][0] = DynamicValue::F32(9.);
+        *value.get_mut(&path("light.angle")).unwrap() = DynamicValue::F32(1.);
+
+        let old = value
+            .set(&path("children[0]"), DynamicValue::U32(7))
+            .unwrap();
+        assert_eq!(old, Some(DynamicValue::U32(4)));
+
+        // Appending to sequences and maps
+        assert_eq!(
+            value.set(&path("children[2]"), DynamicValue::U32(6)),
+            Ok(None)
+        );
+        assert_eq!(
+            value.set(&path("properties.armor"), DynamicValue::I32(3)),
+            Ok(None)
+        );
+        assert_eq!(
+            value.set(&path("children[5]"), DynamicValue::U32(0)),
+            Err(KobbleError::PathNotFound {
+                path: path("children[5]")
+            })
+        );
+
+        assert_eq!(
+            value.remove(&path("children[1]")),
+            Some(DynamicValue::U32(5))
+        );
+        assert_eq!(
+            value.remove(&path("properties.health")),
+            Some(DynamicValue::I32(10))
+        );
+        assert_eq!(value.remove(&path("transform.position")), None);
+
+        assert_eq!(
+            value.get(&path("transform.position[0]")),
+            Some(&DynamicValue::F32(9.))
+        );
+        assert_eq!(
+            value.get(&path("light.angle")),
+            Some(&DynamicValue::F32(1.))
+        );
+        assert_eq!(
+            value["children"],
+            DynamicValue::UniformSequence(vec![DynamicValue::U32(7), DynamicValue::U32(6)])
+        );
+        assert_eq!(
+            value["properties"],
+            DynamicValue::Map(vec![(
+                DynamicValue::String("armor".into()),
+                DynamicValue::I32(3)
+            )])
+        );
+    }
+}
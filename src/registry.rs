@@ -0,0 +1,194 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::error::KobbleError;
+use crate::path::ValuePath;
+use crate::{deserialize_dynamic, DynamicValue, Schema, SchemaSeed};
+
+/// Compact identifier of a schema within a SchemaRegistry
+pub type SchemaId = u32;
+
+/// A set of schemas, each assigned an ID in the order it was first registered. Registering a
+/// schema which is already present returns its existing ID.
+///
+/// Serializes as the list of schemas, so that IDs stay the same when loaded back.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(from = "Vec<Schema>", into = "Vec<Schema>")]
+pub struct SchemaRegistry {
+    schemas: Vec<Schema>,
+    ids: HashMap<[u8; 32], SchemaId>,
+}
+
+impl SchemaRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a schema, returning its ID
+    pub fn register(&mut self, schema: Schema) -> SchemaId {
+        let fingerprint = schema.fingerprint();
+        if let Some(&id) = self.ids.get(&fingerprint) {
+            return id;
+        }
+
+        let id = self.schemas.len() as SchemaId;
+        self.schemas.push(schema);
+        self.ids.insert(fingerprint, id);
+        id
+    }
+
+    /// Add the schema of `T`, returning its ID
+    pub fn register_type<'de, T: Deserialize<'de>>(&mut self) -> SchemaId {
+        self.register(Schema::infer::<T>())
+    }
+
+    pub fn get(&self, id: SchemaId) -> Option<&Schema> {
+        self.schemas.get(id as usize)
+    }
+
+    /// The ID of the schema, if it has been registered
+    pub fn id_of(&self, schema: &Schema) -> Option<SchemaId> {
+        self.id_by_fingerprint(&schema.fingerprint())
+    }
+
+    /// The ID of the schema with the given fingerprint, if it has been registered
+    pub fn id_by_fingerprint(&self, fingerprint: &[u8; 32]) -> Option<SchemaId> {
+        self.ids.get(fingerprint).copied()
+    }
+
+    /// Seed which deserializes data described by the schema with the given ID
+    pub fn seed(&self, id: SchemaId) -> Option<SchemaSeed<'_>> {
+        self.get(id).map(SchemaSeed::new)
+    }
+
+    /// Construct a DynamicValue described by the schema with the given ID, using the given
+    /// deserializer
+    pub fn decode<'de, D>(&self, id: SchemaId, deser: D) -> Result<DynamicValue, KobbleError>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let schema = self.get(id).ok_or(KobbleError::UnknownSchema {
+            path: ValuePath::root(),
+            id,
+        })?;
+        deserialize_dynamic(schema, deser)
+    }
+
+    /// Every schema, along with its ID
+    pub fn iter(&self) -> impl Iterator<Item = (SchemaId, &Schema)> {
+        self.schemas
+            .iter()
+            .enumerate()
+            .map(|(id, schema)| (id as SchemaId, schema))
+    }
+
+    pub fn len(&self) -> usize {
+        self.schemas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.schemas.is_empty()
+    }
+}
+
+impl From<Vec<Schema>> for SchemaRegistry {
+    /// Registers each schema in turn. Duplicates keep their place, but only the first of them is
+    /// found by `id_of`
+    fn from(schemas: Vec<Schema>) -> Self {
+        let mut ids = HashMap::new();
+        for (id, schema) in schemas.iter().enumerate() {
+            ids.entry(schema.fingerprint()).or_insert(id as SchemaId);
+        }
+
+        Self { schemas, ids }
+    }
+}
+
+impl From<SchemaRegistry> for Vec<Schema> {
+    fn from(registry: SchemaRegistry) -> Self {
+        registry.schemas
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SchemaRegistry;
+    use crate::{from_dynamic, KobbleError, Schema};
+    use bincode::Options;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Join {
+        name: String,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Input {
+        Move(f32, f32),
+        Jump,
+    }
+
+    fn decode(registry: &SchemaRegistry, id: u32, bytes: &[u8]) -> crate::DynamicValue {
+        let mut deser = bincode::Deserializer::from_slice(
+            bytes,
+            bincode::DefaultOptions::new().with_fixint_encoding(),
+        );
+        registry.decode(id, &mut deser).unwrap()
+    }
+
+    #[test]
+    fn test_register() {
+        let mut registry = SchemaRegistry::new();
+        assert_eq!(registry.register_type::<Join>(), 0);
+        assert_eq!(registry.register_type::<Input>(), 1);
+        assert_eq!(registry.register_type::<Join>(), 0);
+        assert_eq!(registry.register(Schema::infer::<Input>()), 1);
+        assert_eq!(registry.len(), 2);
+
+        assert_eq!(registry.id_of(&Schema::infer::<Input>()), Some(1));
+        assert_eq!(registry.id_of(&Schema::String), None);
+        assert_eq!(registry.get(0), Some(&Schema::infer::<Join>()));
+    }
+
+    #[test]
+    fn test_reload_and_decode() {
+        let mut registry = SchemaRegistry::new();
+        let join = registry.register_type::<Join>();
+        let input = registry.register_type::<Input>();
+
+        let saved = bincode::serialize(&registry).unwrap();
+        let registry: SchemaRegistry = bincode::deserialize(&saved).unwrap();
+        assert_eq!(registry.id_of(&Schema::infer::<Input>()), Some(input));
+
+        let messages = [
+            (input, bincode::serialize(&Input::Move(1., -1.)).unwrap()),
+            (
+                join,
+                bincode::serialize(&Join {
+                    name: "Spoon".into(),
+                })
+                .unwrap(),
+            ),
+        ];
+
+        let value = decode(&registry, messages[0].0, &messages[0].1);
+        assert_eq!(from_dynamic::<Input>(&value).unwrap(), Input::Move(1., -1.));
+
+        let value = decode(&registry, messages[1].0, &messages[1].1);
+        assert_eq!(
+            from_dynamic::<Join>(&value).unwrap(),
+            Join {
+                name: "Spoon".into()
+            }
+        );
+
+        let mut deser = bincode::Deserializer::from_slice(
+            &messages[0].1,
+            bincode::DefaultOptions::new().with_fixint_encoding(),
+        );
+        assert!(matches!(
+            registry.decode(7, &mut deser),
+            Err(KobbleError::UnknownSchema { id: 7, .. })
+        ));
+    }
+}
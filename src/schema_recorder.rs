@@ -1,27 +1,379 @@
-use crate::error::GenericError;
-use crate::{EnumSchema, Schema, StructSchema};
-use serde::de::{self, EnumAccess, IntoDeserializer, SeqAccess, VariantAccess, Visitor};
+use crate::error::KobbleError;
+use crate::path::{PathSegment, ValuePath};
+use crate::scope::reserve;
+use crate::{Definitions, EnumSchema, Schema, StructSchema, VariantSchema};
+use serde::de::value::U32Deserializer;
+use serde::de::{self, EnumAccess, IntoDeserializer, MapAccess, SeqAccess, VariantAccess, Visitor};
 use serde::{Deserialize, Deserializer};
+use std::collections::{HashMap, HashSet};
 
 /// Use the given struct to record a schema
-pub fn record_schema<'de, T: Deserialize<'de>>() -> Result<Schema, GenericError> {
-    let mut rec = SchemaRecorder::new();
-    T::deserialize(&mut rec)?;
-    Ok(rec.0.remove(0))
+pub fn record_schema<'de, T: Deserialize<'de>>() -> Result<Schema, KobbleError> {
+    // A visitor is consumed by visiting a single enum variant, so the type is recorded several
+    // times over. Each pass takes a different path through the variant tree, until every
+    // variant of every enum has been seen.
+    let mut state = RecorderState::default();
+    loop {
+        state.begin_pass();
+        let discovered = state.discovered();
+
+        let mut rec = SchemaRecorder::new(&mut state);
+        let result = T::deserialize(&mut rec);
+        let mut records = rec.records;
+
+        if let Err(e) = result {
+            // A replay of a recursive type chose the wrong variants; try again with others
+            if state.retry {
+                continue;
+            }
+            return Err(e);
+        }
+
+        if state.is_complete() {
+            let mut definitions = Definitions::new();
+            let root = state.resolve(records.remove(0), &mut definitions);
+
+            return Ok(if definitions.is_empty() {
+                root
+            } else {
+                Schema::Recursive {
+                    definitions,
+                    root: Box::new(root),
+                }
+            });
+        }
+
+        if state.discovered() == discovered {
+            let (path, name) = state
+                .undiscovered(&records[0], &mut HashSet::new())
+                .expect("An enum is incomplete");
+            return Err(KobbleError::UndiscoveredVariants { path, name });
+        }
+    }
+}
+
+/// Variants of an enum discovered so far
+struct EnumRecord {
+    names: &'static [&'static str],
+    variants: Vec<Option<VariantSchema>>,
+}
+
+/// How a named type is being recorded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Visit {
+    /// Recorded in full
+    Full,
+    /// The type is already being recorded further up. It is replayed with empty containers in
+    /// order to obtain a value, and a reference is recorded in its place
+    Recursion,
+    /// Part of a replay
+    Replay,
+}
+
+/// State shared between recorders, over the course of every pass
+#[derive(Default)]
+struct RecorderState {
+    enums: HashMap<&'static str, EnumRecord>,
+    /// Named types which refer to themselves
+    recursive: HashSet<&'static str>,
+    /// Named types currently being recorded in full
+    stack: Vec<&'static str>,
+    /// Named types currently being replayed
+    replay: Vec<&'static str>,
+    /// Variant to visit for each enum during a replay
+    replay_choices: HashMap<&'static str, usize>,
+    /// Set if the current pass must be abandoned and tried again
+    retry: bool,
+}
+
+impl RecorderState {
+    fn begin_pass(&mut self) {
+        self.stack.clear();
+        self.replay.clear();
+        self.retry = false;
+    }
+
+    fn replaying(&self) -> bool {
+        !self.replay.is_empty()
+    }
+
+    /// Start recording a named type, detecting whether it refers to itself
+    fn enter(&mut self, name: &'static str) -> Result<Visit, KobbleError> {
+        if let Some(pos) = self.replay.iter().position(|n| *n == name) {
+            return Err(self.replay_loop(name, pos));
+        }
+
+        if self.replaying() {
+            self.replay.push(name);
+            Ok(Visit::Replay)
+        } else if self.stack.contains(&name) {
+            self.recursive.insert(name);
+            self.replay.push(name);
+            Ok(Visit::Recursion)
+        } else {
+            self.stack.push(name);
+            Ok(Visit::Full)
+        }
+    }
+
+    fn exit(&mut self, visit: Visit) {
+        match visit {
+            Visit::Full => self.stack.pop(),
+            Visit::Recursion | Visit::Replay => self.replay.pop(),
+        };
+    }
+
+    /// A replay looped back on itself, even though containers are left empty. The only way out
+    /// is through a different variant of one of the enums along the loop.
+    fn replay_loop(&mut self, name: &'static str, pos: usize) -> KobbleError {
+        for enum_name in self.replay[pos..].iter().rev() {
+            let Some(record) = self.enums.get(enum_name) else {
+                continue;
+            };
+
+            let choice = self.replay_choices.entry(enum_name).or_insert(0);
+            if *choice + 1 < record.variants.len() {
+                *choice += 1;
+                self.retry = true;
+                return KobbleError::custom(format!("Retrying replay of {}", name));
+            }
+        }
+
+        KobbleError::NoFiniteInstance {
+            path: Default::default(),
+            name: name.into(),
+        }
+    }
+
+    /// Total number of enum variants discovered so far
+    fn discovered(&self) -> usize {
+        self.enums
+            .values()
+            .map(|record| record.variants.iter().filter(|v| v.is_some()).count())
+            .sum()
+    }
+
+    /// Returns true if every variant of every enum encountered has been discovered
+    fn is_complete(&self) -> bool {
+        self.enums
+            .values()
+            .all(|record| record.variants.iter().all(Option::is_some))
+    }
+
+    /// Decide which variant of the given enum to visit on this pass
+    fn choose_variant(&self, name: &str) -> usize {
+        let record = &self.enums[name];
+
+        // Prefer variants we have never seen before
+        if let Some(idx) = record.variants.iter().position(Option::is_none) {
+            return idx;
+        }
+
+        // Otherwise head towards enums which still have undiscovered variants
+        record
+            .variants
+            .iter()
+            .flatten()
+            .position(|variant| self.variant_incomplete(variant, &mut HashSet::new()))
+            .unwrap_or(0)
+    }
+
+    /// Returns true if the variant contains an enum with undiscovered variants
+    fn variant_incomplete(&self, variant: &VariantSchema, visited: &mut HashSet<String>) -> bool {
+        self.undiscovered_in_variant(variant, visited).is_some()
+    }
+
+    /// An enum within the schema which has undiscovered variants, along with where it is
+    fn undiscovered(
+        &self,
+        schema: &Schema,
+        visited: &mut HashSet<String>,
+    ) -> Option<(ValuePath, String)> {
+        let (segment, found) = match schema {
+            Schema::Struct(s) => fields_undiscovered(s, |s| self.undiscovered(s, visited))?,
+            Schema::Tuple(t) | Schema::TupleStruct(_, t) => {
+                elements_undiscovered(t, |s| self.undiscovered(s, visited))?
+            }
+            Schema::NewtypeStruct(_, s) | Schema::UniformSequence(s) => {
+                (PathSegment::Index(0), self.undiscovered(s, visited)?)
+            }
+            Schema::Option(s) => return self.undiscovered(s, visited),
+            Schema::Map { key, value } => (
+                PathSegment::Index(0),
+                self.undiscovered(key, visited)
+                    .or_else(|| self.undiscovered(value, visited))?,
+            ),
+            Schema::Enum(e) => {
+                if !visited.insert(e.name.clone()) {
+                    return None;
+                }
+
+                let record = &self.enums[e.name.as_str()];
+                if record.variants.iter().any(Option::is_none) {
+                    return Some((ValuePath::root(), e.name.clone()));
+                }
+
+                return record
+                    .variants
+                    .iter()
+                    .flatten()
+                    .find_map(|variant| self.undiscovered_in_variant(variant, visited));
+            }
+            _ => return None,
+        };
+
+        let (mut path, name) = found;
+        path.0.insert(0, segment);
+        Some((path, name))
+    }
+
+    fn undiscovered_in_variant(
+        &self,
+        variant: &VariantSchema,
+        visited: &mut HashSet<String>,
+    ) -> Option<(ValuePath, String)> {
+        let (segment, (path, name)) = match variant {
+            VariantSchema::Struct(s) => fields_undiscovered(s, |s| self.undiscovered(s, visited))?,
+            VariantSchema::Tuple(t) => elements_undiscovered(t, |s| self.undiscovered(s, visited))?,
+            VariantSchema::Newtype(s) => (PathSegment::Index(0), self.undiscovered(s, visited)?),
+            VariantSchema::Unit => return None,
+        };
+
+        let mut path = path;
+        path.0.insert(0, segment);
+        Some((path, name))
+    }
+
+    /// Fill in the variants of each enum in the schema, and move recursive types into the
+    /// definitions
+    fn resolve(&self, schema: Schema, defs: &mut Definitions) -> Schema {
+        let name = match &schema {
+            Schema::Struct(StructSchema { name, .. })
+            | Schema::Enum(EnumSchema { name, .. })
+            | Schema::TupleStruct(name, _)
+            | Schema::NewtypeStruct(name, _) => Some(name.clone()),
+            _ => None,
+        };
+
+        match name.filter(|name| self.recursive.contains(name.as_str())) {
+            Some(name) => {
+                if reserve(defs, &name) {
+                    let schema = self.resolve_inner(schema, defs);
+                    defs.insert(name.clone(), schema);
+                }
+
+                Schema::Ref(name)
+            }
+            None => self.resolve_inner(schema, defs),
+        }
+    }
+
+    fn resolve_inner(&self, schema: Schema, defs: &mut Definitions) -> Schema {
+        match schema {
+            Schema::Struct(s) => Schema::Struct(self.resolve_struct(s, defs)),
+            Schema::Tuple(t) => Schema::Tuple(self.resolve_tuple(t, defs)),
+            Schema::TupleStruct(name, t) => Schema::TupleStruct(name, self.resolve_tuple(t, defs)),
+            Schema::NewtypeStruct(name, s) => {
+                Schema::NewtypeStruct(name, Box::new(self.resolve(*s, defs)))
+            }
+            Schema::UniformSequence(s) => Schema::UniformSequence(Box::new(self.resolve(*s, defs))),
+            Schema::Option(s) => Schema::Option(Box::new(self.resolve(*s, defs))),
+            Schema::Map { key, value } => Schema::Map {
+                key: Box::new(self.resolve(*key, defs)),
+                value: Box::new(self.resolve(*value, defs)),
+            },
+            Schema::Enum(e) => {
+                let record = &self.enums[e.name.as_str()];
+                let variants = record
+                    .names
+                    .iter()
+                    .zip(&record.variants)
+                    .map(|(name, variant)| {
+                        let variant = variant.clone().expect("Incomplete enum");
+                        (name.to_string(), self.resolve_variant(variant, defs))
+                    })
+                    .collect();
+
+                Schema::Enum(EnumSchema {
+                    name: e.name,
+                    variants,
+                })
+            }
+            other => other,
+        }
+    }
+
+    fn resolve_variant(&self, variant: VariantSchema, defs: &mut Definitions) -> VariantSchema {
+        match variant {
+            VariantSchema::Struct(s) => VariantSchema::Struct(self.resolve_struct(s, defs)),
+            VariantSchema::Tuple(t) => VariantSchema::Tuple(self.resolve_tuple(t, defs)),
+            VariantSchema::Newtype(s) => VariantSchema::Newtype(Box::new(self.resolve(*s, defs))),
+            VariantSchema::Unit => VariantSchema::Unit,
+        }
+    }
+
+    fn resolve_struct(&self, s: StructSchema, defs: &mut Definitions) -> StructSchema {
+        StructSchema {
+            name: s.name,
+            fields: s
+                .fields
+                .into_iter()
+                .map(|(name, s)| (name, self.resolve(s, defs)))
+                .collect(),
+        }
+    }
+
+    fn resolve_tuple(&self, t: Vec<Schema>, defs: &mut Definitions) -> Vec<Schema> {
+        t.into_iter().map(|s| self.resolve(s, defs)).collect()
+    }
+}
+
+/// The first field in which `find` finds something, along with the field
+fn fields_undiscovered<T>(
+    s: &StructSchema,
+    mut find: impl FnMut(&Schema) -> Option<T>,
+) -> Option<(PathSegment, T)> {
+    s.fields
+        .iter()
+        .find_map(|(name, s)| Some((PathSegment::Field(name.clone()), find(s)?)))
+}
+
+/// The first element in which `find` finds something, along with its index
+fn elements_undiscovered<T>(
+    t: &[Schema],
+    mut find: impl FnMut(&Schema) -> Option<T>,
+) -> Option<(PathSegment, T)> {
+    t.iter()
+        .enumerate()
+        .find_map(|(i, s)| Some((PathSegment::Index(i), find(s)?)))
 }
 
 /// Records the structure of a data type by acting as a Deserializer
-#[derive(Debug, Clone)]
-struct SchemaRecorder(Vec<Schema>);
+struct SchemaRecorder<'s> {
+    records: Vec<Schema>,
+    state: &'s mut RecorderState,
+}
 
-impl SchemaRecorder {
-    pub fn new() -> Self {
-        Self(vec![])
+impl<'s> SchemaRecorder<'s> {
+    fn new(state: &'s mut RecorderState) -> Self {
+        Self {
+            records: vec![],
+            state,
+        }
+    }
+
+    /// Finish recording a named type
+    fn push_named(&mut self, name: &'static str, schema: Schema, visit: Visit) {
+        self.state.exit(visit);
+        self.records.push(match visit {
+            Visit::Recursion => Schema::Ref(name.into()),
+            Visit::Full | Visit::Replay => schema,
+        });
     }
 }
 
-impl<'de> Deserializer<'de> for &mut SchemaRecorder {
-    type Error = GenericError;
+impl<'de> Deserializer<'de> for &mut SchemaRecorder<'_> {
+    type Error = KobbleError;
 
     fn deserialize_enum<V>(
         self,
@@ -32,21 +384,37 @@ impl<'de> Deserializer<'de> for &mut SchemaRecorder {
     where
         V: Visitor<'de>,
     {
-        self.0.push(Schema::Enum(EnumSchema {
+        let visit = self.state.enter(name)?;
+
+        self.state.enums.entry(name).or_insert_with(|| EnumRecord {
+            names: variants,
+            variants: vec![None; variants.len()],
+        });
+
+        let index = match visit {
+            Visit::Full => self.state.choose_variant(name),
+            Visit::Recursion | Visit::Replay => {
+                self.state.replay_choices.get(name).copied().unwrap_or(0)
+            }
+        };
+
+        let ret = visitor.visit_enum(EnumRecorder::new(&mut *self.state, name, index))?;
+
+        // The variants are filled in once every one of them has been discovered
+        let schema = Schema::Enum(EnumSchema {
             name: name.into(),
-            variants: variants.iter().map(|v| v.to_string()).collect(),
-        }));
+            variants: vec![],
+        });
+        self.push_named(name, schema, visit);
 
-        visitor.visit_enum(EnumRecorder::new(variants.len()))
+        Ok(ret)
     }
 
     fn deserialize_identifier<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        Err(GenericError(
-            "Under the impression this is only relevant to enums".into(),
-        ))
+        Err(unsupported("identifiers outside of enums"))
     }
 
     fn deserialize_struct<V>(
@@ -58,33 +426,36 @@ impl<'de> Deserializer<'de> for &mut SchemaRecorder {
     where
         V: Visitor<'de>,
     {
+        let visit = self.state.enter(name)?;
+
         // Visit the entries in the struct
-        let mut rec = SeqRecorder::new(fields.len());
-        let ret = visitor.visit_seq(&mut rec);
+        let mut rec = SeqRecorder::with_names(fields, &mut *self.state);
+        let ret = visitor.visit_seq(&mut rec)?;
 
         // Zip the names of the fields with their respective schema
         let fields = fields
             .iter()
             .map(|s| s.to_string())
-            .zip(rec.records.0)
+            .zip(rec.records.records)
             .collect();
 
-        self.0.push(Schema::Struct(StructSchema {
+        let schema = Schema::Struct(StructSchema {
             name: name.into(),
             fields,
-        }));
+        });
+        self.push_named(name, schema, visit);
 
-        ret
+        Ok(ret)
     }
 
     fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        let mut rec = SeqRecorder::new(len);
+        let mut rec = SeqRecorder::new(len, &mut *self.state);
         let ret = visitor.visit_seq(&mut rec);
 
-        self.0.push(Schema::Tuple(rec.records.0));
+        self.records.push(Schema::Tuple(rec.records.records));
 
         ret
     }
@@ -97,7 +468,7 @@ impl<'de> Deserializer<'de> for &mut SchemaRecorder {
     where
         V: Visitor<'de>,
     {
-        self.0.push(Schema::UnitStruct(name.to_string()));
+        self.records.push(Schema::UnitStruct(name.to_string()));
         visitor.visit_unit()
     }
 
@@ -110,13 +481,15 @@ impl<'de> Deserializer<'de> for &mut SchemaRecorder {
     where
         V: Visitor<'de>,
     {
-        let mut rec = SeqRecorder::new(len);
-        let ret = visitor.visit_seq(&mut rec);
+        let visit = self.state.enter(name)?;
+
+        let mut rec = SeqRecorder::new(len, &mut *self.state);
+        let ret = visitor.visit_seq(&mut rec)?;
 
-        self.0
-            .push(Schema::TupleStruct(name.to_string(), rec.records.0));
+        let schema = Schema::TupleStruct(name.to_string(), rec.records.records);
+        self.push_named(name, schema, visit);
 
-        ret
+        Ok(ret)
     }
 
     fn deserialize_newtype_struct<V>(
@@ -127,55 +500,73 @@ impl<'de> Deserializer<'de> for &mut SchemaRecorder {
     where
         V: Visitor<'de>,
     {
-        let mut rec = SeqRecorder::new(1);
-        let ret = visitor.visit_seq(&mut rec);
+        let visit = self.state.enter(name)?;
+
+        let mut rec = SeqRecorder::new(1, &mut *self.state);
+        let ret = visitor.visit_seq(&mut rec)?;
 
-        self.0.push(Schema::NewtypeStruct(
-            name.to_string(),
-            Box::new(rec.records.0.remove(0)),
-        ));
+        let schema =
+            Schema::NewtypeStruct(name.to_string(), Box::new(rec.records.records.remove(0)));
+        self.push_named(name, schema, visit);
 
-        ret
+        Ok(ret)
     }
 
     fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        Err(GenericError("Any".into()))
+        Err(unsupported("self-describing types (deserialize_any)"))
     }
 
     fn deserialize_ignored_any<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        Err(GenericError("Any".into()))
+        Err(unsupported("self-describing types (deserialize_any)"))
     }
 
     fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        let mut rec = SeqRecorder::new(1);
+        // While replaying a recursive type, containers are left empty so that the recursion ends.
+        // Whatever is recorded during a replay is discarded, so the placeholder is never seen.
+        let len = if self.state.replaying() { 0 } else { 1 };
+
+        let mut rec = SeqRecorder::new(len, &mut *self.state);
         let ret = visitor.visit_seq(&mut rec)?;
-        let _ty = rec.records.0.remove(0);
-        self.0.push(Schema::UniformSequence(Box::new(_ty)));
+        let ty = rec.records.records.pop().unwrap_or(Schema::Unit);
+        self.records.push(Schema::UniformSequence(Box::new(ty)));
 
         Ok(ret)
     }
 
-    fn deserialize_map<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        Err(GenericError("Maps unsupported".into()))
+        // Left empty during a replay, see deserialize_seq()
+        let len = if self.state.replaying() { 0 } else { 1 };
+
+        let mut rec = MapRecorder::new(len, &mut *self.state);
+        let ret = visitor.visit_map(&mut rec)?;
+        let key = rec.keys.pop().unwrap_or(Schema::Unit);
+        let value = rec.values.pop().unwrap_or(Schema::Unit);
+
+        self.records.push(Schema::Map {
+            key: Box::new(key),
+            value: Box::new(value),
+        });
+
+        Ok(ret)
     }
 
     fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        self.0.push(Schema::String);
+        self.records.push(Schema::String);
         visitor.visit_borrowed_str(Default::default())
     }
 
@@ -183,7 +574,7 @@ impl<'de> Deserializer<'de> for &mut SchemaRecorder {
     where
         V: Visitor<'de>,
     {
-        self.0.push(Schema::I8);
+        self.records.push(Schema::I8);
         visitor.visit_i8(Default::default())
     }
 
@@ -191,7 +582,7 @@ impl<'de> Deserializer<'de> for &mut SchemaRecorder {
     where
         V: Visitor<'de>,
     {
-        self.0.push(Schema::U8);
+        self.records.push(Schema::U8);
         visitor.visit_u8(Default::default())
     }
 
@@ -199,7 +590,7 @@ impl<'de> Deserializer<'de> for &mut SchemaRecorder {
     where
         V: Visitor<'de>,
     {
-        self.0.push(Schema::I16);
+        self.records.push(Schema::I16);
         visitor.visit_i16(Default::default())
     }
 
@@ -207,7 +598,7 @@ impl<'de> Deserializer<'de> for &mut SchemaRecorder {
     where
         V: Visitor<'de>,
     {
-        self.0.push(Schema::U16);
+        self.records.push(Schema::U16);
         visitor.visit_u16(Default::default())
     }
 
@@ -215,7 +606,7 @@ impl<'de> Deserializer<'de> for &mut SchemaRecorder {
     where
         V: Visitor<'de>,
     {
-        self.0.push(Schema::I32);
+        self.records.push(Schema::I32);
         visitor.visit_i32(Default::default())
     }
 
@@ -223,7 +614,7 @@ impl<'de> Deserializer<'de> for &mut SchemaRecorder {
     where
         V: Visitor<'de>,
     {
-        self.0.push(Schema::U32);
+        self.records.push(Schema::U32);
         visitor.visit_u32(Default::default())
     }
 
@@ -231,7 +622,7 @@ impl<'de> Deserializer<'de> for &mut SchemaRecorder {
     where
         V: Visitor<'de>,
     {
-        self.0.push(Schema::I64);
+        self.records.push(Schema::I64);
         visitor.visit_i64(Default::default())
     }
 
@@ -239,7 +630,7 @@ impl<'de> Deserializer<'de> for &mut SchemaRecorder {
     where
         V: Visitor<'de>,
     {
-        self.0.push(Schema::U64);
+        self.records.push(Schema::U64);
         visitor.visit_u64(Default::default())
     }
 
@@ -247,7 +638,7 @@ impl<'de> Deserializer<'de> for &mut SchemaRecorder {
     where
         V: Visitor<'de>,
     {
-        self.0.push(Schema::I128);
+        self.records.push(Schema::I128);
         visitor.visit_i128(Default::default())
     }
 
@@ -255,7 +646,7 @@ impl<'de> Deserializer<'de> for &mut SchemaRecorder {
     where
         V: Visitor<'de>,
     {
-        self.0.push(Schema::U128);
+        self.records.push(Schema::U128);
         visitor.visit_u128(Default::default())
     }
 
@@ -263,7 +654,7 @@ impl<'de> Deserializer<'de> for &mut SchemaRecorder {
     where
         V: Visitor<'de>,
     {
-        self.0.push(Schema::F32);
+        self.records.push(Schema::F32);
         visitor.visit_f32(Default::default())
     }
 
@@ -271,7 +662,7 @@ impl<'de> Deserializer<'de> for &mut SchemaRecorder {
     where
         V: Visitor<'de>,
     {
-        self.0.push(Schema::F64);
+        self.records.push(Schema::F64);
         visitor.visit_f64(Default::default())
     }
 
@@ -279,7 +670,7 @@ impl<'de> Deserializer<'de> for &mut SchemaRecorder {
     where
         V: Visitor<'de>,
     {
-        self.0.push(Schema::Bool);
+        self.records.push(Schema::Bool);
         visitor.visit_bool(Default::default())
     }
 
@@ -287,7 +678,7 @@ impl<'de> Deserializer<'de> for &mut SchemaRecorder {
     where
         V: Visitor<'de>,
     {
-        self.0.push(Schema::Char);
+        self.records.push(Schema::Char);
         visitor.visit_char(Default::default())
     }
 
@@ -295,62 +686,97 @@ impl<'de> Deserializer<'de> for &mut SchemaRecorder {
     where
         V: Visitor<'de>,
     {
-        self.0.push(Schema::Unit);
+        self.records.push(Schema::Unit);
         visitor.visit_unit()
     }
 
-    fn deserialize_bytes<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        //self.0.push(Schema::Bytes);
-        //visitor.visit_bytes(Default::default())
-        Err(GenericError("Byte buffers".to_string()))
+        self.records.push(Schema::Bytes);
+        visitor.visit_borrowed_bytes(Default::default())
     }
 
-    fn deserialize_byte_buf<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        //self.0.push(Schema::ByteBuf);
-        //visitor.visit_byte_buf(Default::default())
-        Err(GenericError("Byte buffers".to_string()))
+        self.records.push(Schema::Bytes);
+        visitor.visit_byte_buf(Default::default())
     }
 
-    fn deserialize_option<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        //self.0.push(Schema::Option);
-        //visitor.visit_none()
-        Err(GenericError("Option type unsupported".to_string()))
+        // Left empty during a replay, see deserialize_seq()
+        if self.state.replaying() {
+            self.records.push(Schema::Option(Box::new(Schema::Unit)));
+            return visitor.visit_none();
+        }
+
+        // Visit the Some branch in order to learn the inner type
+        let mut rec = SchemaRecorder::new(&mut *self.state);
+        let ret = visitor.visit_some(&mut rec)?;
+        let inner = rec.records.remove(0);
+
+        self.records.push(Schema::Option(Box::new(inner)));
+
+        Ok(ret)
     }
 
     fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, Self::Error>
     where
         V: Visitor<'de>,
     {
-        self.0.push(Schema::String);
+        self.records.push(Schema::String);
         visitor.visit_string(Default::default())
     }
 }
 
-struct SeqRecorder {
-    records: SchemaRecorder,
+fn unsupported(what: &str) -> KobbleError {
+    KobbleError::Unsupported {
+        path: Default::default(),
+        what: what.into(),
+    }
+}
+
+struct SeqRecorder<'s> {
+    records: SchemaRecorder<'s>,
     len: usize,
+    /// Field names, if this is a struct
+    names: &'static [&'static str],
 }
 
-impl SeqRecorder {
-    pub fn new(len: usize) -> Self {
+impl<'s> SeqRecorder<'s> {
+    fn new(len: usize, state: &'s mut RecorderState) -> Self {
         Self {
-            records: SchemaRecorder::new(),
+            records: SchemaRecorder::new(state),
             len,
+            names: &[],
+        }
+    }
+
+    fn with_names(names: &'static [&'static str], state: &'s mut RecorderState) -> Self {
+        Self {
+            names,
+            ..Self::new(names.len(), state)
+        }
+    }
+
+    /// Path segment of the next element
+    fn segment(&self) -> PathSegment {
+        let index = self.records.records.len();
+        match self.names.get(index) {
+            Some(name) => PathSegment::Field(name.to_string()),
+            None => PathSegment::Index(index),
         }
     }
 }
 
-impl<'de> SeqAccess<'de> for SeqRecorder {
-    type Error = GenericError;
+impl<'de> SeqAccess<'de> for SeqRecorder<'_> {
+    type Error = KobbleError;
 
     fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
     where
@@ -358,7 +784,9 @@ impl<'de> SeqAccess<'de> for SeqRecorder {
     {
         if self.len > 0 {
             self.len -= 1;
-            let value = serde::de::DeserializeSeed::deserialize(seed, &mut self.records)?;
+            let segment = self.segment();
+            let value = serde::de::DeserializeSeed::deserialize(seed, &mut self.records)
+                .map_err(|e| e.prefixed(segment))?;
             Ok(Some(value))
         } else {
             Ok(None)
@@ -370,37 +798,111 @@ impl<'de> SeqAccess<'de> for SeqRecorder {
     }
 }
 
-struct EnumRecorder {
-    variants: SchemaRecorder,
+struct MapRecorder<'s> {
+    keys: Vec<Schema>,
+    values: Vec<Schema>,
+    state: &'s mut RecorderState,
     len: usize,
 }
 
-impl EnumRecorder {
-    pub fn new(len: usize) -> Self {
+impl<'s> MapRecorder<'s> {
+    fn new(len: usize, state: &'s mut RecorderState) -> Self {
         Self {
-            variants: SchemaRecorder::new(),
+            keys: vec![],
+            values: vec![],
+            state,
             len,
         }
     }
 }
 
-impl<'de> EnumAccess<'de> for EnumRecorder {
-    type Error = GenericError;
-    type Variant = EnumRecorder;
+impl<'de> MapAccess<'de> for MapRecorder<'_> {
+    type Error = KobbleError;
 
-    fn variant_seed<V>(self, mut seed: V) -> Result<(V::Value, Self::Variant), Self::Error>
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        if self.len > 0 {
+            self.len -= 1;
+            let mut rec = SchemaRecorder::new(&mut *self.state);
+            let key = seed
+                .deserialize(&mut rec)
+                .map_err(|e| e.prefixed(PathSegment::Index(0)))?;
+            self.keys.append(&mut rec.records);
+            Ok(Some(key))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
     where
         V: de::DeserializeSeed<'de>,
     {
-        let r = seed.deserialize(0u32.into_deserializer())?;
+        let mut rec = SchemaRecorder::new(&mut *self.state);
+        let value = seed
+            .deserialize(&mut rec)
+            .map_err(|e| e.prefixed(PathSegment::Index(0)))?;
+        self.values.append(&mut rec.records);
+        Ok(value)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.len)
+    }
+}
+
+/// Records a single variant of an enum
+struct EnumRecorder<'s> {
+    state: &'s mut RecorderState,
+    name: &'static str,
+    index: usize,
+}
+
+impl<'s> EnumRecorder<'s> {
+    fn new(state: &'s mut RecorderState, name: &'static str, index: usize) -> Self {
+        Self { state, name, index }
+    }
+
+    /// Store the variant schema, if we haven't seen it before
+    fn finish(self, variant: VariantSchema) {
+        // Replays leave containers empty, so they don't record the whole variant
+        if self.state.replaying() {
+            return;
+        }
+
+        let record = self
+            .state
+            .enums
+            .get_mut(self.name)
+            .expect("Enum not registered");
+
+        record.variants[self.index].get_or_insert(variant);
+    }
+}
+
+impl<'de, 's> EnumAccess<'de> for EnumRecorder<'s> {
+    type Error = KobbleError;
+    type Variant = EnumRecorder<'s>;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        // `?` could convert the error of either KobbleError or bincode::Error, so the type of
+        // the deserializer must be spelled out
+        let index: U32Deserializer<KobbleError> = (self.index as u32).into_deserializer();
+        let r = seed.deserialize(index)?;
         Ok((r, self))
     }
 }
 
-impl<'de> VariantAccess<'de> for EnumRecorder {
-    type Error = GenericError;
+impl<'de> VariantAccess<'de> for EnumRecorder<'_> {
+    type Error = KobbleError;
 
     fn unit_variant(self) -> Result<(), Self::Error> {
+        self.finish(VariantSchema::Unit);
         Ok(())
     }
 
@@ -408,14 +910,13 @@ impl<'de> VariantAccess<'de> for EnumRecorder {
     where
         V: Visitor<'de>,
     {
-        // Because visitor.visit_enum() necessarily consumes visitor, we cannot visit multiple
-        // enum variants in one shot. One potential solution would be to invoke the entire
-        // serializer many times. Each invocation would take a different path through the variant
-        // tree. These trees would then be merged into a single schema containing all variants.
-        // But this must wait!
-        Err(GenericError(
-            "Enums containing data are unsupported for schema generation.".into(),
-        ))
+        let mut rec = SeqRecorder::new(len, &mut *self.state);
+        let ret = visitor.visit_seq(&mut rec)?;
+        let records = rec.records.records;
+
+        self.finish(VariantSchema::Tuple(records));
+
+        Ok(ret)
     }
 
     fn struct_variant<V>(
@@ -426,17 +927,34 @@ impl<'de> VariantAccess<'de> for EnumRecorder {
     where
         V: Visitor<'de>,
     {
-        Err(GenericError(
-            "Enums containing data are unsupported for schema generation.".into(),
-        ))
+        let mut rec = SeqRecorder::with_names(fields, &mut *self.state);
+        let ret = visitor.visit_seq(&mut rec)?;
+
+        // Zip the names of the fields with their respective schema
+        let fields = fields
+            .iter()
+            .map(|s| s.to_string())
+            .zip(rec.records.records)
+            .collect();
+
+        let name = self.state.enums[self.name].names[self.index].to_string();
+        self.finish(VariantSchema::Struct(StructSchema { name, fields }));
+
+        Ok(ret)
     }
 
-    fn newtype_variant_seed<T>(mut self, seed: T) -> Result<T::Value, Self::Error>
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
     where
         T: de::DeserializeSeed<'de>,
     {
-        Err(GenericError(
-            "Enums containing data are unsupported for schema generation.".into(),
-        ))
+        let mut rec = SchemaRecorder::new(&mut *self.state);
+        let ret = seed
+            .deserialize(&mut rec)
+            .map_err(|e| e.prefixed(PathSegment::Index(0)))?;
+        let schema = rec.records.remove(0);
+
+        self.finish(VariantSchema::Newtype(Box::new(schema)));
+
+        Ok(ret)
     }
 }
@@ -0,0 +1,70 @@
+use crate::error::KobbleError;
+use crate::path::ValuePath;
+use crate::{Definitions, Schema};
+
+/// Definitions of the recursive schemas a walk is inside of, innermost last. A reference is
+/// resolved by the innermost definitions which have its name.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Scopes<'a>(Vec<&'a Definitions>);
+
+impl<'a> Scopes<'a> {
+    /// Enter a `Schema::Recursive`
+    pub(crate) fn push(&mut self, definitions: &'a Definitions) {
+        self.0.push(definitions);
+    }
+
+    pub(crate) fn pop(&mut self) {
+        self.0.pop();
+    }
+
+    /// Leave the `n` outermost scopes, returning the rest for `restore`
+    pub(crate) fn split_off(&mut self, n: usize) -> Vec<&'a Definitions> {
+        self.0.split_off(n)
+    }
+
+    /// Return to the scopes left by `split_off`
+    pub(crate) fn restore(&mut self, inner: Vec<&'a Definitions>) {
+        self.0.extend(inner);
+    }
+
+    /// Every scope, outermost first
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &'a Definitions> + '_ {
+        self.0.iter().copied()
+    }
+
+    /// The schema a reference refers to, along with the position of the definitions it was
+    /// found in, counting from the outermost
+    pub(crate) fn find(&self, name: &str) -> Option<(usize, &'a Schema)> {
+        self.0
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(i, definitions)| Some((i, definitions.get(name)?)))
+    }
+
+    /// The schema a reference refers to
+    pub(crate) fn get(&self, name: &str) -> Option<&'a Schema> {
+        self.find(name).map(|(_, schema)| schema)
+    }
+
+    /// The schema a reference refers to, or an error if there is none. The error is at the root
+    /// path, for the caller to place.
+    pub(crate) fn lookup(&self, name: &str) -> Result<&'a Schema, KobbleError> {
+        self.get(name).ok_or_else(|| KobbleError::UndefinedType {
+            path: ValuePath::root(),
+            name: name.to_string(),
+        })
+    }
+}
+
+/// Reserve `name` for a recursive type about to be defined, returning false if it is defined
+/// already. The type may refer to itself while its schema is being built, and those references
+/// must find the name taken.
+pub(crate) fn reserve(definitions: &mut Definitions, name: &str) -> bool {
+    if definitions.contains_key(name) {
+        return false;
+    }
+
+    definitions.insert(name.to_string(), Schema::Unit);
+    true
+}
@@ -0,0 +1,610 @@
+//! Building blocks for `DeserializeSeed`s which carry state into nested values, such as how deeply
+//! they are nested, so that corrupt or malicious input stops at `RECURSION_LIMIT` rather than
+//! overflowing the stack.
+
+use serde::de::{
+    self, DeserializeSeed, EnumAccess, Expected, IgnoredAny, MapAccess, SeqAccess, VariantAccess,
+    Visitor,
+};
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::marker::PhantomData;
+
+use crate::deserialize::RECURSION_LIMIT;
+use crate::{Definitions, EnumSchema, Schema, StructSchema, VariantSchema};
+
+/// Reads a sequence, each element with the same seed
+#[derive(Clone, Copy)]
+pub(crate) struct Seq<S>(pub(crate) S);
+
+/// Reads a pair, as a tuple of two
+#[derive(Clone, Copy)]
+pub(crate) struct Pair<A, B>(pub(crate) A, pub(crate) B);
+
+/// The next element of a sequence visited as a struct, which must be there
+pub(crate) fn field<'de, A, S>(
+    seq: &mut A,
+    seed: S,
+    index: usize,
+    visitor: &dyn Expected,
+) -> Result<S::Value, A::Error>
+where
+    A: SeqAccess<'de>,
+    S: DeserializeSeed<'de>,
+{
+    seq.next_element_seed(seed)?
+        .ok_or_else(|| de::Error::invalid_length(index, visitor))
+}
+
+/// A field of a struct visited as a map, or an error if it is missing
+pub(crate) fn required<T, E: de::Error>(value: Option<T>, name: &'static str) -> Result<T, E> {
+    value.ok_or_else(|| de::Error::missing_field(name))
+}
+
+impl<'de, S: DeserializeSeed<'de> + Copy> DeserializeSeed<'de> for Seq<S> {
+    type Value = Vec<S::Value>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deser: D) -> Result<Self::Value, D::Error> {
+        deser.deserialize_seq(self)
+    }
+}
+
+impl<'de, S: DeserializeSeed<'de> + Copy> Visitor<'de> for Seq<S> {
+    type Value = Vec<S::Value>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a sequence")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut values = vec![];
+        while let Some(value) = seq.next_element_seed(self.0)? {
+            values.push(value);
+        }
+        Ok(values)
+    }
+}
+
+impl<'de, A, B> DeserializeSeed<'de> for Pair<A, B>
+where
+    A: DeserializeSeed<'de>,
+    B: DeserializeSeed<'de>,
+{
+    type Value = (A::Value, B::Value);
+
+    fn deserialize<D: Deserializer<'de>>(self, deser: D) -> Result<Self::Value, D::Error> {
+        deser.deserialize_tuple(2, self)
+    }
+}
+
+impl<'de, A, B> Visitor<'de> for Pair<A, B>
+where
+    A: DeserializeSeed<'de>,
+    B: DeserializeSeed<'de>,
+{
+    type Value = (A::Value, B::Value);
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a tuple of 2 elements")
+    }
+
+    fn visit_seq<S: SeqAccess<'de>>(self, mut seq: S) -> Result<Self::Value, S::Error> {
+        let first = field(&mut seq, self.0, 0, &"a tuple of 2 elements")?;
+        let second = field(&mut seq, self.1, 1, &"a tuple of 2 elements")?;
+        Ok((first, second))
+    }
+}
+
+/// Reads a Schema, as its derived Deserialize impl does, failing once schemas are nested
+/// `RECURSION_LIMIT` deep
+#[derive(Clone, Copy, Default)]
+pub(crate) struct LimitedSchemaSeed {
+    /// How many schemas this one is nested in
+    depth: usize,
+}
+
+impl LimitedSchemaSeed {
+    /// Seed for the schemas within this one
+    fn next(self) -> Self {
+        Self {
+            depth: self.depth + 1,
+        }
+    }
+}
+
+/// Names of the variants of Schema, in order
+const SCHEMA_VARIANTS: &[&str] = &[
+    "I8",
+    "U8",
+    "I16",
+    "U16",
+    "I32",
+    "U32",
+    "I64",
+    "U64",
+    "I128",
+    "U128",
+    "F32",
+    "F64",
+    "Bool",
+    "Char",
+    "Unit",
+    "Bytes",
+    "String",
+    "Option",
+    "Struct",
+    "Tuple",
+    "TupleStruct",
+    "NewtypeStruct",
+    "UnitStruct",
+    "UniformSequence",
+    "Map",
+    "Enum",
+    "Ref",
+    "Recursive",
+];
+
+/// Names of the variants of VariantSchema, in order
+const VARIANT_SCHEMA_VARIANTS: &[&str] = &["Struct", "Tuple", "Newtype", "Unit"];
+
+#[derive(Deserialize)]
+#[serde(variant_identifier)]
+enum SchemaKind {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    I64,
+    U64,
+    I128,
+    U128,
+    F32,
+    F64,
+    Bool,
+    Char,
+    Unit,
+    Bytes,
+    String,
+    Option,
+    Struct,
+    Tuple,
+    TupleStruct,
+    NewtypeStruct,
+    UnitStruct,
+    UniformSequence,
+    Map,
+    Enum,
+    Ref,
+    Recursive,
+}
+
+#[derive(Deserialize)]
+#[serde(variant_identifier)]
+enum VariantSchemaKind {
+    Struct,
+    Tuple,
+    Newtype,
+    Unit,
+}
+
+/// Fields of StructSchema, EnumSchema and the struct variants of Schema
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "lowercase")]
+enum SchemaField {
+    Name,
+    Fields,
+    Variants,
+    Key,
+    Value,
+    Definitions,
+    Root,
+    #[serde(other)]
+    Other,
+}
+
+/// Reads a StructSchema, whose fields are nested `depth` deep
+#[derive(Clone, Copy)]
+struct StructSchemaSeed(LimitedSchemaSeed);
+
+/// Reads an EnumSchema, whose variants are nested `depth` deep
+#[derive(Clone, Copy)]
+struct EnumSchemaSeed(LimitedSchemaSeed);
+
+/// Reads a VariantSchema, whose schemas are nested `depth` deep
+#[derive(Clone, Copy)]
+struct VariantSchemaSeed(LimitedSchemaSeed);
+
+/// Visits the `key` and `value` of `Schema::Map`
+struct MapVisitor(LimitedSchemaSeed);
+
+/// Visits the `definitions` and `root` of `Schema::Recursive`
+struct RecursiveVisitor(LimitedSchemaSeed);
+
+/// Reads the definitions of `Schema::Recursive`
+#[derive(Clone, Copy)]
+struct DefinitionsSeed(LimitedSchemaSeed);
+
+impl<'de> DeserializeSeed<'de> for LimitedSchemaSeed {
+    type Value = Schema;
+
+    fn deserialize<D: Deserializer<'de>>(self, deser: D) -> Result<Self::Value, D::Error> {
+        if self.depth >= RECURSION_LIMIT {
+            return Err(de::Error::custom("recursion limit exceeded"));
+        }
+
+        deser.deserialize_enum("Schema", SCHEMA_VARIANTS, self)
+    }
+}
+
+impl<'de> Visitor<'de> for LimitedSchemaSeed {
+    type Value = Schema;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a schema")
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+        let next = self.next();
+        let (kind, access) = data.variant()?;
+        match kind {
+            SchemaKind::I8 => access.unit_variant().map(|()| Schema::I8),
+            SchemaKind::U8 => access.unit_variant().map(|()| Schema::U8),
+            SchemaKind::I16 => access.unit_variant().map(|()| Schema::I16),
+            SchemaKind::U16 => access.unit_variant().map(|()| Schema::U16),
+            SchemaKind::I32 => access.unit_variant().map(|()| Schema::I32),
+            SchemaKind::U32 => access.unit_variant().map(|()| Schema::U32),
+            SchemaKind::I64 => access.unit_variant().map(|()| Schema::I64),
+            SchemaKind::U64 => access.unit_variant().map(|()| Schema::U64),
+            SchemaKind::I128 => access.unit_variant().map(|()| Schema::I128),
+            SchemaKind::U128 => access.unit_variant().map(|()| Schema::U128),
+            SchemaKind::F32 => access.unit_variant().map(|()| Schema::F32),
+            SchemaKind::F64 => access.unit_variant().map(|()| Schema::F64),
+            SchemaKind::Bool => access.unit_variant().map(|()| Schema::Bool),
+            SchemaKind::Char => access.unit_variant().map(|()| Schema::Char),
+            SchemaKind::Unit => access.unit_variant().map(|()| Schema::Unit),
+            SchemaKind::Bytes => access.unit_variant().map(|()| Schema::Bytes),
+            SchemaKind::String => access.unit_variant().map(|()| Schema::String),
+            SchemaKind::Option => Ok(Schema::Option(Box::new(access.newtype_variant_seed(next)?))),
+            SchemaKind::Struct => Ok(Schema::Struct(
+                access.newtype_variant_seed(StructSchemaSeed(next))?,
+            )),
+            SchemaKind::Tuple => Ok(Schema::Tuple(access.newtype_variant_seed(Seq(next))?)),
+            SchemaKind::TupleStruct => {
+                let (name, schemas) = access.tuple_variant(2, Pair(PhantomData, Seq(next)))?;
+                Ok(Schema::TupleStruct(name, schemas))
+            }
+            SchemaKind::NewtypeStruct => {
+                let (name, inner) = access.tuple_variant(2, Pair(PhantomData, next))?;
+                Ok(Schema::NewtypeStruct(name, Box::new(inner)))
+            }
+            SchemaKind::UnitStruct => Ok(Schema::UnitStruct(access.newtype_variant()?)),
+            SchemaKind::UniformSequence => Ok(Schema::UniformSequence(Box::new(
+                access.newtype_variant_seed(next)?,
+            ))),
+            SchemaKind::Map => access.struct_variant(&["key", "value"], MapVisitor(next)),
+            SchemaKind::Enum => Ok(Schema::Enum(
+                access.newtype_variant_seed(EnumSchemaSeed(next))?,
+            )),
+            SchemaKind::Ref => Ok(Schema::Ref(access.newtype_variant()?)),
+            SchemaKind::Recursive => {
+                access.struct_variant(&["definitions", "root"], RecursiveVisitor(next))
+            }
+        }
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for StructSchemaSeed {
+    type Value = StructSchema;
+
+    fn deserialize<D: Deserializer<'de>>(self, deser: D) -> Result<Self::Value, D::Error> {
+        deser.deserialize_struct("StructSchema", &["name", "fields"], self)
+    }
+}
+
+impl<'de> Visitor<'de> for StructSchemaSeed {
+    type Value = StructSchema;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a struct schema")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        Ok(StructSchema {
+            name: field(&mut seq, PhantomData, 0, &self)?,
+            fields: field(&mut seq, Seq(Pair(PhantomData, self.0)), 1, &self)?,
+        })
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let (mut name, mut fields) = (None, None);
+        while let Some(key) = map.next_key()? {
+            match key {
+                SchemaField::Name => name = Some(map.next_value()?),
+                SchemaField::Fields => {
+                    fields = Some(map.next_value_seed(Seq(Pair(PhantomData, self.0)))?)
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        Ok(StructSchema {
+            name: required(name, "name")?,
+            fields: required(fields, "fields")?,
+        })
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for EnumSchemaSeed {
+    type Value = EnumSchema;
+
+    fn deserialize<D: Deserializer<'de>>(self, deser: D) -> Result<Self::Value, D::Error> {
+        deser.deserialize_struct("EnumSchema", &["name", "variants"], self)
+    }
+}
+
+impl<'de> Visitor<'de> for EnumSchemaSeed {
+    type Value = EnumSchema;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an enum schema")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let variants = Seq(Pair(PhantomData, VariantSchemaSeed(self.0)));
+        Ok(EnumSchema {
+            name: field(&mut seq, PhantomData, 0, &self)?,
+            variants: field(&mut seq, variants, 1, &self)?,
+        })
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let (mut name, mut variants) = (None, None);
+        while let Some(key) = map.next_key()? {
+            match key {
+                SchemaField::Name => name = Some(map.next_value()?),
+                SchemaField::Variants => {
+                    let seed = Seq(Pair(PhantomData, VariantSchemaSeed(self.0)));
+                    variants = Some(map.next_value_seed(seed)?)
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        Ok(EnumSchema {
+            name: required(name, "name")?,
+            variants: required(variants, "variants")?,
+        })
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for VariantSchemaSeed {
+    type Value = VariantSchema;
+
+    fn deserialize<D: Deserializer<'de>>(self, deser: D) -> Result<Self::Value, D::Error> {
+        deser.deserialize_enum("VariantSchema", VARIANT_SCHEMA_VARIANTS, self)
+    }
+}
+
+impl<'de> Visitor<'de> for VariantSchemaSeed {
+    type Value = VariantSchema;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a variant schema")
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+        let seed = self.0;
+        let (kind, access) = data.variant()?;
+        Ok(match kind {
+            VariantSchemaKind::Struct => {
+                VariantSchema::Struct(access.newtype_variant_seed(StructSchemaSeed(seed))?)
+            }
+            VariantSchemaKind::Tuple => {
+                VariantSchema::Tuple(access.newtype_variant_seed(Seq(seed))?)
+            }
+            VariantSchemaKind::Newtype => {
+                VariantSchema::Newtype(Box::new(access.newtype_variant_seed(seed)?))
+            }
+            VariantSchemaKind::Unit => {
+                access.unit_variant()?;
+                VariantSchema::Unit
+            }
+        })
+    }
+}
+
+impl<'de> Visitor<'de> for MapVisitor {
+    type Value = Schema;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map schema")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        Ok(Schema::Map {
+            key: Box::new(field(&mut seq, self.0, 0, &self)?),
+            value: Box::new(field(&mut seq, self.0, 1, &self)?),
+        })
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let (mut key, mut value) = (None, None);
+        while let Some(field) = map.next_key()? {
+            match field {
+                SchemaField::Key => key = Some(map.next_value_seed(self.0)?),
+                SchemaField::Value => value = Some(map.next_value_seed(self.0)?),
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        Ok(Schema::Map {
+            key: Box::new(required(key, "key")?),
+            value: Box::new(required(value, "value")?),
+        })
+    }
+}
+
+impl<'de> Visitor<'de> for RecursiveVisitor {
+    type Value = Schema;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a recursive schema")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        Ok(Schema::Recursive {
+            definitions: field(&mut seq, DefinitionsSeed(self.0), 0, &self)?,
+            root: Box::new(field(&mut seq, self.0, 1, &self)?),
+        })
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let (mut definitions, mut root) = (None, None);
+        while let Some(key) = map.next_key()? {
+            match key {
+                SchemaField::Definitions => {
+                    definitions = Some(map.next_value_seed(DefinitionsSeed(self.0))?)
+                }
+                SchemaField::Root => root = Some(map.next_value_seed(self.0)?),
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        Ok(Schema::Recursive {
+            definitions: required(definitions, "definitions")?,
+            root: Box::new(required(root, "root")?),
+        })
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for DefinitionsSeed {
+    type Value = Definitions;
+
+    fn deserialize<D: Deserializer<'de>>(self, deser: D) -> Result<Self::Value, D::Error> {
+        deser.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for DefinitionsSeed {
+    type Value = Definitions;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("definitions")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut definitions = BTreeMap::new();
+        while let Some((name, schema)) = map.next_entry_seed(PhantomData, self.0)? {
+            definitions.insert(name, schema);
+        }
+        Ok(definitions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LimitedSchemaSeed;
+    use crate::{EnumSchema, Schema, StructSchema, VariantSchema};
+    use bincode::Options;
+    use serde::de::DeserializeSeed;
+    use std::collections::BTreeMap;
+
+    fn nested(depth: usize) -> Schema {
+        let mut schema = Schema::Unit;
+        for _ in 0..depth {
+            schema = Schema::Option(Box::new(schema));
+        }
+        schema
+    }
+
+    #[test]
+    fn test_schema() {
+        let tree = Schema::Struct(StructSchema {
+            name: "Tree".to_string(),
+            fields: vec![
+                (
+                    "value".to_string(),
+                    Schema::Tuple(vec![Schema::I8, Schema::Char]),
+                ),
+                (
+                    "children".to_string(),
+                    Schema::UniformSequence(Box::new(Schema::Ref("Tree".to_string()))),
+                ),
+            ],
+        });
+        let schema = Schema::Recursive {
+            definitions: BTreeMap::from([("Tree".to_string(), tree)]),
+            root: Box::new(Schema::Enum(EnumSchema {
+                name: "Shape".to_string(),
+                variants: vec![
+                    ("Empty".to_string(), VariantSchema::Unit),
+                    (
+                        "Tree".to_string(),
+                        VariantSchema::Newtype(Box::new(Schema::Ref("Tree".to_string()))),
+                    ),
+                    (
+                        "Pair".to_string(),
+                        VariantSchema::Tuple(vec![Schema::Bytes, Schema::UnitStruct("U".into())]),
+                    ),
+                    (
+                        "Named".to_string(),
+                        VariantSchema::Struct(StructSchema {
+                            name: "Named".to_string(),
+                            fields: vec![(
+                                "map".to_string(),
+                                Schema::Map {
+                                    key: Box::new(Schema::String),
+                                    value: Box::new(Schema::NewtypeStruct(
+                                        "N".to_string(),
+                                        Box::new(Schema::TupleStruct(
+                                            "T".to_string(),
+                                            vec![Schema::F64, Schema::U128],
+                                        )),
+                                    )),
+                                },
+                            )],
+                        }),
+                    ),
+                ],
+            })),
+        };
+
+        let bytes = bincode::DefaultOptions::new().serialize(&schema).unwrap();
+        let mut deser = bincode::Deserializer::from_slice(&bytes, bincode::DefaultOptions::new());
+        let decoded = LimitedSchemaSeed::default()
+            .deserialize(&mut deser)
+            .unwrap();
+        assert_eq!(decoded, schema);
+
+        let json = serde_json::to_string(&schema).unwrap();
+        let mut deser = serde_json::Deserializer::from_str(&json);
+        let decoded = LimitedSchemaSeed::default()
+            .deserialize(&mut deser)
+            .unwrap();
+        assert_eq!(decoded, schema);
+    }
+
+    #[test]
+    fn test_schema_depth() {
+        let decode = |schema: &Schema| {
+            let bytes = bincode::DefaultOptions::new().serialize(schema).unwrap();
+            let mut deser =
+                bincode::Deserializer::from_slice(&bytes, bincode::DefaultOptions::new());
+            LimitedSchemaSeed::default().deserialize(&mut deser)
+        };
+
+        assert_eq!(decode(&nested(63)).unwrap(), nested(63));
+        assert!(decode(&nested(64)).is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{string_to_static, DynamicValue};
+use crate::{string_to_static, DynamicValue, VariantValue};
 use serde::ser::*;
 use serde::Serialize;
 
@@ -15,6 +15,13 @@ impl Serialize for DynamicValue {
                 }
                 ser.end()
             }
+            DynamicValue::Map(entries) => {
+                let mut ser = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    ser.serialize_entry(key, value)?;
+                }
+                ser.end()
+            }
             DynamicValue::Struct { name, fields } => {
                 let mut ser =
                     serializer.serialize_struct(string_to_static(name.clone()), fields.len())?;
@@ -47,12 +54,50 @@ impl Serialize for DynamicValue {
             DynamicValue::UnitStruct(name) => {
                 serializer.serialize_unit_struct(string_to_static(name.clone()))
             }
-            DynamicValue::Enum(schema, idx) => serializer.serialize_unit_variant(
-                string_to_static(schema.name.clone()),
-                *idx,
-                string_to_static(schema.variants[*idx as usize].clone()),
-            ),
+            DynamicValue::Enum {
+                name,
+                variant,
+                index,
+                value,
+            } => {
+                let name = string_to_static(name.clone());
+                let variant = string_to_static(variant.clone());
+
+                match value {
+                    VariantValue::Unit => serializer.serialize_unit_variant(name, *index, variant),
+                    VariantValue::Newtype(value) => {
+                        serializer.serialize_newtype_variant(name, *index, variant, value)
+                    }
+                    VariantValue::Tuple(fields) => {
+                        let mut ser = serializer.serialize_tuple_variant(
+                            name,
+                            *index,
+                            variant,
+                            fields.len(),
+                        )?;
+                        for field in fields {
+                            ser.serialize_field(field)?;
+                        }
+                        ser.end()
+                    }
+                    VariantValue::Struct(fields) => {
+                        let mut ser = serializer.serialize_struct_variant(
+                            name,
+                            *index,
+                            variant,
+                            fields.len(),
+                        )?;
+                        for (name, value) in fields {
+                            ser.serialize_field(string_to_static(name.clone()), value)?;
+                        }
+                        ser.end()
+                    }
+                }
+            }
             DynamicValue::String(s) => serializer.serialize_str(s),
+            DynamicValue::Bytes(b) => serializer.serialize_bytes(b),
+            DynamicValue::Option(Some(value)) => serializer.serialize_some(value),
+            DynamicValue::Option(None) => serializer.serialize_none(),
             DynamicValue::I8(v) => serializer.serialize_i8(*v),
             DynamicValue::U8(v) => serializer.serialize_u8(*v),
             DynamicValue::I16(v) => serializer.serialize_i16(*v),
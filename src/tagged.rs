@@ -0,0 +1,500 @@
+//! Self-describing encoding of DynamicValues, for use with `#[serde(with = "...")]`.
+//!
+//! The Serialize impl of DynamicValue writes the value exactly as the original type would, so
+//! that the bytes can only be read back with the help of a schema. Here each value is written
+//! along with what kind of value it is, so that it can be read back without one.
+
+use serde::de::{
+    self, DeserializeSeed, EnumAccess, IgnoredAny, MapAccess, SeqAccess, VariantAccess, Visitor,
+};
+use serde::ser::{SerializeStructVariant, SerializeTupleVariant};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::marker::PhantomData;
+
+use crate::deserialize::RECURSION_LIMIT;
+use crate::seed::{field, required, Pair, Seq};
+use crate::{DynamicValue, VariantValue};
+
+pub(crate) fn serialize<S: Serializer>(value: &DynamicValue, ser: S) -> Result<S::Ok, S::Error> {
+    TaggedValue(value).serialize(ser)
+}
+
+pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deser: D) -> Result<DynamicValue, D::Error> {
+    ValueSeed { depth: 0 }.deserialize(deser)
+}
+
+/// The same, for the data carried by an enum variant
+pub(crate) mod variant {
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(
+        value: &VariantValue,
+        ser: S,
+    ) -> Result<S::Ok, S::Error> {
+        TaggedVariant(value).serialize(ser)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deser: D,
+    ) -> Result<VariantValue, D::Error> {
+        VariantSeed { depth: 0 }.deserialize(deser)
+    }
+}
+
+/// Names of the variants of the tagged encoding of a DynamicValue, in order
+const VALUE_VARIANTS: &[&str] = &[
+    "I8",
+    "U8",
+    "I16",
+    "U16",
+    "I32",
+    "U32",
+    "I64",
+    "U64",
+    "I128",
+    "U128",
+    "F32",
+    "F64",
+    "Bool",
+    "Char",
+    "Unit",
+    "Bytes",
+    "String",
+    "Option",
+    "TupleStruct",
+    "NewtypeStruct",
+    "Struct",
+    "Tuple",
+    "UniformSequence",
+    "Map",
+    "UnitStruct",
+    "Enum",
+];
+
+/// Names of the variants of the tagged encoding of a VariantValue, in order
+const VARIANT_VARIANTS: &[&str] = &["Struct", "Tuple", "Newtype", "Unit"];
+
+#[derive(Deserialize)]
+#[serde(variant_identifier)]
+enum ValueKind {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    I64,
+    U64,
+    I128,
+    U128,
+    F32,
+    F64,
+    Bool,
+    Char,
+    Unit,
+    Bytes,
+    String,
+    Option,
+    TupleStruct,
+    NewtypeStruct,
+    Struct,
+    Tuple,
+    UniformSequence,
+    Map,
+    UnitStruct,
+    Enum,
+}
+
+#[derive(Deserialize)]
+#[serde(variant_identifier)]
+enum VariantKind {
+    Struct,
+    Tuple,
+    Newtype,
+    Unit,
+}
+
+/// Fields of the struct variants, `Struct` and `Enum`
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "lowercase")]
+enum Field {
+    Name,
+    Fields,
+    Variant,
+    Index,
+    Value,
+    #[serde(other)]
+    Other,
+}
+
+/// Reads a tagged DynamicValue nested `depth` values deep, stopping at `RECURSION_LIMIT` rather
+/// than overflowing the stack
+#[derive(Clone, Copy)]
+struct ValueSeed {
+    depth: usize,
+}
+
+impl ValueSeed {
+    /// Seed for the values within this one
+    fn next(self) -> Self {
+        Self {
+            depth: self.depth + 1,
+        }
+    }
+}
+
+/// Reads a tagged VariantValue, whose values are nested `depth` deep
+#[derive(Clone, Copy)]
+struct VariantSeed {
+    depth: usize,
+}
+
+/// Reads an optional value
+#[derive(Clone, Copy)]
+struct OptionSeed(ValueSeed);
+
+/// Visits the `name` and `fields` of a `Struct`
+struct StructVisitor(ValueSeed);
+
+/// Visits the `name`, `variant`, `index` and `value` of an `Enum`
+struct EnumVisitor(ValueSeed);
+
+impl<'de> DeserializeSeed<'de> for ValueSeed {
+    type Value = DynamicValue;
+
+    fn deserialize<D: Deserializer<'de>>(self, deser: D) -> Result<Self::Value, D::Error> {
+        if self.depth >= RECURSION_LIMIT {
+            return Err(de::Error::custom("recursion limit exceeded"));
+        }
+
+        deser.deserialize_enum("TaggedValue", VALUE_VARIANTS, self)
+    }
+}
+
+impl<'de> Visitor<'de> for ValueSeed {
+    type Value = DynamicValue;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a tagged value")
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+        let next = self.next();
+        let (kind, access) = data.variant()?;
+        Ok(match kind {
+            ValueKind::I8 => DynamicValue::I8(access.newtype_variant()?),
+            ValueKind::U8 => DynamicValue::U8(access.newtype_variant()?),
+            ValueKind::I16 => DynamicValue::I16(access.newtype_variant()?),
+            ValueKind::U16 => DynamicValue::U16(access.newtype_variant()?),
+            ValueKind::I32 => DynamicValue::I32(access.newtype_variant()?),
+            ValueKind::U32 => DynamicValue::U32(access.newtype_variant()?),
+            ValueKind::I64 => DynamicValue::I64(access.newtype_variant()?),
+            ValueKind::U64 => DynamicValue::U64(access.newtype_variant()?),
+            ValueKind::I128 => DynamicValue::I128(access.newtype_variant()?),
+            ValueKind::U128 => DynamicValue::U128(access.newtype_variant()?),
+            ValueKind::F32 => DynamicValue::F32(access.newtype_variant()?),
+            ValueKind::F64 => DynamicValue::F64(access.newtype_variant()?),
+            ValueKind::Bool => DynamicValue::Bool(access.newtype_variant()?),
+            ValueKind::Char => DynamicValue::Char(access.newtype_variant()?),
+            ValueKind::Unit => {
+                access.unit_variant()?;
+                DynamicValue::Unit
+            }
+            ValueKind::Bytes => DynamicValue::Bytes(access.newtype_variant()?),
+            ValueKind::String => DynamicValue::String(access.newtype_variant()?),
+            ValueKind::Option => {
+                let value = access.newtype_variant_seed(OptionSeed(next))?;
+                DynamicValue::Option(value.map(Box::new))
+            }
+            ValueKind::TupleStruct => {
+                let (name, values) = access.tuple_variant(2, Pair(PhantomData, Seq(next)))?;
+                DynamicValue::TupleStruct(name, values)
+            }
+            ValueKind::NewtypeStruct => {
+                let (name, value) = access.tuple_variant(2, Pair(PhantomData, next))?;
+                DynamicValue::NewtypeStruct(name, Box::new(value))
+            }
+            ValueKind::Struct => access.struct_variant(&["name", "fields"], StructVisitor(next))?,
+            ValueKind::Tuple => DynamicValue::Tuple(access.newtype_variant_seed(Seq(next))?),
+            ValueKind::UniformSequence => {
+                DynamicValue::UniformSequence(access.newtype_variant_seed(Seq(next))?)
+            }
+            ValueKind::Map => {
+                DynamicValue::Map(access.newtype_variant_seed(Seq(Pair(next, next)))?)
+            }
+            ValueKind::UnitStruct => DynamicValue::UnitStruct(access.newtype_variant()?),
+            ValueKind::Enum => {
+                access.struct_variant(&["name", "variant", "index", "value"], EnumVisitor(next))?
+            }
+        })
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for VariantSeed {
+    type Value = VariantValue;
+
+    fn deserialize<D: Deserializer<'de>>(self, deser: D) -> Result<Self::Value, D::Error> {
+        deser.deserialize_enum("TaggedVariant", VARIANT_VARIANTS, self)
+    }
+}
+
+impl<'de> Visitor<'de> for VariantSeed {
+    type Value = VariantValue;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a tagged variant")
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+        let seed = ValueSeed { depth: self.depth };
+        let (kind, access) = data.variant()?;
+        Ok(match kind {
+            VariantKind::Struct => {
+                VariantValue::Struct(access.newtype_variant_seed(Seq(Pair(PhantomData, seed)))?)
+            }
+            VariantKind::Tuple => VariantValue::Tuple(access.newtype_variant_seed(Seq(seed))?),
+            VariantKind::Newtype => {
+                VariantValue::Newtype(Box::new(access.newtype_variant_seed(seed)?))
+            }
+            VariantKind::Unit => {
+                access.unit_variant()?;
+                VariantValue::Unit
+            }
+        })
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for OptionSeed {
+    type Value = Option<DynamicValue>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deser: D) -> Result<Self::Value, D::Error> {
+        deser.deserialize_option(self)
+    }
+}
+
+impl<'de> Visitor<'de> for OptionSeed {
+    type Value = Option<DynamicValue>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an option")
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deser: D) -> Result<Self::Value, D::Error> {
+        self.0.deserialize(deser).map(Some)
+    }
+}
+
+impl<'de> Visitor<'de> for StructVisitor {
+    type Value = DynamicValue;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a tagged struct")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let name = field(&mut seq, PhantomData, 0, &self)?;
+        let fields = field(&mut seq, Seq(Pair(PhantomData, self.0)), 1, &self)?;
+        Ok(DynamicValue::Struct { name, fields })
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let (mut name, mut fields) = (None, None);
+        while let Some(key) = map.next_key()? {
+            match key {
+                Field::Name => name = Some(map.next_value()?),
+                Field::Fields => {
+                    fields = Some(map.next_value_seed(Seq(Pair(PhantomData, self.0)))?)
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        Ok(DynamicValue::Struct {
+            name: required(name, "name")?,
+            fields: required(fields, "fields")?,
+        })
+    }
+}
+
+impl<'de> Visitor<'de> for EnumVisitor {
+    type Value = DynamicValue;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a tagged enum")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let variant_seed = VariantSeed {
+            depth: self.0.depth,
+        };
+        Ok(DynamicValue::Enum {
+            name: field(&mut seq, PhantomData, 0, &self)?,
+            variant: field(&mut seq, PhantomData, 1, &self)?,
+            index: field(&mut seq, PhantomData, 2, &self)?,
+            value: field(&mut seq, variant_seed, 3, &self)?,
+        })
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let (mut name, mut variant, mut index, mut value) = (None, None, None, None);
+        while let Some(key) = map.next_key()? {
+            match key {
+                Field::Name => name = Some(map.next_value()?),
+                Field::Variant => variant = Some(map.next_value()?),
+                Field::Index => index = Some(map.next_value()?),
+                Field::Value => {
+                    value = Some(map.next_value_seed(VariantSeed {
+                        depth: self.0.depth,
+                    })?)
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        Ok(DynamicValue::Enum {
+            name: required(name, "name")?,
+            variant: required(variant, "variant")?,
+            index: required(index, "index")?,
+            value: required(value, "value")?,
+        })
+    }
+}
+
+/// Borrowed view of a DynamicValue, serialized in the tagged encoding that ValueSeed reads
+struct TaggedValue<'a>(&'a DynamicValue);
+
+/// Borrowed view of a VariantValue, serialized in the tagged encoding that VariantSeed reads
+struct TaggedVariant<'a>(&'a VariantValue);
+
+/// A list of values, each tagged
+struct TaggedValues<'a>(&'a [DynamicValue]);
+
+/// A list of named values, each tagged
+struct TaggedFields<'a>(&'a [(String, DynamicValue)]);
+
+/// A list of map entries, each key and value tagged
+struct TaggedEntries<'a>(&'a [(DynamicValue, DynamicValue)]);
+
+impl Serialize for TaggedValue<'_> {
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        const NAME: &str = "TaggedValue";
+
+        match self.0 {
+            DynamicValue::I8(v) => ser.serialize_newtype_variant(NAME, 0, "I8", v),
+            DynamicValue::U8(v) => ser.serialize_newtype_variant(NAME, 1, "U8", v),
+            DynamicValue::I16(v) => ser.serialize_newtype_variant(NAME, 2, "I16", v),
+            DynamicValue::U16(v) => ser.serialize_newtype_variant(NAME, 3, "U16", v),
+            DynamicValue::I32(v) => ser.serialize_newtype_variant(NAME, 4, "I32", v),
+            DynamicValue::U32(v) => ser.serialize_newtype_variant(NAME, 5, "U32", v),
+            DynamicValue::I64(v) => ser.serialize_newtype_variant(NAME, 6, "I64", v),
+            DynamicValue::U64(v) => ser.serialize_newtype_variant(NAME, 7, "U64", v),
+            DynamicValue::I128(v) => ser.serialize_newtype_variant(NAME, 8, "I128", v),
+            DynamicValue::U128(v) => ser.serialize_newtype_variant(NAME, 9, "U128", v),
+            DynamicValue::F32(v) => ser.serialize_newtype_variant(NAME, 10, "F32", v),
+            DynamicValue::F64(v) => ser.serialize_newtype_variant(NAME, 11, "F64", v),
+            DynamicValue::Bool(v) => ser.serialize_newtype_variant(NAME, 12, "Bool", v),
+            DynamicValue::Char(v) => ser.serialize_newtype_variant(NAME, 13, "Char", v),
+            DynamicValue::Unit => ser.serialize_unit_variant(NAME, 14, "Unit"),
+            DynamicValue::Bytes(v) => ser.serialize_newtype_variant(NAME, 15, "Bytes", v),
+            DynamicValue::String(v) => ser.serialize_newtype_variant(NAME, 16, "String", v),
+            DynamicValue::Option(v) => {
+                let v = v.as_deref().map(TaggedValue);
+                ser.serialize_newtype_variant(NAME, 17, "Option", &v)
+            }
+            DynamicValue::TupleStruct(name, values) => {
+                let mut state = ser.serialize_tuple_variant(NAME, 18, "TupleStruct", 2)?;
+                state.serialize_field(name)?;
+                state.serialize_field(&TaggedValues(values))?;
+                state.end()
+            }
+            DynamicValue::NewtypeStruct(name, v) => {
+                let mut state = ser.serialize_tuple_variant(NAME, 19, "NewtypeStruct", 2)?;
+                state.serialize_field(name)?;
+                state.serialize_field(&TaggedValue(v))?;
+                state.end()
+            }
+            DynamicValue::Struct { name, fields } => {
+                let mut state = ser.serialize_struct_variant(NAME, 20, "Struct", 2)?;
+                state.serialize_field("name", name)?;
+                state.serialize_field("fields", &TaggedFields(fields))?;
+                state.end()
+            }
+            DynamicValue::Tuple(values) => {
+                ser.serialize_newtype_variant(NAME, 21, "Tuple", &TaggedValues(values))
+            }
+            DynamicValue::UniformSequence(values) => {
+                let values = TaggedValues(values);
+                ser.serialize_newtype_variant(NAME, 22, "UniformSequence", &values)
+            }
+            DynamicValue::Map(entries) => {
+                ser.serialize_newtype_variant(NAME, 23, "Map", &TaggedEntries(entries))
+            }
+            DynamicValue::UnitStruct(name) => {
+                ser.serialize_newtype_variant(NAME, 24, "UnitStruct", name)
+            }
+            DynamicValue::Enum {
+                name,
+                variant,
+                index,
+                value,
+            } => {
+                let mut state = ser.serialize_struct_variant(NAME, 25, "Enum", 4)?;
+                state.serialize_field("name", name)?;
+                state.serialize_field("variant", variant)?;
+                state.serialize_field("index", index)?;
+                state.serialize_field("value", &TaggedVariant(value))?;
+                state.end()
+            }
+        }
+    }
+}
+
+impl Serialize for TaggedVariant<'_> {
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        const NAME: &str = "TaggedVariant";
+
+        match self.0 {
+            VariantValue::Struct(fields) => {
+                ser.serialize_newtype_variant(NAME, 0, "Struct", &TaggedFields(fields))
+            }
+            VariantValue::Tuple(values) => {
+                ser.serialize_newtype_variant(NAME, 1, "Tuple", &TaggedValues(values))
+            }
+            VariantValue::Newtype(v) => {
+                ser.serialize_newtype_variant(NAME, 2, "Newtype", &TaggedValue(v))
+            }
+            VariantValue::Unit => ser.serialize_unit_variant(NAME, 3, "Unit"),
+        }
+    }
+}
+
+impl Serialize for TaggedValues<'_> {
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        ser.collect_seq(self.0.iter().map(TaggedValue))
+    }
+}
+
+impl Serialize for TaggedFields<'_> {
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        ser.collect_seq(self.0.iter().map(|(name, v)| (name, TaggedValue(v))))
+    }
+}
+
+impl Serialize for TaggedEntries<'_> {
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        ser.collect_seq(self.0.iter().map(|(k, v)| (TaggedValue(k), TaggedValue(v))))
+    }
+}
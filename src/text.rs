@@ -0,0 +1,890 @@
+//! Human-readable notation for DynamicValues, e.g. `Person { gender: Gender::Sedan, age: 30 }`.
+//!
+//! DynamicValues are printed with `Display`, on a single line by default, or spread over
+//! several with `{:#}`. Text is parsed back with the help of a schema, which decides the width
+//! of each number, so type names may be left out:
+//!
+//! * Structs: `Name { field: value, .. }`, or `{ field: value, .. }`. Fields may be in any order
+//! * Tuple structs `Name(a, b)`, newtype structs `Name(a)` and unit structs `Name`
+//! * Enums: `Name::Variant`, with the data of the variant as for structs, e.g. `Variant(a, b)`
+//! * Options: `Some(value)` or `None`
+//! * Sequences `[a, b]`, tuples `(a, b)`, maps `{ key: value }` and the unit value `()`
+//! * Strings and chars as in Rust, and bytes as byte strings, e.g. `b"\x00\xff"`
+//!
+//! Trailing commas and `//` comments are allowed.
+
+use std::fmt::{self, Display, Write};
+
+use crate::deserialize::RECURSION_LIMIT;
+use crate::error::KobbleError;
+use crate::path::{PathSegment, ValuePath};
+use crate::scope::Scopes;
+use crate::{DynamicValue, EnumSchema, Schema, StructSchema, VariantSchema, VariantValue};
+
+const INDENT: &str = "    ";
+
+impl Display for DynamicValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pretty = f.alternate();
+        Printer {
+            out: f,
+            pretty,
+            depth: 0,
+        }
+        .value(self)
+    }
+}
+
+struct Printer<'a, 'b> {
+    out: &'a mut fmt::Formatter<'b>,
+    pretty: bool,
+    depth: usize,
+}
+
+impl Printer<'_, '_> {
+    fn newline(&mut self) -> fmt::Result {
+        self.out.write_char('\n')?;
+        for _ in 0..self.depth {
+            self.out.write_str(INDENT)?;
+        }
+        Ok(())
+    }
+
+    /// Print items between brackets, one per line when pretty printing
+    fn list<T>(
+        &mut self,
+        open: &str,
+        close: &str,
+        items: &[T],
+        mut item: impl FnMut(&mut Self, &T) -> fmt::Result,
+    ) -> fmt::Result {
+        self.out.write_str(open)?;
+        if items.is_empty() {
+            return self.out.write_str(close);
+        }
+
+        // Braces get some room, as in Rust
+        let padded = open.ends_with('{');
+
+        self.depth += 1;
+        for (i, value) in items.iter().enumerate() {
+            if self.pretty {
+                self.newline()?;
+            } else if i > 0 || padded {
+                self.out.write_char(' ')?;
+            }
+
+            item(self, value)?;
+
+            if self.pretty || i + 1 < items.len() {
+                self.out.write_char(',')?;
+            }
+        }
+        self.depth -= 1;
+
+        if self.pretty {
+            self.newline()?;
+        } else if padded {
+            self.out.write_char(' ')?;
+        } else if items.len() == 1 && open == "(" {
+            // A tuple with one element
+            self.out.write_char(',')?;
+        }
+
+        self.out.write_str(close)
+    }
+
+    fn elements(&mut self, open: &str, values: &[DynamicValue]) -> fmt::Result {
+        let close = if open.ends_with('[') { "]" } else { ")" };
+        self.list(open, close, values, |p, v| p.value(v))
+    }
+
+    fn fields(&mut self, name: &str, fields: &[(String, DynamicValue)]) -> fmt::Result {
+        self.list(&format!("{} {{", name), "}", fields, |p, (name, value)| {
+            write!(p.out, "{}: ", name)?;
+            p.value(value)
+        })
+    }
+
+    fn value(&mut self, value: &DynamicValue) -> fmt::Result {
+        match value {
+            DynamicValue::I8(v) => write!(self.out, "{}", v),
+            DynamicValue::U8(v) => write!(self.out, "{}", v),
+            DynamicValue::I16(v) => write!(self.out, "{}", v),
+            DynamicValue::U16(v) => write!(self.out, "{}", v),
+            DynamicValue::I32(v) => write!(self.out, "{}", v),
+            DynamicValue::U32(v) => write!(self.out, "{}", v),
+            DynamicValue::I64(v) => write!(self.out, "{}", v),
+            DynamicValue::U64(v) => write!(self.out, "{}", v),
+            DynamicValue::I128(v) => write!(self.out, "{}", v),
+            DynamicValue::U128(v) => write!(self.out, "{}", v),
+            // Debug always includes a decimal point or exponent
+            DynamicValue::F32(v) => write!(self.out, "{:?}", v),
+            DynamicValue::F64(v) => write!(self.out, "{:?}", v),
+            DynamicValue::Bool(v) => write!(self.out, "{}", v),
+            DynamicValue::Char(v) => write!(self.out, "{:?}", v),
+            DynamicValue::Unit => self.out.write_str("()"),
+            DynamicValue::Bytes(v) => write!(self.out, "b\"{}\"", v.escape_ascii()),
+            DynamicValue::String(v) => write!(self.out, "{:?}", v),
+            DynamicValue::Option(None) => self.out.write_str("None"),
+            DynamicValue::Option(Some(v)) => {
+                self.out.write_str("Some(")?;
+                self.value(v)?;
+                self.out.write_char(')')
+            }
+            DynamicValue::Struct { name, fields } => self.fields(name, fields),
+            DynamicValue::Tuple(values) => self.elements("(", values),
+            DynamicValue::TupleStruct(name, values) => self.elements(&format!("{}(", name), values),
+            DynamicValue::NewtypeStruct(name, v) => {
+                write!(self.out, "{}(", name)?;
+                self.value(v)?;
+                self.out.write_char(')')
+            }
+            DynamicValue::UnitStruct(name) => self.out.write_str(name),
+            DynamicValue::UniformSequence(values) => self.elements("[", values),
+            DynamicValue::Map(entries) => self.list("{", "}", entries, |p, (k, v)| {
+                p.value(k)?;
+                p.out.write_str(": ")?;
+                p.value(v)
+            }),
+            DynamicValue::Enum {
+                name,
+                variant,
+                value,
+                ..
+            } => {
+                let name = format!("{}::{}", name, variant);
+                match value {
+                    VariantValue::Unit => self.out.write_str(&name),
+                    VariantValue::Newtype(v) => {
+                        write!(self.out, "{}(", name)?;
+                        self.value(v)?;
+                        self.out.write_char(')')
+                    }
+                    VariantValue::Tuple(values) => self.elements(&format!("{}(", name), values),
+                    VariantValue::Struct(fields) => self.fields(&name, fields),
+                }
+            }
+        }
+    }
+}
+
+/// Parse text describing a value of the given schema
+pub fn parse(text: &str, schema: &Schema) -> Result<DynamicValue, KobbleError> {
+    parse_with_recursion_limit(text, schema, Some(RECURSION_LIMIT))
+}
+
+/// As `parse`, allowing references to nest `limit` deep, or however deep they go with `None`.
+/// Each reference takes more stack, so the thread parsing must have enough for the text it reads.
+pub fn parse_with_recursion_limit(
+    text: &str,
+    schema: &Schema,
+    limit: Option<usize>,
+) -> Result<DynamicValue, KobbleError> {
+    let mut parser = Parser::new(text);
+    parser.recursion_limit = limit;
+    let value = parser.value(schema)?;
+    parser.path = ValuePath::root();
+    parser.finish()?;
+    Ok(value)
+}
+
+/// Parses values, and provides the tokens of the notation to other parsers, such as the IDL
+pub(crate) struct Parser<'a> {
+    text: &'a str,
+    /// Byte offset of the next character
+    pub(crate) pos: usize,
+    scopes: Scopes<'a>,
+    path: ValuePath,
+    /// How many references the current value is nested in
+    depth: usize,
+    recursion_limit: Option<usize>,
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn is_ident(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+impl<'a> Parser<'a> {
+    pub(crate) fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            pos: 0,
+            scopes: Scopes::default(),
+            path: ValuePath::root(),
+            depth: 0,
+            recursion_limit: Some(RECURSION_LIMIT),
+        }
+    }
+
+    /// Check that nothing but whitespace is left
+    pub(crate) fn finish(&mut self) -> Result<(), KobbleError> {
+        if self.at_end() {
+            Ok(())
+        } else {
+            Err(self.error("expected the end of the text"))
+        }
+    }
+
+    pub(crate) fn at_end(&mut self) -> bool {
+        self.skip_whitespace();
+        self.pos == self.text.len()
+    }
+
+    pub(crate) fn error(&self, message: impl Display) -> KobbleError {
+        let before = &self.text[..self.pos];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+
+        KobbleError::InvalidFormat {
+            path: self.path.clone(),
+            message: format!("{} at line {}, column {}", message, line, column),
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    /// Skip whitespace and comments
+    pub(crate) fn skip_whitespace(&mut self) {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+
+            if trimmed.starts_with("//") {
+                self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else {
+                return;
+            }
+        }
+    }
+
+    /// Consume the given text, if it comes next
+    pub(crate) fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    pub(crate) fn expect(&mut self, token: &str) -> Result<(), KobbleError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error(format_args!("expected `{}`", token)))
+        }
+    }
+
+    /// Consume the next identifier, if there is one
+    pub(crate) fn ident(&mut self) -> Option<&'a str> {
+        self.skip_whitespace();
+        let rest = self.rest();
+        if !rest.starts_with(is_ident_start) {
+            return None;
+        }
+
+        let len = rest.find(|c| !is_ident(c)).unwrap_or(rest.len());
+        self.pos += len;
+        Some(&rest[..len])
+    }
+
+    pub(crate) fn expect_ident(&mut self, what: &str) -> Result<&'a str, KobbleError> {
+        self.ident()
+            .ok_or_else(|| self.error(format_args!("expected {}", what)))
+    }
+
+    /// Parse a quoted string
+    pub(crate) fn string(&mut self) -> Result<String, KobbleError> {
+        self.skip_whitespace();
+        self.quoted('"', false)
+    }
+
+    /// Consume the name of a type, if one is given, checking that it is the expected one
+    fn type_name(&mut self, expected: &str) -> Result<(), KobbleError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        match self.ident() {
+            Some(name) if name != expected => {
+                self.pos = start;
+                Err(self.error(format_args!("expected type {}, found {}", expected, name)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Parse a comma-separated list of items, up to the closing bracket
+    pub(crate) fn list(
+        &mut self,
+        close: &str,
+        mut item: impl FnMut(&mut Self, usize) -> Result<(), KobbleError>,
+    ) -> Result<(), KobbleError> {
+        for i in 0.. {
+            if self.eat(close) {
+                break;
+            }
+
+            item(self, i)?;
+
+            if !self.eat(",") {
+                self.expect(close)?;
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parse a value nested one step further in
+    fn nested(
+        &mut self,
+        segment: PathSegment,
+        schema: &'a Schema,
+    ) -> Result<DynamicValue, KobbleError> {
+        self.path.push(segment);
+        let value = self.value(schema)?;
+        self.path.0.pop();
+        Ok(value)
+    }
+
+    fn elements(
+        &mut self,
+        close: &str,
+        schemas: &'a [Schema],
+    ) -> Result<Vec<DynamicValue>, KobbleError> {
+        let mut values = vec![];
+        self.list(close, |p, i| {
+            let schema = schemas
+                .get(i)
+                .ok_or_else(|| p.error(format_args!("expected {} elements", schemas.len())))?;
+            values.push(p.nested(PathSegment::Index(i), schema)?);
+            Ok(())
+        })?;
+
+        if values.len() != schemas.len() {
+            return Err(self.error(format_args!(
+                "expected {} elements, found {}",
+                schemas.len(),
+                values.len()
+            )));
+        }
+
+        Ok(values)
+    }
+
+    /// Parse the fields of a struct or struct variant, after the opening brace
+    fn fields(
+        &mut self,
+        schema: &'a StructSchema,
+    ) -> Result<Vec<(String, DynamicValue)>, KobbleError> {
+        let mut found: Vec<(&str, DynamicValue)> = vec![];
+        self.list("}", |p, _| {
+            p.skip_whitespace();
+            let start = p.pos;
+            let name = p.expect_ident("a field name")?;
+            let Some((_, field)) = schema.fields.iter().find(|(n, _)| n == name) else {
+                p.pos = start;
+                return Err(p.error(format_args!("no field {} in {}", name, schema.name)));
+            };
+            if found.iter().any(|(n, _)| *n == name) {
+                p.pos = start;
+                return Err(p.error(format_args!("field {} given twice", name)));
+            }
+
+            p.expect(":")?;
+            found.push((name, p.nested(PathSegment::Field(name.to_string()), field)?));
+            Ok(())
+        })?;
+
+        // Put the fields in the order given by the schema
+        let mut fields = vec![];
+        for (name, _) in &schema.fields {
+            let i = found
+                .iter()
+                .position(|(n, _)| n == name)
+                .ok_or_else(|| self.error(format_args!("missing field {}", name)))?;
+            fields.push((name.clone(), found.swap_remove(i).1));
+        }
+
+        Ok(fields)
+    }
+
+    /// Parse a value of the given schema. Each kind of value is parsed by a function of its own,
+    /// keeping this one small, as it is on the stack once for every level of nesting.
+    fn value(&mut self, schema: &'a Schema) -> Result<DynamicValue, KobbleError> {
+        match schema {
+            Schema::Recursive { definitions, root } => {
+                self.scopes.push(definitions);
+                let value = self.value(root);
+                self.scopes.pop();
+                value
+            }
+            Schema::Ref(name) => self.reference(name),
+            Schema::Option(inner) => self.option(inner),
+            Schema::Struct(schema) => self.structure(schema),
+            Schema::Tuple(schemas) => {
+                self.expect("(")?;
+                Ok(DynamicValue::Tuple(self.elements(")", schemas)?))
+            }
+            Schema::TupleStruct(name, schemas) => self.tuple_struct(name, schemas),
+            Schema::NewtypeStruct(name, inner) => self.newtype_struct(name, inner),
+            Schema::UnitStruct(name) => self.unit_struct(name),
+            Schema::UniformSequence(inner) => self.sequence(inner),
+            Schema::Map { key, value } => self.map(key, value),
+            Schema::Enum(schema) => self.variant(schema),
+            schema => self.primitive(schema),
+        }
+    }
+
+    fn reference(&mut self, name: &str) -> Result<DynamicValue, KobbleError> {
+        if self
+            .recursion_limit
+            .is_some_and(|limit| self.depth >= limit)
+        {
+            return Err(KobbleError::RecursionLimit {
+                path: self.path.clone(),
+                name: name.to_string(),
+            });
+        }
+
+        let schema = self
+            .scopes
+            .lookup(name)
+            .map_err(|e| e.at(self.path.clone()))?;
+
+        self.depth += 1;
+        let value = self.value(schema)?;
+        self.depth -= 1;
+        Ok(value)
+    }
+
+    fn option(&mut self, inner: &'a Schema) -> Result<DynamicValue, KobbleError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        match self.ident() {
+            Some("None") => Ok(DynamicValue::Option(None)),
+            Some("Some") => {
+                self.expect("(")?;
+                let value = self.value(inner)?;
+                self.expect(")")?;
+                Ok(DynamicValue::Option(Some(Box::new(value))))
+            }
+            _ => {
+                self.pos = start;
+                Err(self.error("expected `Some` or `None`"))
+            }
+        }
+    }
+
+    fn structure(&mut self, schema: &'a StructSchema) -> Result<DynamicValue, KobbleError> {
+        self.type_name(&schema.name)?;
+        self.expect("{")?;
+        Ok(DynamicValue::Struct {
+            name: schema.name.clone(),
+            fields: self.fields(schema)?,
+        })
+    }
+
+    fn tuple_struct(
+        &mut self,
+        name: &str,
+        schemas: &'a [Schema],
+    ) -> Result<DynamicValue, KobbleError> {
+        self.type_name(name)?;
+        self.expect("(")?;
+        Ok(DynamicValue::TupleStruct(
+            name.to_string(),
+            self.elements(")", schemas)?,
+        ))
+    }
+
+    fn newtype_struct(
+        &mut self,
+        name: &str,
+        inner: &'a Schema,
+    ) -> Result<DynamicValue, KobbleError> {
+        self.type_name(name)?;
+        self.expect("(")?;
+        let value = self.nested(PathSegment::Index(0), inner)?;
+        self.eat(",");
+        self.expect(")")?;
+        Ok(DynamicValue::NewtypeStruct(
+            name.to_string(),
+            Box::new(value),
+        ))
+    }
+
+    fn unit_struct(&mut self, name: &str) -> Result<DynamicValue, KobbleError> {
+        if self.ident() != Some(name) {
+            return Err(self.error(format_args!("expected {}", name)));
+        }
+        Ok(DynamicValue::UnitStruct(name.to_string()))
+    }
+
+    fn sequence(&mut self, inner: &'a Schema) -> Result<DynamicValue, KobbleError> {
+        self.expect("[")?;
+        let mut values = vec![];
+        self.list("]", |p, i| {
+            values.push(p.nested(PathSegment::Index(i), inner)?);
+            Ok(())
+        })?;
+        Ok(DynamicValue::UniformSequence(values))
+    }
+
+    fn map(&mut self, key: &'a Schema, value: &'a Schema) -> Result<DynamicValue, KobbleError> {
+        self.expect("{")?;
+        let mut entries = vec![];
+        self.list("}", |p, i| {
+            let k = p.nested(PathSegment::Key(i), key)?;
+            p.expect(":")?;
+            let v = p.nested(PathSegment::Index(i), value)?;
+            entries.push((k, v));
+            Ok(())
+        })?;
+        Ok(DynamicValue::Map(entries))
+    }
+
+    fn variant(&mut self, schema: &'a EnumSchema) -> Result<DynamicValue, KobbleError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let mut variant = self.expect_ident("a variant")?;
+        if self.eat("::") {
+            if variant != schema.name {
+                self.pos = start;
+                return Err(self.error(format_args!(
+                    "expected type {}, found {}",
+                    schema.name, variant
+                )));
+            }
+            variant = self.expect_ident("a variant")?;
+        }
+
+        let Some((index, (_, variant_schema))) = schema
+            .variants
+            .iter()
+            .enumerate()
+            .find(|(_, (name, _))| name == variant)
+        else {
+            self.pos = start;
+            return Err(self.error(format_args!("no variant {} in {}", variant, schema.name)));
+        };
+
+        let value = match variant_schema {
+            VariantSchema::Unit => VariantValue::Unit,
+            VariantSchema::Newtype(inner) => {
+                self.expect("(")?;
+                let value = self.nested(PathSegment::Index(0), inner)?;
+                self.eat(",");
+                self.expect(")")?;
+                VariantValue::Newtype(Box::new(value))
+            }
+            VariantSchema::Tuple(schemas) => {
+                self.expect("(")?;
+                VariantValue::Tuple(self.elements(")", schemas)?)
+            }
+            VariantSchema::Struct(schema) => {
+                self.expect("{")?;
+                VariantValue::Struct(self.fields(schema)?)
+            }
+        };
+
+        Ok(DynamicValue::Enum {
+            name: schema.name.clone(),
+            variant: variant.to_string(),
+            index: index as u32,
+            value,
+        })
+    }
+
+    /// Parse a value which contains no others
+    fn primitive(&mut self, schema: &'a Schema) -> Result<DynamicValue, KobbleError> {
+        Ok(match schema {
+            Schema::I8 => DynamicValue::I8(self.integer("i8")?),
+            Schema::U8 => DynamicValue::U8(self.integer("u8")?),
+            Schema::I16 => DynamicValue::I16(self.integer("i16")?),
+            Schema::U16 => DynamicValue::U16(self.integer("u16")?),
+            Schema::I32 => DynamicValue::I32(self.integer("i32")?),
+            Schema::U32 => DynamicValue::U32(self.integer("u32")?),
+            Schema::I64 => DynamicValue::I64(self.integer("i64")?),
+            Schema::U64 => DynamicValue::U64(self.integer("u64")?),
+            Schema::I128 => DynamicValue::I128(self.integer("i128")?),
+            Schema::U128 => DynamicValue::U128(self.integer("u128")?),
+            Schema::F32 => DynamicValue::F32(self.number("f32")?),
+            Schema::F64 => DynamicValue::F64(self.number("f64")?),
+            Schema::Bool => {
+                self.skip_whitespace();
+                let start = self.pos;
+                match self.ident() {
+                    Some("true") => DynamicValue::Bool(true),
+                    Some("false") => DynamicValue::Bool(false),
+                    _ => {
+                        self.pos = start;
+                        return Err(self.error("expected a bool"));
+                    }
+                }
+            }
+            Schema::Char => {
+                self.skip_whitespace();
+                let start = self.pos;
+                let text = self.quoted('\'', false)?;
+                let mut chars = text.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => DynamicValue::Char(c),
+                    _ => {
+                        self.pos = start;
+                        return Err(self.error("expected a single char"));
+                    }
+                }
+            }
+            Schema::Unit => {
+                self.expect("(")?;
+                self.expect(")")?;
+                DynamicValue::Unit
+            }
+            Schema::Bytes => {
+                self.skip_whitespace();
+                if !self.rest().starts_with("b\"") {
+                    return Err(self.error("expected a byte string"));
+                }
+                self.pos += 1;
+                let text = self.quoted('"', true)?;
+                DynamicValue::Bytes(text.chars().map(|c| c as u8).collect())
+            }
+            Schema::String => {
+                self.skip_whitespace();
+                DynamicValue::String(self.quoted('"', false)?)
+            }
+            schema => unreachable!("{:?} is not a primitive", schema),
+        })
+    }
+
+    /// Consume the characters which may make up a number
+    fn number_text(&mut self) -> &'a str {
+        self.skip_whitespace();
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(is_ident(c) || matches!(c, '-' | '+' | '.')))
+            .unwrap_or(rest.len());
+        self.pos += len;
+        &rest[..len]
+    }
+
+    fn integer<T: TryFrom<i128> + TryFrom<u128>>(&mut self, kind: &str) -> Result<T, KobbleError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let text = self.number_text();
+        let value = match text.parse::<i128>() {
+            Ok(v) => T::try_from(v).ok(),
+            Err(_) => text.parse::<u128>().ok().and_then(|v| T::try_from(v).ok()),
+        };
+
+        value.ok_or_else(|| {
+            self.pos = start;
+            if text.parse::<i128>().is_ok() || text.parse::<u128>().is_ok() {
+                self.error(format_args!("{} is out of range for {}", text, kind))
+            } else {
+                self.error(format_args!("expected {}", kind))
+            }
+        })
+    }
+
+    fn number<T: std::str::FromStr>(&mut self, kind: &str) -> Result<T, KobbleError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let text = self.number_text();
+        text.parse().map_err(|_| {
+            self.pos = start;
+            self.error(format_args!("expected {}", kind))
+        })
+    }
+
+    /// Parse a quoted string or char, starting at the opening quote. Bytes may only contain
+    /// ASCII, and escapes of any byte.
+    fn quoted(&mut self, quote: char, bytes: bool) -> Result<String, KobbleError> {
+        if !self.rest().starts_with(quote) {
+            return Err(self.error(format_args!("expected `{}`", quote)));
+        }
+        self.pos += 1;
+
+        let mut out = String::new();
+        loop {
+            let Some(c) = self.rest().chars().next() else {
+                return Err(self.error(format_args!("expected closing `{}`", quote)));
+            };
+
+            if c == quote {
+                self.pos += 1;
+                return Ok(out);
+            } else if bytes && !c.is_ascii() {
+                return Err(self.error("non-ASCII character in byte string"));
+            } else if c == '\\' {
+                self.pos += 1;
+                out.push(self.escape(bytes)?);
+            } else {
+                self.pos += c.len_utf8();
+                out.push(c);
+            }
+        }
+    }
+
+    /// Parse an escape sequence, after the backslash
+    fn escape(&mut self, bytes: bool) -> Result<char, KobbleError> {
+        let rest = self.rest();
+        let (c, len) = match rest.chars().next() {
+            Some('n') => ('\n', 1),
+            Some('r') => ('\r', 1),
+            Some('t') => ('\t', 1),
+            Some('0') => ('\0', 1),
+            Some(c @ ('\\' | '\'' | '"')) => (c, 1),
+            Some('x') => {
+                let value = rest
+                    .get(1..3)
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .filter(|&v| bytes || v < 0x80)
+                    .ok_or_else(|| self.error("invalid escape"))?;
+                (value as char, 3)
+            }
+            Some('u') if !bytes => {
+                let end = rest.find('}').ok_or_else(|| self.error("invalid escape"))?;
+                let value = rest
+                    .get(2..end)
+                    .filter(|_| rest[1..].starts_with('{'))
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| self.error("invalid escape"))?;
+                (value, end + 1)
+            }
+            _ => return Err(self.error("invalid escape")),
+        };
+
+        self.pos += len;
+        Ok(c)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, parse_with_recursion_limit};
+    use crate::fixtures::{everything, Everything, Gender, Person, Shape};
+    use crate::{from_dynamic, to_dynamic, DynamicValue, KobbleError, Schema, ValuePath};
+    use serde::{Deserialize, Serialize};
+
+    #[test]
+    fn test_print() {
+        let person = to_dynamic(&Person {
+            gender: Gender::Sedan,
+            age: 30,
+        })
+        .unwrap();
+
+        assert_eq!(
+            person.to_string(),
+            "Person { gender: Gender::Sedan, age: 30 }"
+        );
+        assert_eq!(
+            format!("{:#}", person),
+            "Person {\n    gender: Gender::Sedan,\n    age: 30,\n}"
+        );
+
+        let shape = to_dynamic(&Shape::Polygon(vec![(1, 2)])).unwrap();
+        assert_eq!(shape.to_string(), "Shape::Polygon([(1, 2)])");
+        assert_eq!(
+            format!("{:#}", shape),
+            "Shape::Polygon([\n    (\n        1,\n        2,\n    ),\n])"
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let schema = Schema::infer::<Everything>();
+        let value = to_dynamic(&everything()).unwrap();
+
+        for text in [value.to_string(), format!("{:#}", value)] {
+            let parsed = parse(&text, &schema).unwrap();
+            assert_eq!(parsed, value, "{}", text);
+            assert_eq!(from_dynamic::<Everything>(&parsed).unwrap(), everything());
+        }
+    }
+
+    #[test]
+    fn test_parse_by_hand() {
+        let text = "
+            // Fields in any order, type names optional
+            {
+                age: 30,
+                gender: Sedan,
+            }
+        ";
+
+        let value = parse(text, &Schema::infer::<Person>()).unwrap();
+        assert_eq!(
+            from_dynamic::<Person>(&value).unwrap(),
+            Person {
+                gender: Gender::Sedan,
+                age: 30
+            }
+        );
+        assert!(matches!(
+            value,
+            DynamicValue::Struct { ref fields, .. } if fields[1].1 == DynamicValue::U64(30)
+        ));
+
+        let value = parse("Some((7, 8))", &Schema::infer::<Option<(u8, i16)>>()).unwrap();
+        assert_eq!(
+            value,
+            DynamicValue::Option(Some(Box::new(DynamicValue::Tuple(vec![
+                DynamicValue::U8(7),
+                DynamicValue::I16(8)
+            ]))))
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let schema = Schema::infer::<Vec<Person>>();
+        let error = |text| match parse(text, &schema) {
+            Err(KobbleError::InvalidFormat { path, message }) => (path, message),
+            other => panic!("unexpected {:?}", other),
+        };
+
+        let (path, message) = error("[\n  Person { gender: Truck, age: -1 },\n]");
+        assert_eq!(path, ValuePath::root().index(0).field("age"));
+        assert_eq!(message, "-1 is out of range for u64 at line 2, column 32");
+
+        let (path, message) = error("[{ gender: Bicycle, age: 1 }]");
+        assert_eq!(path, ValuePath::root().index(0).field("gender"));
+        assert_eq!(message, "no variant Bicycle in Gender at line 1, column 12");
+
+        let (_, message) = error("[{ gender: Truck }]");
+        assert!(message.starts_with("missing field age"));
+
+        let (_, message) = error("[Animal { gender: Truck, age: 1 }]");
+        assert!(message.starts_with("expected type Person, found Animal"));
+
+        let (path, message) = error("[] []");
+        assert!(path.is_root());
+        assert_eq!(message, "expected the end of the text at line 1, column 4");
+
+        // Deeply nested input stops at the recursion limit rather than overflowing the stack
+        #[derive(Serialize, Deserialize)]
+        struct Nested(Option<Box<Nested>>);
+
+        let text = "Nested(Some(".repeat(10_000);
+        let result = parse(&text, &Schema::infer::<Nested>());
+        assert!(matches!(result, Err(KobbleError::RecursionLimit { .. })));
+
+        // Unless the limit is lifted
+        let depth = crate::RECURSION_LIMIT + 1;
+        let text = "Nested(Some(".repeat(depth) + "Nested(None)" + &"))".repeat(depth);
+        let result = parse_with_recursion_limit(&text, &Schema::infer::<Nested>(), None);
+        assert!(result.is_ok());
+    }
+}
@@ -0,0 +1,521 @@
+use crate::error::KobbleError;
+use crate::path::PathSegment;
+use crate::{DynamicValue, VariantValue};
+use serde::ser::{self, Serialize};
+
+/// Convert any serializable value directly into a DynamicValue
+pub fn to_dynamic<T: Serialize + ?Sized>(value: &T) -> Result<DynamicValue, KobbleError> {
+    value.serialize(DynamicSerializer)
+}
+
+/// Serializer which produces a DynamicValue, rather than bytes
+struct DynamicSerializer;
+
+/// Serialize an element of a compound value, recording where any error occurred
+fn element<T: Serialize + ?Sized>(
+    value: &T,
+    segment: PathSegment,
+) -> Result<DynamicValue, KobbleError> {
+    to_dynamic(value).map_err(|e| e.prefixed(segment))
+}
+
+impl ser::Serializer for DynamicSerializer {
+    type Ok = DynamicValue;
+    type Error = KobbleError;
+
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = SeqSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = StructSerializer;
+    type SerializeStructVariant = StructSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
+        Ok(DynamicValue::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
+        Ok(DynamicValue::I8(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
+        Ok(DynamicValue::I16(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
+        Ok(DynamicValue::I32(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
+        Ok(DynamicValue::I64(v))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Self::Ok, Self::Error> {
+        Ok(DynamicValue::I128(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        Ok(DynamicValue::U8(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        Ok(DynamicValue::U16(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        Ok(DynamicValue::U32(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        Ok(DynamicValue::U64(v))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Self::Ok, Self::Error> {
+        Ok(DynamicValue::U128(v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
+        Ok(DynamicValue::F32(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
+        Ok(DynamicValue::F64(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        Ok(DynamicValue::Char(v))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
+        Ok(DynamicValue::String(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        Ok(DynamicValue::Bytes(v.to_vec()))
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        Ok(DynamicValue::Option(None))
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok, Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        Ok(DynamicValue::Option(Some(Box::new(to_dynamic(value)?))))
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        Ok(DynamicValue::Unit)
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<Self::Ok, Self::Error> {
+        Ok(DynamicValue::UnitStruct(name.to_string()))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        Ok(DynamicValue::Enum {
+            name: name.to_string(),
+            variant: variant.to_string(),
+            index: variant_index,
+            value: VariantValue::Unit,
+        })
+    }
+
+    fn serialize_newtype_struct<T>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let value = element(value, PathSegment::Index(0))?;
+        Ok(DynamicValue::NewtypeStruct(
+            name.to_string(),
+            Box::new(value),
+        ))
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let value = element(value, PathSegment::Index(0))?;
+        Ok(DynamicValue::Enum {
+            name: name.to_string(),
+            variant: variant.to_string(),
+            index: variant_index,
+            value: VariantValue::Newtype(Box::new(value)),
+        })
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Ok(SeqSerializer::new(SeqKind::Sequence, len.unwrap_or(0)))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        Ok(SeqSerializer::new(SeqKind::Tuple, len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        Ok(SeqSerializer::new(SeqKind::TupleStruct(name), len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        let kind = SeqKind::Variant(name, variant_index, variant);
+        Ok(SeqSerializer::new(kind, len))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Ok(MapSerializer {
+            entries: Vec::with_capacity(len.unwrap_or(0)),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        Ok(StructSerializer::new(StructKind::Struct(name), len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        let kind = StructKind::Variant(name, variant_index, variant);
+        Ok(StructSerializer::new(kind, len))
+    }
+}
+
+/// What a sequence of values will become
+enum SeqKind {
+    Sequence,
+    Tuple,
+    TupleStruct(&'static str),
+    /// (enum name, variant index, variant name)
+    Variant(&'static str, u32, &'static str),
+}
+
+struct SeqSerializer {
+    kind: SeqKind,
+    values: Vec<DynamicValue>,
+}
+
+impl SeqSerializer {
+    fn new(kind: SeqKind, len: usize) -> Self {
+        Self {
+            kind,
+            values: Vec::with_capacity(len),
+        }
+    }
+
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), KobbleError> {
+        let value = element(value, PathSegment::Index(self.values.len()))?;
+        self.values.push(value);
+        Ok(())
+    }
+
+    fn finish(self) -> DynamicValue {
+        match self.kind {
+            SeqKind::Sequence => DynamicValue::UniformSequence(self.values),
+            SeqKind::Tuple => DynamicValue::Tuple(self.values),
+            SeqKind::TupleStruct(name) => DynamicValue::TupleStruct(name.to_string(), self.values),
+            SeqKind::Variant(name, index, variant) => DynamicValue::Enum {
+                name: name.to_string(),
+                variant: variant.to_string(),
+                index,
+                value: VariantValue::Tuple(self.values),
+            },
+        }
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = DynamicValue;
+    type Error = KobbleError;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = DynamicValue;
+    type Error = KobbleError;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = DynamicValue;
+    type Error = KobbleError;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer {
+    type Ok = DynamicValue;
+    type Error = KobbleError;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(self.finish())
+    }
+}
+
+struct MapSerializer {
+    entries: Vec<(DynamicValue, DynamicValue)>,
+    /// Key waiting for its value
+    key: Option<DynamicValue>,
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = DynamicValue;
+    type Error = KobbleError;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.key = Some(element(key, PathSegment::Key(self.entries.len()))?);
+        Ok(())
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let index = self.entries.len();
+        let key = self.key.take().ok_or_else(|| {
+            KobbleError::custom("serialize_value called before serialize_key")
+                .prefixed(PathSegment::Index(index))
+        })?;
+
+        let value = element(value, PathSegment::Index(index))?;
+        self.entries.push((key, value));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(DynamicValue::Map(self.entries))
+    }
+}
+
+/// What a collection of named fields will become
+enum StructKind {
+    Struct(&'static str),
+    /// (enum name, variant index, variant name)
+    Variant(&'static str, u32, &'static str),
+}
+
+struct StructSerializer {
+    kind: StructKind,
+    fields: Vec<(String, DynamicValue)>,
+}
+
+impl StructSerializer {
+    fn new(kind: StructKind, len: usize) -> Self {
+        Self {
+            kind,
+            fields: Vec::with_capacity(len),
+        }
+    }
+
+    fn push<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), KobbleError> {
+        let value = element(value, PathSegment::Field(key.to_string()))?;
+        self.fields.push((key.to_string(), value));
+        Ok(())
+    }
+
+    fn finish(self) -> DynamicValue {
+        match self.kind {
+            StructKind::Struct(name) => DynamicValue::Struct {
+                name: name.to_string(),
+                fields: self.fields,
+            },
+            StructKind::Variant(name, index, variant) => DynamicValue::Enum {
+                name: name.to_string(),
+                variant: variant.to_string(),
+                index,
+                value: VariantValue::Struct(self.fields),
+            },
+        }
+    }
+}
+
+impl ser::SerializeStruct for StructSerializer {
+    type Ok = DynamicValue;
+    type Error = KobbleError;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeStructVariant for StructSerializer {
+    type Ok = DynamicValue;
+    type Error = KobbleError;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(self.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::to_dynamic;
+    use crate::{DynamicValue, Schema, SchemaSeed, VariantValue};
+    use bincode::Options;
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize, Deserialize)]
+    struct Component {
+        name: String,
+        transform: glam::Mat4,
+        tags: BTreeMap<u16, Option<Tag>>,
+        shape: Shape,
+        unit: Marker,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    enum Tag {
+        Hidden,
+        Layer(u8),
+    }
+
+    #[derive(Serialize, Deserialize)]
+    enum Shape {
+        Circle { radius: f32 },
+        Polygon(Vec<(f32, f32)>, bool),
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Marker;
+
+    /// Converting directly must give the same result as going through bytes
+    fn same_as_bytes<'de, T: Serialize + Deserialize<'de>>(value: T) {
+        let schema = Schema::infer::<T>();
+        let bytes = bincode::serialize(&value).unwrap();
+        let decoded = bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .deserialize_seed(SchemaSeed::new(&schema), &bytes)
+            .unwrap();
+
+        let dynamic = to_dynamic(&value).unwrap();
+        assert_eq!(dynamic, decoded);
+        assert_eq!(bincode::serialize(&dynamic).unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_same_as_bytes() {
+        same_as_bytes(Component {
+            name: "Spoon".into(),
+            transform: glam::Mat4::IDENTITY,
+            tags: [(1, None), (5, Some(Tag::Layer(3))), (8, Some(Tag::Hidden))]
+                .into_iter()
+                .collect(),
+            shape: Shape::Polygon(vec![(0., 1.), (2., 3.)], true),
+            unit: Marker,
+        });
+
+        same_as_bytes(Component {
+            name: "Fork".into(),
+            transform: glam::Mat4::ZERO,
+            tags: BTreeMap::new(),
+            shape: Shape::Circle { radius: 4. },
+            unit: Marker,
+        });
+    }
+
+    #[test]
+    fn test_enum_variant() {
+        assert_eq!(
+            to_dynamic(&Shape::Circle { radius: 1.5 }).unwrap(),
+            DynamicValue::Enum {
+                name: "Shape".into(),
+                variant: "Circle".into(),
+                index: 0,
+                value: VariantValue::Struct(vec![("radius".into(), DynamicValue::F32(1.5))]),
+            }
+        );
+    }
+}
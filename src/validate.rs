@@ -0,0 +1,489 @@
+use crate::deserialize::RECURSION_LIMIT;
+use crate::error::KobbleError;
+use crate::path::{PathSegment, ValuePath};
+use crate::scope::Scopes;
+use crate::{DynamicValue, EnumSchema, Schema, StructSchema, VariantSchema, VariantValue};
+
+/// A single way in which a DynamicValue fails to conform to a Schema
+pub type ValidationError = KobbleError;
+
+impl Schema {
+    /// Check that `value` conforms to this schema, reporting every mismatch found. References to
+    /// recursive types may nest `RECURSION_LIMIT` deep, as deep as deserializing allows.
+    pub fn validate(&self, value: &DynamicValue) -> Result<(), Vec<ValidationError>> {
+        self.validate_with_recursion_limit(value, Some(RECURSION_LIMIT))
+    }
+
+    /// As `validate`, allowing references to nest `limit` deep, or however deep they go with
+    /// `None`
+    pub fn validate_with_recursion_limit(
+        &self,
+        value: &DynamicValue,
+        limit: Option<usize>,
+    ) -> Result<(), Vec<ValidationError>> {
+        let mut validator = Validator {
+            scopes: Scopes::default(),
+            path: ValuePath::root(),
+            errors: vec![],
+            refs: 0,
+            depth: 0,
+            recursion_limit: limit,
+        };
+
+        validator.value(self, value);
+
+        if validator.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(validator.errors)
+        }
+    }
+}
+
+struct Validator<'a> {
+    scopes: Scopes<'a>,
+    path: ValuePath,
+    errors: Vec<ValidationError>,
+    /// References followed without descending into the value, which bounds cycles such as
+    /// `type A = B; type B = A`
+    refs: usize,
+    /// References followed to reach the current value
+    depth: usize,
+    recursion_limit: Option<usize>,
+}
+
+impl<'a> Validator<'a> {
+    fn error(&mut self, error: KobbleError) {
+        self.errors.push(error.at(self.path.clone()));
+    }
+
+    fn mismatch(&mut self, expected: impl Into<String>, found: impl Into<String>) {
+        self.error(KobbleError::SchemaMismatch {
+            path: ValuePath::root(),
+            expected: expected.into(),
+            found: found.into(),
+        });
+    }
+
+    /// Validate a value nested one step further in
+    fn nested(&mut self, segment: PathSegment, schema: &'a Schema, value: &DynamicValue) {
+        self.path.push(segment);
+        self.inner(schema, value);
+        self.path.0.pop();
+    }
+
+    /// Validate a value contained in the current one, at the same path
+    fn inner(&mut self, schema: &'a Schema, value: &DynamicValue) {
+        let refs = std::mem::take(&mut self.refs);
+        self.value(schema, value);
+        self.refs = refs;
+    }
+
+    fn value(&mut self, schema: &'a Schema, value: &DynamicValue) {
+        match (schema, value) {
+            (Schema::Recursive { definitions, root }, _) => {
+                self.scopes.push(definitions);
+                self.value(root, value);
+                self.scopes.pop();
+            }
+            (Schema::Ref(name), _) => {
+                let too_deep = self
+                    .recursion_limit
+                    .is_some_and(|limit| self.depth >= limit);
+                if self.refs == RECURSION_LIMIT || too_deep {
+                    self.error(KobbleError::RecursionLimit {
+                        path: ValuePath::root(),
+                        name: name.clone(),
+                    });
+                    return;
+                }
+
+                match self.scopes.lookup(name) {
+                    Ok(schema) => {
+                        self.refs += 1;
+                        self.depth += 1;
+                        self.value(schema, value);
+                        self.depth -= 1;
+                        self.refs -= 1;
+                    }
+                    Err(error) => self.error(error),
+                }
+            }
+            (Schema::I8, DynamicValue::I8(_))
+            | (Schema::U8, DynamicValue::U8(_))
+            | (Schema::I16, DynamicValue::I16(_))
+            | (Schema::U16, DynamicValue::U16(_))
+            | (Schema::I32, DynamicValue::I32(_))
+            | (Schema::U32, DynamicValue::U32(_))
+            | (Schema::I64, DynamicValue::I64(_))
+            | (Schema::U64, DynamicValue::U64(_))
+            | (Schema::I128, DynamicValue::I128(_))
+            | (Schema::U128, DynamicValue::U128(_))
+            | (Schema::F32, DynamicValue::F32(_))
+            | (Schema::F64, DynamicValue::F64(_))
+            | (Schema::Bool, DynamicValue::Bool(_))
+            | (Schema::Char, DynamicValue::Char(_))
+            | (Schema::Unit, DynamicValue::Unit)
+            | (Schema::Bytes, DynamicValue::Bytes(_))
+            | (Schema::String, DynamicValue::String(_))
+            | (Schema::Option(_), DynamicValue::Option(None)) => (),
+            (Schema::Option(inner), DynamicValue::Option(Some(value))) => self.inner(inner, value),
+            (Schema::Struct(schema), DynamicValue::Struct { name, fields }) => {
+                self.name(&schema.name, name);
+                self.fields(schema, fields);
+            }
+            (Schema::Tuple(schemas), DynamicValue::Tuple(values)) => self.elements(schemas, values),
+            (Schema::TupleStruct(expected, schemas), DynamicValue::TupleStruct(name, values)) => {
+                self.name(expected, name);
+                self.elements(schemas, values);
+            }
+            (Schema::NewtypeStruct(expected, inner), DynamicValue::NewtypeStruct(name, value)) => {
+                self.name(expected, name);
+                self.nested(PathSegment::Index(0), inner, value);
+            }
+            (Schema::UnitStruct(expected), DynamicValue::UnitStruct(name)) => {
+                self.name(expected, name)
+            }
+            (Schema::UniformSequence(inner), DynamicValue::UniformSequence(values)) => {
+                for (i, value) in values.iter().enumerate() {
+                    self.nested(PathSegment::Index(i), inner, value);
+                }
+            }
+            (Schema::Map { key, value: inner }, DynamicValue::Map(entries)) => {
+                for (i, (k, v)) in entries.iter().enumerate() {
+                    self.nested(PathSegment::Key(i), key, k);
+                    self.nested(PathSegment::Index(i), inner, v);
+                }
+            }
+            (Schema::Enum(schema), DynamicValue::Enum { .. }) => self.variant(schema, value),
+            (schema, value) => self.mismatch(schema_kind(schema), value_kind(value)),
+        }
+    }
+
+    fn name(&mut self, expected: &str, found: &str) {
+        if expected != found {
+            self.mismatch(format!("type {}", expected), format!("type {}", found));
+        }
+    }
+
+    fn elements(&mut self, schemas: &'a [Schema], values: &[DynamicValue]) {
+        if schemas.len() != values.len() {
+            self.mismatch(
+                format!("{} elements", schemas.len()),
+                format!("{} elements", values.len()),
+            );
+        }
+
+        for (i, (schema, value)) in schemas.iter().zip(values).enumerate() {
+            self.nested(PathSegment::Index(i), schema, value);
+        }
+    }
+
+    fn fields(&mut self, schema: &'a StructSchema, fields: &[(String, DynamicValue)]) {
+        if schema.fields.len() != fields.len() {
+            self.mismatch(
+                format!("{} fields", schema.fields.len()),
+                format!("{} fields", fields.len()),
+            );
+        }
+
+        for ((expected, schema), (name, value)) in schema.fields.iter().zip(fields) {
+            if expected != name {
+                self.mismatch(format!("field {}", expected), format!("field {}", name));
+            } else {
+                self.nested(PathSegment::Field(name.clone()), schema, value);
+            }
+        }
+    }
+
+    fn variant(&mut self, schema: &'a EnumSchema, value: &DynamicValue) {
+        let DynamicValue::Enum {
+            name,
+            variant,
+            index,
+            value,
+        } = value
+        else {
+            return;
+        };
+
+        self.name(&schema.name, name);
+
+        let Some((expected, variant_schema)) = schema.variants.get(*index as usize) else {
+            self.error(KobbleError::InvalidEnumIndex {
+                path: ValuePath::root(),
+                name: schema.name.clone(),
+                index: *index,
+            });
+            return;
+        };
+
+        if expected != variant {
+            self.mismatch(
+                format!("variant {}", expected),
+                format!("variant {}", variant),
+            );
+            return;
+        }
+
+        match (variant_schema, value) {
+            (VariantSchema::Unit, VariantValue::Unit) => (),
+            (VariantSchema::Newtype(inner), VariantValue::Newtype(value)) => {
+                self.nested(PathSegment::Index(0), inner, value)
+            }
+            (VariantSchema::Tuple(schemas), VariantValue::Tuple(values)) => {
+                self.elements(schemas, values)
+            }
+            (VariantSchema::Struct(schema), VariantValue::Struct(fields)) => {
+                self.fields(schema, fields)
+            }
+            (schema, value) => self.mismatch(variant_kind(schema), value.kind()),
+        }
+    }
+}
+
+pub(crate) fn schema_kind(schema: &Schema) -> String {
+    match schema {
+        Schema::I8 => "i8".into(),
+        Schema::U8 => "u8".into(),
+        Schema::I16 => "i16".into(),
+        Schema::U16 => "u16".into(),
+        Schema::I32 => "i32".into(),
+        Schema::U32 => "u32".into(),
+        Schema::I64 => "i64".into(),
+        Schema::U64 => "u64".into(),
+        Schema::I128 => "i128".into(),
+        Schema::U128 => "u128".into(),
+        Schema::F32 => "f32".into(),
+        Schema::F64 => "f64".into(),
+        Schema::Bool => "bool".into(),
+        Schema::Char => "char".into(),
+        Schema::Unit => "unit".into(),
+        Schema::Bytes => "bytes".into(),
+        Schema::String => "string".into(),
+        Schema::Option(_) => "option".into(),
+        Schema::Struct(s) => format!("struct {}", s.name),
+        Schema::Tuple(_) => "tuple".into(),
+        Schema::TupleStruct(name, _) => format!("tuple struct {}", name),
+        Schema::NewtypeStruct(name, _) => format!("newtype struct {}", name),
+        Schema::UnitStruct(name) => format!("unit struct {}", name),
+        Schema::UniformSequence(_) => "sequence".into(),
+        Schema::Map { .. } => "map".into(),
+        Schema::Enum(e) => format!("enum {}", e.name),
+        Schema::Ref(name) => name.clone(),
+        Schema::Recursive { root, .. } => schema_kind(root),
+    }
+}
+
+pub(crate) fn value_kind(value: &DynamicValue) -> String {
+    match value {
+        DynamicValue::I8(_) => "i8".into(),
+        DynamicValue::U8(_) => "u8".into(),
+        DynamicValue::I16(_) => "i16".into(),
+        DynamicValue::U16(_) => "u16".into(),
+        DynamicValue::I32(_) => "i32".into(),
+        DynamicValue::U32(_) => "u32".into(),
+        DynamicValue::I64(_) => "i64".into(),
+        DynamicValue::U64(_) => "u64".into(),
+        DynamicValue::I128(_) => "i128".into(),
+        DynamicValue::U128(_) => "u128".into(),
+        DynamicValue::F32(_) => "f32".into(),
+        DynamicValue::F64(_) => "f64".into(),
+        DynamicValue::Bool(_) => "bool".into(),
+        DynamicValue::Char(_) => "char".into(),
+        DynamicValue::Unit => "unit".into(),
+        DynamicValue::Bytes(_) => "bytes".into(),
+        DynamicValue::String(_) => "string".into(),
+        DynamicValue::Option(_) => "option".into(),
+        DynamicValue::Struct { name, .. } => format!("struct {}", name),
+        DynamicValue::Tuple(_) => "tuple".into(),
+        DynamicValue::TupleStruct(name, _) => format!("tuple struct {}", name),
+        DynamicValue::NewtypeStruct(name, _) => format!("newtype struct {}", name),
+        DynamicValue::UnitStruct(name) => format!("unit struct {}", name),
+        DynamicValue::UniformSequence(_) => "sequence".into(),
+        DynamicValue::Map(_) => "map".into(),
+        DynamicValue::Enum { name, .. } => format!("enum {}", name),
+    }
+}
+
+pub(crate) fn variant_kind(schema: &VariantSchema) -> &'static str {
+    match schema {
+        VariantSchema::Struct(_) => "struct variant",
+        VariantSchema::Tuple(_) => "tuple variant",
+        VariantSchema::Newtype(_) => "newtype variant",
+        VariantSchema::Unit => "unit variant",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{to_dynamic, DynamicValue, KobbleError, Schema, ValuePath, VariantValue};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    struct Scene {
+        name: String,
+        nodes: Vec<Node>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Node {
+        position: (f32, f32),
+        light: Option<Light>,
+        children: Vec<Node>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    enum Light {
+        Point { radius: f32 },
+        Spot(f32, f32),
+        Ambient,
+    }
+
+    fn scene() -> Scene {
+        Scene {
+            name: "Stage".into(),
+            nodes: vec![
+                Node {
+                    position: (1., 2.),
+                    light: Some(Light::Spot(0.5, 1.)),
+                    children: vec![Node {
+                        position: (3., 4.),
+                        light: Some(Light::Point { radius: 4. }),
+                        children: vec![],
+                    }],
+                },
+                Node {
+                    position: (0., 0.),
+                    light: None,
+                    children: vec![],
+                },
+            ],
+        }
+    }
+
+    /// Get a mutable reference to the node at `nodes[i]`
+    fn node(scene: &mut DynamicValue, i: usize) -> &mut Vec<(String, DynamicValue)> {
+        let DynamicValue::Struct { fields, .. } = scene else {
+            panic!()
+        };
+        let DynamicValue::UniformSequence(nodes) = &mut fields[1].1 else {
+            panic!()
+        };
+        let DynamicValue::Struct { fields, .. } = &mut nodes[i] else {
+            panic!()
+        };
+        fields
+    }
+
+    #[test]
+    fn test_valid() {
+        let schema = Schema::infer::<Scene>();
+        assert_eq!(schema.validate(&to_dynamic(&scene()).unwrap()), Ok(()));
+    }
+
+    #[test]
+    fn test_every_mismatch_reported() {
+        let schema = Schema::infer::<Scene>();
+        let mut value = to_dynamic(&scene()).unwrap();
+
+        // Wrong element type in a tuple
+        node(&mut value, 0)[0].1 =
+            DynamicValue::Tuple(vec![DynamicValue::F32(1.), DynamicValue::F64(2.)]);
+
+        // Out of range enum index
+        let DynamicValue::Option(Some(light)) = &mut node(&mut value, 0)[1].1 else {
+            panic!()
+        };
+        let DynamicValue::Enum { index, .. } = &mut **light else {
+            panic!()
+        };
+        *index = 7;
+
+        // Fields out of order
+        node(&mut value, 1).swap(0, 1);
+
+        let errors = schema.validate(&value).unwrap_err();
+        let paths: Vec<String> = errors.iter().map(|e| e.path().to_string()).collect();
+        assert_eq!(
+            paths,
+            [
+                "nodes[0].position[1]",
+                "nodes[0].light",
+                "nodes[1]",
+                "nodes[1]"
+            ]
+        );
+        assert!(matches!(
+            errors[1],
+            KobbleError::InvalidEnumIndex { index: 7, .. }
+        ));
+    }
+
+    #[test]
+    fn test_variant_kind() {
+        let schema = Schema::infer::<Light>();
+        let value = DynamicValue::Enum {
+            name: "Light".into(),
+            variant: "Ambient".into(),
+            index: 2,
+            value: VariantValue::Tuple(vec![]),
+        };
+
+        assert_eq!(
+            schema.validate(&value),
+            Err(vec![KobbleError::SchemaMismatch {
+                path: ValuePath::root(),
+                expected: "unit variant".into(),
+                found: "tuple variant".into(),
+            }])
+        );
+    }
+
+    #[test]
+    fn test_recursive_child() {
+        let schema = Schema::infer::<Scene>();
+        let mut value = to_dynamic(&scene()).unwrap();
+
+        let DynamicValue::UniformSequence(children) = &mut node(&mut value, 0)[2].1 else {
+            panic!()
+        };
+        children[0] = DynamicValue::Unit;
+
+        let errors = schema.validate(&value).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path().to_string(), "nodes[0].children[0]");
+    }
+
+    #[test]
+    fn test_map_entry() {
+        let schema = Schema::Map {
+            key: Box::new(Schema::String),
+            value: Box::new(Schema::U8),
+        };
+        let value = DynamicValue::Map(vec![(
+            DynamicValue::U8(1),
+            DynamicValue::String("a".into()),
+        )]);
+
+        let errors = schema.validate(&value).unwrap_err();
+        let paths: Vec<String> = errors.iter().map(|e| e.path().to_string()).collect();
+        assert_eq!(paths, ["[key 0]", "[0]"]);
+    }
+
+    #[test]
+    fn test_reference_cycle() {
+        let schema = Schema::Recursive {
+            definitions: [
+                ("A".to_string(), Schema::Ref("B".into())),
+                ("B".to_string(), Schema::Ref("A".into())),
+            ]
+            .into_iter()
+            .collect(),
+            root: Box::new(Schema::Ref("A".into())),
+        };
+
+        let errors = schema.validate(&DynamicValue::Unit).unwrap_err();
+        assert!(matches!(errors[..], [KobbleError::RecursionLimit { .. }]));
+    }
+}